@@ -0,0 +1,81 @@
+#![allow(dead_code)] // Only the engine uses this so far
+
+use crate::flow::Step;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// File in the cache directory that holds the cached outputs
+pub const CACHE_FILE: &str = "step-cache.json";
+
+/// Step outputs memoized on disk across runs (`RunOptions::cache_dir`):
+/// a JSON map from cache key (see `cache_key`) to output
+#[derive(Debug, Default)]
+pub struct StepCache {
+    path: PathBuf,
+    entries: BTreeMap<String, String>,
+}
+
+impl StepCache {
+    /// Reads the cache in `dir`; a missing file is an empty cache, and so is
+    /// an unreadable one (with a warning) — it's only an optimization
+    pub fn open(dir: &Path) -> Self {
+        let path = dir.join(CACHE_FILE);
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).unwrap_or_else(|err| {
+                warn!("⚠️ Ignoring unreadable step cache {:?}: {err}", path);
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+        StepCache { path, entries }
+    }
+
+    /// The cached output for `key`, if any
+    pub fn get(&self, key: &str) -> Option<&String> {
+        self.entries.get(key)
+    }
+
+    /// Remembers `output` for `key` (written out by `save`)
+    pub fn insert(&mut self, key: String, output: String) {
+        self.entries.insert(key, output);
+    }
+
+    /// Forgets the output cached for `key` (e.g. after its step was undone)
+    pub fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    /// Writes the cache back to its file, creating the directory if needed
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(&self.entries)?;
+        std::fs::write(&self.path, json)
+    }
+}
+
+/// What a step's output is cached under: its `idempotency_key` if it has
+/// one, otherwise a hash of its kind and (rendered) config, plus the items
+/// of a `for_each` step. `None` for steps that don't opt in to caching
+/// (neither `cache: true` nor an `idempotency_key`).
+pub fn cache_key(step: &Step, items: Option<&[serde_yaml::Value]>) -> Option<String> {
+    if let Some(key) = &step.idempotency_key {
+        return Some(key.clone());
+    }
+    if !step.cache {
+        return None;
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(step.kind.as_bytes());
+    hasher.update([0u8]); // field separator, as in `RunHistory::compute_digest`
+    hasher.update(serde_yaml::to_string(&step.config).unwrap_or_default().as_bytes());
+    if let Some(items) = items {
+        hasher.update([0u8]);
+        hasher.update(serde_yaml::to_string(items).unwrap_or_default().as_bytes());
+    }
+    Some(format!("sha256:{:x}", hasher.finalize()))
+}
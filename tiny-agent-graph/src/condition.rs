@@ -0,0 +1,65 @@
+#![allow(dead_code)] // Only the engine uses this so far
+
+use std::collections::HashMap;
+
+/// One side of a `when` comparison
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    /// A quoted string, e.g. `"ready"` or `'ready'`
+    Literal(String),
+    /// `steps.<id>.output` — the output of an earlier step
+    StepOutput(String),
+}
+
+/// Evaluates a step's `when` expression against the outputs produced so far
+///
+/// Grammar (deliberately tiny):
+/// - `<operand> == <operand>` or `<operand> != <operand>`
+/// - an operand is a quoted string literal or `steps.<id>.output`
+///
+/// A step without an output (failed, skipped, not run yet) never equals
+/// anything, so `==` is false and `!=` is true for it.
+pub fn evaluate(expr: &str, outputs: &HashMap<String, String>) -> Result<bool, String> {
+    let (left, negated, right) = if let Some((left, right)) = expr.split_once("==") {
+        (left, false, right)
+    } else if let Some((left, right)) = expr.split_once("!=") {
+        (left, true, right)
+    } else {
+        return Err(format!("Expected `==` or `!=` in condition '{expr}'"));
+    };
+
+    let left = parse_operand(left)?;
+    let right = parse_operand(right)?;
+    let equal = match (resolve(&left, outputs), resolve(&right, outputs)) {
+        (Some(left), Some(right)) => left == right,
+        _ => false,
+    };
+
+    Ok(equal != negated)
+}
+
+/// Looks up an operand's value; `None` for a step without an output
+fn resolve<'a>(operand: &'a Operand, outputs: &'a HashMap<String, String>) -> Option<&'a str> {
+    match operand {
+        Operand::Literal(value) => Some(value),
+        Operand::StepOutput(step_id) => outputs.get(step_id).map(String::as_str),
+    }
+}
+
+/// Parses a single operand, trimming surrounding whitespace
+fn parse_operand(raw: &str) -> Result<Operand, String> {
+    let raw = raw.trim();
+
+    for quote in ['"', '\''] {
+        if raw.len() >= 2 && raw.starts_with(quote) && raw.ends_with(quote) {
+            return Ok(Operand::Literal(raw[1..raw.len() - 1].to_string()));
+        }
+    }
+
+    match raw.strip_prefix("steps.").and_then(|rest| rest.strip_suffix(".output")) {
+        Some(step_id) if !step_id.is_empty() => Ok(Operand::StepOutput(step_id.to_string())),
+        _ => Err(format!(
+            "Unsupported operand '{raw}' (expected a quoted string or steps.<id>.output)"
+        )),
+    }
+}
@@ -1,41 +1,668 @@
 #![allow(dead_code)] // We build incrementally — not every field is wired up yet
 
-use crate::flow::{Flow, StepGraph};
+use crate::condition;
+use crate::flow::{
+    descendants, resolve_inputs, step_depths, Compensation, DependOn, Dependency, Flow, RetryPolicy, Step, StepGraph,
+    StepSelection,
+};
+use crate::handlers::{Change, HandlerError, HandlerOutput, HandlerRegistry, StepContext};
+use crate::notify::notify_run;
+use crate::cache::{cache_key, StepCache};
+use crate::template::{self, EnvAccess, TemplateContext};
 use petgraph::algo::toposort;
 use rand::{thread_rng, Rng};
-use tracing::{info, warn};
-use std::collections::HashMap;
+use tracing::{info, info_span, warn, Instrument};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::time::Duration;
+use futures::stream::FuturesUnordered;
+use futures::{FutureExt, Stream, StreamExt};
+use governor::{DefaultDirectRateLimiter, Quota, RateLimiter};
+use petgraph::graph::NodeIndex;
+use tokio::sync::mpsc::{self, unbounded_channel};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard, Semaphore};
+use tokio_util::sync::CancellationToken;
 use tokio::time::sleep;
 
+/// Failure reason for steps cut off by `RunOptions::flow_timeout_seconds`
+/// (or the flow's `timeout_seconds`)
+pub const FLOW_TIMEOUT: &str = "flow timeout";
+
+/// Failure reason for runs stopped via `RunOptions::cancel` or Ctrl-C
+pub const CANCELLED: &str = "cancelled";
+
+/// Latency range (milliseconds) of simulated steps unless
+/// `RunOptions::sim_latency_ms` says otherwise
+pub const DEFAULT_SIM_LATENCY_MS: (u64, u64) = (100, 300);
+
+/// Output cap (`RunOptions::max_output_bytes`) the CLI and server use unless
+/// told otherwise
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Skip reason for steps with `enabled: false` — unlike other skips, it
+/// counts as satisfied for the step's dependents
+pub const DISABLED: &str = "disabled";
+
+/// `RunHistory::note` for runs of a flow without steps
+pub const EMPTY_FLOW_NOTE: &str = "Flow has no steps; nothing ran";
+
 /// Summary of a completed DAG run (used for reporting or persistence)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RunHistory {
     pub run_id: String,
     pub flow_id: String,
     pub status: RunStatus,
     pub step_results: HashMap<String, StepResult>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    /// SHA-256 over the step outcomes (see `compute_digest`)
+    pub digest: String,
+    /// Compensations run after a failure (`Flow::rollback_on_failure`),
+    /// in the order they ran
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rollback: Vec<Compensated>,
+    /// Something worth knowing about the run as a whole, e.g. that the flow
+    /// had no steps to run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Nothing was executed (`RunOptions::dry_run`): the step results are
+    /// predictions, so the run can't be resumed from
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+impl RunHistory {
+    /// Deterministic hash over step IDs (sorted), statuses, and output hashes
+    ///
+    /// Run IDs and timestamps are deliberately left out, so two runs with the
+    /// same outcomes share a digest and can be compared at a glance.
+    pub fn compute_digest(&self) -> String {
+        let mut step_ids: Vec<&String> = self.step_results.keys().collect();
+        step_ids.sort();
+
+        let mut hasher = Sha256::new();
+        for step_id in step_ids {
+            let result = &self.step_results[step_id];
+            let output_hash = result
+                .output
+                .as_ref()
+                .map(|output| format!("{:x}", Sha256::digest(output.as_bytes())))
+                .unwrap_or_default();
+
+            let fields = [
+                step_id.as_str(),
+                result.status.state(),
+                result.status.reason().unwrap_or_default(),
+                output_hash.as_str(),
+            ];
+            for field in fields {
+                hasher.update(field.as_bytes());
+                hasher.update([0u8]); // field separator, so "ab"+"c" != "a"+"bc"
+            }
+        }
+
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Step results in a stable, topological order: by `level` (so every
+    /// step comes after its dependencies), then by step ID
+    pub fn ordered_step_results(&self) -> Vec<(&String, &StepResult)> {
+        let mut ordered: Vec<(&String, &StepResult)> = self.step_results.iter().collect();
+        ordered.sort_by(|(a_id, a), (b_id, b)| (a.level, a_id).cmp(&(b.level, b_id)));
+        ordered
+    }
+
+    /// True if the stored digest still matches the step outcomes
+    pub fn verify_digest(&self) -> bool {
+        self.digest == self.compute_digest()
+    }
+}
+
+/// One step undone during a rollback, and how its compensation went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Compensated {
+    pub step_id: String,
+    /// The compensation's handler kind
+    pub kind: String,
+    /// `Success`, or `Failed` with the compensation's error
+    pub status: StepStatus,
 }
 
 /// Final result of the DAG execution
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", content = "reason", rename_all = "snake_case")]
 pub enum RunStatus {
     Success,
     Failed(String), // includes a reason (e.g. “step X failed” or “blocked”)
 }
 
 /// Outcome for a single step (used for audit or export)
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct StepResult {
     pub status: StepStatus,
     pub output: Option<String>,
+    /// Side-channel info from the handler (e.g. command stderr), kept out
+    /// of `output` so later steps only see the actual result
+    #[serde(default)]
+    pub diagnostics: Option<String>,
+    /// One-line rationale for the outcome, e.g. "ran (all deps ok)" or
+    /// "skipped (dep 'fetch' failed)" — what `run-flow --explain` prints
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Scheduling wave: 0 for roots, otherwise one more than the deepest
+    /// dependency (the longest path from a root)
+    #[serde(default)]
+    pub level: usize,
+    /// How many times the handler was called: 1 for a first-try success,
+    /// up to the retry policy's `max_attempts` (summed over a `for_each`
+    /// step's items); 0 for steps that didn't run
+    #[serde(default)]
+    pub attempts: usize,
+    /// What a dry run (`RunOptions::dry_run`) says the step would do
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<Change>,
+    /// The output came from the step cache (`RunOptions::cache_dir`) rather
+    /// than from running the step
+    #[serde(default)]
+    pub from_cache: bool,
+    /// Full size in bytes of an `output` that was cut short (see
+    /// `RunOptions::max_output_bytes`); `None` when the output is complete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_bytes: Option<usize>,
+}
+
+impl StepResult {
+    /// A successful step with its output
+    pub fn success(output: String) -> Self {
+        StepResult {
+            status: StepStatus::Success,
+            output: Some(output),
+            diagnostics: None,
+            reason: None,
+            level: 0,
+            attempts: 0,
+            change: None,
+            from_cache: false,
+            output_bytes: None,
+        }
+    }
+
+    /// A failed step (no output)
+    pub fn failed(kind: FailureKind, reason: impl Into<String>) -> Self {
+        StepResult {
+            status: StepStatus::Failed(kind, reason.into()),
+            output: None,
+            diagnostics: None,
+            reason: None,
+            level: 0,
+            attempts: 0,
+            change: None,
+            from_cache: false,
+            output_bytes: None,
+        }
+    }
+
+    /// A step that was not run (no output)
+    pub fn skipped(reason: impl Into<String>) -> Self {
+        StepResult {
+            status: StepStatus::Skipped(reason.into()),
+            output: None,
+            diagnostics: None,
+            reason: None,
+            level: 0,
+            attempts: 0,
+            change: None,
+            from_cache: false,
+            output_bytes: None,
+        }
+    }
+
+    /// A step stopped (or never started) because the run was cancelled
+    pub fn cancelled() -> Self {
+        StepResult {
+            status: StepStatus::Cancelled,
+            output: None,
+            diagnostics: None,
+            reason: None,
+            level: 0,
+            attempts: 0,
+            change: None,
+            from_cache: false,
+            output_bytes: None,
+        }
+    }
+
+    /// Attaches the rationale shown by `--explain`
+    pub fn because(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Cuts `output` down to at most `max_bytes` (on a character boundary)
+    /// plus a "...(truncated, N bytes total)" marker, remembering the full
+    /// size in `output_bytes`. Shorter outputs are left alone.
+    pub fn truncate_output(&mut self, max_bytes: usize) {
+        let Some(output) = &mut self.output else {
+            return;
+        };
+        let total = output.len();
+        if total <= max_bytes {
+            return;
+        }
+
+        let mut cut = max_bytes;
+        while !output.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        output.truncate(cut);
+        output.push_str(&format!("...(truncated, {total} bytes total)"));
+        self.output_bytes = Some(total);
+    }
 }
 
 /// Execution status of an individual step
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StepStatusSpec", into = "StepStatusSpec")]
 pub enum StepStatus {
     Success,
-    Failed(String), // failure reason (e.g. timeout, bad input, dependency block)
+    Failed(FailureKind, String), // what kind of failure, and the reason
+    Skipped(String),             // why the step was not run (e.g. skipped by user, run aborted)
+    Cancelled,                   // in flight or not started yet when the run was cancelled
+}
+
+impl StepStatus {
+    /// Short machine-friendly name of the status ("success", "failed", ...)
+    pub fn state(&self) -> &'static str {
+        match self {
+            StepStatus::Success => "success",
+            StepStatus::Failed(..) => "failed",
+            StepStatus::Skipped(_) => "skipped",
+            StepStatus::Cancelled => "cancelled",
+        }
+    }
+
+    /// The failure/skip reason, if the status carries one
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            StepStatus::Success | StepStatus::Cancelled => None,
+            StepStatus::Failed(_, reason) | StepStatus::Skipped(reason) => Some(reason),
+        }
+    }
+
+    /// What kind of failure this is, if the step failed (or was cancelled)
+    pub fn failure_kind(&self) -> Option<FailureKind> {
+        match self {
+            StepStatus::Failed(kind, _) => Some(*kind),
+            StepStatus::Cancelled => Some(FailureKind::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+impl Dependency {
+    /// Whether a dependency that ended with `status` lets the dependent run
+    /// (`Dependency` lives in `flow`, but what a status means is the engine's)
+    pub fn is_satisfied_by(&self, status: &StepStatus) -> bool {
+        match self.on {
+            DependOn::Success => match status {
+                StepStatus::Success => true,
+                StepStatus::Skipped(reason) => reason == DISABLED,
+                _ => false,
+            },
+            DependOn::Completion => true,
+        }
+    }
+}
+
+/// Why a step failed, for reacting to failures without parsing reasons
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailureKind {
+    /// The step ran out of its own time (`timeout_seconds`)
+    Timeout,
+    /// The handler returned an error (or its compensation did)
+    #[default]
+    HandlerError,
+    /// A dependency the step needs is missing from the flow (steps blocked
+    /// by a failed dependency are skipped, not failed)
+    DependencyBlocked,
+    /// The step was stopped from outside rather than failing on its own:
+    /// the flow's deadline passed, or the run was cancelled (Ctrl-C)
+    Cancelled,
+    /// The step's own definition is invalid (e.g. a bad `when` or `for_each`)
+    ConfigError,
+}
+
+impl fmt::Display for FailureKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureKind::Timeout => "timeout",
+            FailureKind::HandlerError => "handler_error",
+            FailureKind::DependencyBlocked => "dependency_blocked",
+            FailureKind::Cancelled => "cancelled",
+            FailureKind::ConfigError => "config_error",
+        })
+    }
+}
+
+/// How a `StepStatus` is written out: `{ state, reason, kind }`, where
+/// `kind` is only there for failures (and defaults for older histories)
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum StepStatusSpec {
+    Success,
+    Failed {
+        reason: String,
+        #[serde(default)]
+        kind: FailureKind,
+    },
+    Skipped {
+        reason: String,
+    },
+    Cancelled,
+}
+
+impl From<StepStatusSpec> for StepStatus {
+    fn from(spec: StepStatusSpec) -> Self {
+        match spec {
+            StepStatusSpec::Success => StepStatus::Success,
+            StepStatusSpec::Failed { reason, kind } => StepStatus::Failed(kind, reason),
+            StepStatusSpec::Skipped { reason } => StepStatus::Skipped(reason),
+            StepStatusSpec::Cancelled => StepStatus::Cancelled,
+        }
+    }
+}
+
+impl From<StepStatus> for StepStatusSpec {
+    fn from(status: StepStatus) -> Self {
+        match status {
+            StepStatus::Success => StepStatusSpec::Success,
+            StepStatus::Failed(kind, reason) => StepStatusSpec::Failed { reason, kind },
+            StepStatus::Skipped(reason) => StepStatusSpec::Skipped { reason },
+            StepStatus::Cancelled => StepStatusSpec::Cancelled,
+        }
+    }
+}
+
+/// Progress notifications from `run_flow_stream`, `RunOptions::on_event` and
+/// `RunOptions::event_sender`, in the order they happen
+///
+/// Serialized as `{ "event": "step_finished", "data": { ... } }`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", content = "data", rename_all = "snake_case")]
+pub enum RunEvent {
+    /// The run got past its concurrency group and is about to execute steps
+    RunStarted { run_id: String, flow_id: String },
+    /// A step's handler is about to be invoked with `config` (placeholders
+    /// already filled in)
+    StepStarted { step_id: String, config: serde_yaml::Value },
+    /// A step got its final result (including skipped and blocked steps)
+    StepFinished { step_id: String, result: StepResult },
+    /// Always the last event of a successful run — carries the full history
+    RunFinished(RunHistory),
+    /// The run couldn't start or complete (e.g. rejected, bad selection)
+    RunFailed(String),
+}
+
+/// What to do with a step that is about to run (see `RunOptions::step_gate`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepDecision {
+    Run,
+    Skip,
+    Abort,
+}
+
+/// Hook consulted right before each step executes (e.g. the interactive
+/// debugger). It sees the step as it would run — defaults merged and
+/// placeholders rendered — and is called on a blocking thread, so it may
+/// wait for input.
+pub type StepGate = Arc<dyn Fn(&Step) -> StepDecision + Send + Sync>;
+
+/// Progress callback invoked with every `RunEvent` as it happens (e.g. a TUI)
+pub type EventCallback = Arc<dyn Fn(RunEvent) + Send + Sync>;
+
+/// What a run does when its flow's `concurrency_group` is already busy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnConflict {
+    /// Wait for the other run to finish, then start
+    #[default]
+    Queue,
+    /// Fail immediately without running anything
+    Reject,
+}
+
+impl FromStr for OnConflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queue" => Ok(OnConflict::Queue),
+            "reject" => Ok(OnConflict::Reject),
+            other => Err(format!("unknown conflict policy '{other}' (expected queue or reject)")),
+        }
+    }
+}
+
+/// How the steps of one `stage` ended (see `stage_tallies`)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct StageTally {
+    /// The stage, or `None` for steps without one
+    pub stage: Option<String>,
+    pub success: usize,
+    pub failed: usize,
+    pub skipped: usize,
+    pub cancelled: usize,
+}
+
+/// Step outcomes counted per `stage`, stages in the order they first appear
+/// in the flow (steps without a stage last). Steps the run left out (e.g.
+/// not selected) aren't counted.
+pub fn stage_tallies(flow: &Flow, history: &RunHistory) -> Vec<StageTally> {
+    let mut tallies: Vec<StageTally> = Vec::new();
+    for step in &flow.nodes {
+        let Some(result) = history.step_results.get(&step.id) else {
+            continue;
+        };
+        let position = match tallies.iter().position(|tally| tally.stage == step.stage) {
+            Some(position) => position,
+            None => {
+                tallies.push(StageTally {
+                    stage: step.stage.clone(),
+                    ..Default::default()
+                });
+                tallies.len() - 1
+            }
+        };
+        let tally = &mut tallies[position];
+        match result.status {
+            StepStatus::Success => tally.success += 1,
+            StepStatus::Failed(..) => tally.failed += 1,
+            StepStatus::Skipped(_) => tally.skipped += 1,
+            StepStatus::Cancelled => tally.cancelled += 1,
+        }
+    }
+    tallies.sort_by_key(|tally| tally.stage.is_none());
+    tallies
+}
+
+/// Knobs for a single run — `Default` gives the plain, non-interactive behavior
+#[derive(Clone)]
+pub struct RunOptions {
+    /// Optional gate deciding whether each step runs, is skipped, or aborts the run
+    pub step_gate: Option<StepGate>,
+
+    /// Optional progress callback; called inline by the scheduler, so it
+    /// should return quickly
+    pub on_event: Option<EventCallback>,
+
+    /// Optional channel that gets every event too (after `on_event`), e.g.
+    /// to forward them to a web UI. Sent with `try_send`: if the channel is
+    /// full or its receiver is gone, the event is dropped and the run goes on.
+    pub event_sender: Option<mpsc::Sender<RunEvent>>,
+
+    /// Handlers for step kinds; unregistered kinds are simulated
+    pub registry: Arc<HandlerRegistry>,
+
+    /// Policy when another run holds this flow's concurrency group
+    pub on_conflict: OnConflict,
+
+    /// Subset of steps to run; pruned steps don't appear in the history
+    pub selection: StepSelection,
+
+    /// Timeout for steps without their own `timeout_seconds` (per-step wins)
+    pub default_step_timeout: Option<u64>,
+
+    /// Retry policy for steps without their own `retry` (per-step wins)
+    pub default_retry: Option<RetryPolicy>,
+
+    /// Latency range (min, max milliseconds) for simulated steps — kinds
+    /// without a handler; `None` means `DEFAULT_SIM_LATENCY_MS`, and
+    /// `Some((0, 0))` makes them finish instantly
+    pub sim_latency_ms: Option<(u64, u64)>,
+
+    /// Cap on retries across all steps of the run; once used up, failing
+    /// steps fail on their first attempt whatever their `retry` says
+    pub retry_budget: Option<usize>,
+
+    /// Upper bound for the whole run; whatever hasn't finished by then fails
+    /// and what succeeded is rolled back. Overrides `Flow::timeout_seconds`.
+    pub flow_timeout_seconds: Option<u64>,
+
+    /// Cancels the run when triggered: no new steps start, steps in flight
+    /// are dropped, and they plus everything left are marked `Cancelled`
+    pub cancel: Option<CancellationToken>,
+
+    /// Also cancel the run on Ctrl-C (SIGINT) — meant for the CLI, since the
+    /// listener replaces the default "kill the process" behavior
+    pub cancel_on_ctrl_c: bool,
+
+    /// Values for the flow's declared `inputs` (undeclared names are an error)
+    pub inputs: BTreeMap<String, serde_yaml::Value>,
+
+    /// How many steps may run at once (1 = serial, the default)
+    pub max_parallel: usize,
+
+    /// Per-kind caps on simultaneous steps, on top of `max_parallel`;
+    /// overrides the flow's `concurrency` block for the same kind
+    pub kind_limits: HashMap<String, usize>,
+
+    /// An earlier run of the same flow to pick up from: its successful steps
+    /// are carried over (output included) instead of executing again —
+    /// except those whose output was truncated (`StepResult::output_bytes`),
+    /// which run again so later steps see the whole output
+    pub resume_from: Option<RunHistory>,
+
+    /// With `resume_from`: rerun only the steps that failed in that run (and
+    /// those with a truncated output) and keep every other step's result as
+    /// it was. The run errors out if a failed step's dependencies didn't let
+    /// it run back then.
+    pub only_failed: bool,
+
+    /// Don't execute anything: ask each step's handler whether it would
+    /// change state (recorded as `StepResult::change`). Nothing is rolled back.
+    pub dry_run: bool,
+
+    /// ID for the run, used verbatim (e.g. for golden files or spotting a
+    /// rerun); `None` generates a fresh UUID
+    pub run_id: Option<String>,
+
+    /// Directory of the step cache shared across runs: a step that opts in
+    /// (`Step::cache` or an idempotency key) and whose key (see
+    /// `cache::cache_key`) is cached reuses that output instead of running.
+    /// Successful outputs are written back, and dropped again if the step is
+    /// rolled back. Ignored on dry runs.
+    pub cache_dir: Option<PathBuf>,
+
+    /// Longest step output kept in the run history, in bytes; longer ones
+    /// are truncated (see `StepResult::truncate_output`) once the run is
+    /// over, so later steps still saw them in full. `None` keeps everything.
+    pub max_output_bytes: Option<usize>,
+
+    /// Which environment variables `{{ env.<VAR> }}` placeholders may read
+    /// (all of them by default; the server narrows this for posted flows)
+    pub env_access: EnvAccess,
+}
+
+/// The part of a run's `RunOptions` that runs it starts itself (sub-flows)
+/// inherit; the rest (inputs, selection, resume, ...) belongs to one flow
+#[derive(Debug, Clone)]
+pub struct InheritedOptions {
+    pub registry: Arc<HandlerRegistry>,
+    pub cancel: Option<CancellationToken>,
+    pub dry_run: bool,
+    pub max_parallel: usize,
+    pub cache_dir: Option<PathBuf>,
+    pub sim_latency_ms: Option<(u64, u64)>,
+    pub env_access: EnvAccess,
+}
+
+impl InheritedOptions {
+    /// Options for a nested run: these settings, defaults for the rest
+    pub fn run_options(&self) -> RunOptions {
+        RunOptions {
+            registry: self.registry.clone(),
+            cancel: self.cancel.clone(),
+            dry_run: self.dry_run,
+            max_parallel: self.max_parallel,
+            cache_dir: self.cache_dir.clone(),
+            sim_latency_ms: self.sim_latency_ms,
+            env_access: self.env_access.clone(),
+            ..RunOptions::default()
+        }
+    }
+}
+
+impl From<&RunOptions> for InheritedOptions {
+    fn from(options: &RunOptions) -> Self {
+        InheritedOptions {
+            registry: options.registry.clone(),
+            cancel: options.cancel.clone(),
+            dry_run: options.dry_run,
+            max_parallel: options.max_parallel,
+            cache_dir: options.cache_dir.clone(),
+            sim_latency_ms: options.sim_latency_ms,
+            env_access: options.env_access.clone(),
+        }
+    }
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        RunOptions {
+            step_gate: None,
+            on_event: None,
+            event_sender: None,
+            registry: Arc::new(HandlerRegistry::with_builtins()),
+            on_conflict: OnConflict::default(),
+            selection: StepSelection::default(),
+            default_step_timeout: None,
+            default_retry: None,
+            sim_latency_ms: None,
+            retry_budget: None,
+            flow_timeout_seconds: None,
+            cancel: None,
+            cancel_on_ctrl_c: false,
+            inputs: BTreeMap::new(),
+            max_parallel: 1,
+            kind_limits: HashMap::new(),
+            resume_from: None,
+            only_failed: false,
+            dry_run: false,
+            run_id: None,
+            cache_dir: None,
+            max_output_bytes: None,
+            env_access: EnvAccess::default(),
+        }
+    }
 }
 
 /// Entrypoint: executes a single flow's DAG from top to bottom
@@ -52,8 +679,235 @@ pub enum StepStatus {
 /// - Dependencies are enforced: steps don't run unless all deps succeeded
 /// - Real step execution (with retries, idempotency, etc.) would hook in here
 pub async fn run_flow(flow: &Flow, graph: StepGraph) -> anyhow::Result<RunHistory> {
-    let run_id = uuid::Uuid::new_v4().to_string();
+    run_flow_with_options(flow, graph, &RunOptions::default()).await
+}
+
+/// Same as `run_flow`, but with explicit `RunOptions`
+///
+/// Skipped steps (by the gate or because a dependency was skipped) don't fail
+/// the run; their dependents are skipped too. Aborting skips everything left.
+/// The exception is a disabled step (`enabled: false`), which is skipped but
+/// lets its dependents run as if it had succeeded.
+pub async fn run_flow_with_options(
+    flow: &Flow,
+    graph: StepGraph,
+    options: &RunOptions,
+) -> anyhow::Result<RunHistory> {
+    execute_flow(flow, graph, options).await
+}
+
+/// Same as `run_flow_with_options`, but reports progress as a stream of
+/// `RunEvent`s ending in `RunFinished` (or `RunFailed`)
+///
+/// The run is spawned onto the tokio runtime, so it keeps going even if the
+/// stream is dropped early.
+pub fn run_flow_stream(flow: Flow, graph: StepGraph, mut options: RunOptions) -> impl Stream<Item = RunEvent> {
+    let (tx, rx) = unbounded_channel();
+
+    // Forward events into the stream, still feeding any callback already set
+    let callback = options.on_event.take();
+    options.on_event = Some(Arc::new(move |event: RunEvent| {
+        if let Some(callback) = &callback {
+            callback(event.clone());
+        }
+        // A dropped receiver just means nobody is listening anymore
+        let _ = tx.send(event);
+    }));
+
+    tokio::spawn(async move {
+        let _ = execute_flow(&flow, graph, &options).await;
+    });
+
+    futures::stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|event| (event, rx)) })
+}
+
+/// Steps that could start now, given the results so far (in declaration
+/// order): not in `completed` yet, and every dependency is — with a status
+/// that satisfies it (see `Dependency::is_satisfied_by`). A step with
+/// `on_failure` also needs all of those completed, at least one `Failed`.
+///
+/// This is the engine's readiness check, minus the run-time parts (cancel,
+/// timeouts, `when` conditions, parallelism limits).
+pub fn ready_steps(graph: &StepGraph, completed: &HashMap<String, StepStatus>) -> Vec<String> {
+    graph
+        .node_weights()
+        .map(|node| &node.step)
+        .filter(|step| !completed.contains_key(&step.id))
+        .filter(|step| {
+            step.depends_on
+                .iter()
+                .all(|dep| {
+                    dep.step_id()
+                        .and_then(|id| completed.get(id))
+                        .is_some_and(|status| dep.is_satisfied_by(status))
+                })
+        })
+        .filter(|step| {
+            step.on_failure.is_empty()
+                || (step.on_failure.iter().all(|id| completed.contains_key(id))
+                    && step.on_failure.iter().any(|id| matches!(completed[id], StepStatus::Failed(..))))
+        })
+        .map(|step| step.id.clone())
+        .collect()
+}
+
+/// Records a step's result and tells the event listener, if there is one
+fn record(
+    results: &mut HashMap<String, StepResult>,
+    levels: &HashMap<String, usize>,
+    events: Option<&EventCallback>,
+    step_id: &str,
+    mut result: StepResult,
+) {
+    result.level = levels.get(step_id).copied().unwrap_or_default();
+    if let Some(events) = events {
+        events(RunEvent::StepFinished {
+            step_id: step_id.to_string(),
+            result: result.clone(),
+        });
+    }
+    results.insert(step_id.to_string(), result);
+}
+
+/// Skips every descendant of `failed_id` (see `flow::descendants`) that a
+/// dependency without a result yet can no longer save: one with a
+/// `depends_on` entry — on the failed step, or on a descendant skipped here —
+/// that its result doesn't satisfy. Completion edges and `on_failure`
+/// handlers are left to run. Results read as if each step had waited.
+fn skip_descendants(
+    graph: &StepGraph,
+    failed_id: &str,
+    results: &mut HashMap<String, StepResult>,
+    levels: &HashMap<String, usize>,
+    events: Option<&EventCallback>,
+) {
+    let Ok(below) = descendants(graph, failed_id) else {
+        return;
+    };
+    let mut unsettled: Vec<&Step> = graph
+        .node_weights()
+        .map(|node| &node.step)
+        .filter(|step| below.contains(&step.id) && !results.contains_key(&step.id))
+        .collect();
+    // Shallowest first, so a step sees whether its dependencies were skipped
+    unsettled.sort_by_key(|step| levels.get(&step.id).copied().unwrap_or_default());
+
+    for step in unsettled {
+        let blocked_by = step.depends_on.iter().find_map(|dep| {
+            let dep_id = dep.step_id()?;
+            let status = &results.get(dep_id)?.status;
+            (!dep.is_satisfied_by(status)).then(|| (dep_id.clone(), status.clone()))
+        });
+        let result = match blocked_by {
+            None => continue,
+            Some((dep_id, StepStatus::Skipped(_))) => {
+                info!("⏭️ Step '{}' skipped because dependency '{dep_id}' was skipped", step.id);
+                StepResult::skipped(format!("Dependency '{dep_id}' was skipped"))
+                    .because(format!("skipped (dep '{dep_id}' skipped)"))
+            }
+            Some((dep_id, status)) => {
+                warn!("⛔ Step '{}' blocked by failed dependency '{dep_id}'", step.id);
+                let state = status.state();
+                StepResult::skipped(format!("Dependency '{dep_id}' {state}")).because(format!("skipped (dep '{dep_id}' {state})"))
+            }
+        };
+        record(results, levels, events, &step.id, result);
+    }
+}
+
+/// The executor behind `run_flow_with_options` and `run_flow_stream`
+///
+/// Everything logged during the run happens inside a `run` span carrying
+/// `run_id` and `flow_id`; each started step gets a nested `step` span.
+/// The last event sent to `options.on_event` is `RunFinished` or `RunFailed`.
+async fn execute_flow(flow: &Flow, graph: StepGraph, options: &RunOptions) -> anyhow::Result<RunHistory> {
+    let run_id = options
+        .run_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let span = info_span!("run", run_id = %run_id, flow_id = %flow.id);
+    #[cfg(feature = "otel")]
+    crate::telemetry::annotate_run(&span, &flow.id, &run_id);
+    // The channel, if any, is fed from the same place as the callback
+    let forward: Option<EventCallback> = options.event_sender.clone().map(|sender| {
+        let callback = options.on_event.clone();
+        Arc::new(move |event: RunEvent| {
+            if let Some(callback) = &callback {
+                callback(event.clone());
+            }
+            let _ = sender.try_send(event);
+        }) as EventCallback
+    });
+    let events = forward.as_ref().or(options.on_event.as_ref());
+    let result = execute_flow_in_span(run_id, flow, graph, options, events)
+        .instrument(span)
+        .await;
+
+    if let Some(events) = events {
+        events(match &result {
+            Ok(history) => RunEvent::RunFinished(history.clone()),
+            Err(err) => RunEvent::RunFailed(err.to_string()),
+        });
+    }
+    result
+}
+
+/// Body of `execute_flow`, run inside the `run` span
+async fn execute_flow_in_span(
+    run_id: String,
+    flow: &Flow,
+    graph: StepGraph,
+    options: &RunOptions,
+    events: Option<&EventCallback>,
+) -> anyhow::Result<RunHistory> {
+    // Held until the run returns, so same-group runs never overlap
+    let _group_guard = match &flow.concurrency_group {
+        Some(group) => Some(acquire_concurrency_group(group, options.on_conflict, &flow.id).await?),
+        None => None,
+    };
+
+    let started_at = Utc::now();
     info!("🚀 Starting run {run_id} for flow '{}'", flow.id);
+    if let Some(events) = events {
+        events(RunEvent::RunStarted {
+            run_id: run_id.clone(),
+            flow_id: flow.id.clone(),
+        });
+    }
+
+    if let Some(previous) = &options.resume_from {
+        if previous.flow_id != flow.id {
+            return Err(anyhow::anyhow!(
+                "Cannot resume flow '{}' from a run of flow '{}'",
+                flow.id,
+                previous.flow_id
+            ));
+        }
+        if previous.dry_run {
+            return Err(anyhow::anyhow!(
+                "Cannot resume from run {}: it was a dry run, so nothing in it actually ran",
+                previous.run_id
+            ));
+        }
+        info!("⏩ Resuming from run {}", previous.run_id);
+    }
+
+    if options.only_failed {
+        let Some(previous) = &options.resume_from else {
+            return Err(anyhow::anyhow!("Rerunning only failed steps needs the run they failed in"));
+        };
+        check_rerunnable(&graph, previous)?;
+    }
+
+    // Narrow the graph down to the requested steps (e.g. `--from fetch`)
+    let graph = if options.selection.is_empty() {
+        graph
+    } else {
+        options.selection.apply(&graph)?
+    };
+
+    // Inputs with defaults filled in, for `{{ inputs.<name> }}` in step config
+    let inputs = resolve_inputs(flow, &options.inputs)?;
 
     // Stores the result for each step as we go
     let mut results: HashMap<String, StepResult> = HashMap::new();
@@ -64,81 +918,1061 @@ pub async fn run_flow(flow: &Flow, graph: StepGraph) -> anyhow::Result<RunHistor
             "Cycle detected at step {:?}",
             graph[cycle.node_id()].step.id
         ))?;
+    // Scheduling wave of each step (see `flow::step_depths`), by step ID
+    let levels: HashMap<String, usize> = step_depths(&graph)
+        .into_iter()
+        .map(|(idx, depth)| (graph[idx].step.id.clone(), depth))
+        .collect();
 
-    // Execute each step in topological order
-    for node_idx in sorted {
-        let node = &graph[node_idx];
-        let step = &node.step;
+    // Set once the step gate asks to abort — everything after that is skipped
+    let mut aborted = false;
+
+    // The whole run races against this deadline; once it passes, the step in
+    // flight is dropped and everything left fails with "flow timeout"
+    let deadline = options
+        .flow_timeout_seconds
+        .or(flow.timeout_seconds)
+        .map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs));
+    let mut timed_out = false;
+
+    // Resolves once the run is cancelled; never polled again after that
+    let cancel_signal = wait_for_cancel(options);
+    tokio::pin!(cancel_signal);
+    let mut cancelled = false;
+
+    // Successful outputs by `idempotency_key`, so a later step with the same
+    // key reuses the result instead of executing again (this run only)
+    let mut idempotent_outputs: HashMap<String, String> = HashMap::new();
+
+    // Outputs kept from earlier runs, and the keys of started steps (by ID)
+    // whose output goes into it once they succeed
+    let mut cache = options
+        .cache_dir
+        .as_deref()
+        .filter(|_| !options.dry_run)
+        .map(StepCache::open);
+    let mut cache_keys: HashMap<String, String> = HashMap::new();
+
+    // One semaphore per capped kind: the flow's `concurrency` block, with
+    // `RunOptions::kind_limits` taking precedence
+    let mut limits = flow.concurrency.clone();
+    limits.extend(options.kind_limits.iter().map(|(kind, limit)| (kind.clone(), *limit)));
+    let kind_limits: HashMap<String, Semaphore> = limits
+        .into_iter()
+        .map(|(kind, limit)| (kind, Semaphore::new(limit.max(1))))
+        .collect();
+
+    // Retries left for the whole run, shared by steps running in parallel
+    let retry_budget = options.retry_budget.map(AtomicUsize::new);
+
+    let executor = Executor {
+        registry: &options.registry,
+        sim_latency_ms: options.sim_latency_ms.unwrap_or(DEFAULT_SIM_LATENCY_MS),
+        rate_limits: flow
+            .rate_limits
+            .iter()
+            .map(|(kind, limit)| {
+                let quota = Quota::per_second(limit.per_second).allow_burst(NonZeroU32::MIN);
+                (kind.clone(), RateLimiter::direct(quota))
+            })
+            .collect(),
+        dry_run: options.dry_run,
+        env_access: &options.env_access,
+        slots: Semaphore::new(options.max_parallel.max(1)),
+        inherited: InheritedOptions::from(options),
+    };
+
+    let max_parallel = options.max_parallel.max(1);
+    let step_ids: HashSet<&str> = graph.node_weights().map(|node| node.step.id.as_str()).collect();
+    // Ready steps start highest `priority` first, then in declaration order
+    // (`flow.nodes`) rather than toposort's arbitrary order among independent
+    // steps, so serial runs and their logs are reproducible
+    let position: HashMap<&str, usize> = flow
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, step)| (step.id.as_str(), idx))
+        .collect();
+    let mut pending = sorted;
+    pending.sort_by_key(|idx| {
+        let step = &graph[*idx].step;
+        let declared = position.get(step.id.as_str()).copied();
+        (Reverse(step.priority), declared.unwrap_or(usize::MAX), *idx)
+    });
+    let mut running: Vec<NodeIndex> = Vec::new();
+    let mut in_flight = FuturesUnordered::new();
+    // Failed steps whose descendants have been settled already
+    let mut failures_seen: HashSet<String> = HashSet::new();
 
-        // Enforce dependency rules — don’t run if any parent failed
-        let mut all_deps_ok = true;
-        for dep_id in &step.depends_on {
-            if let Some(dep_result) = results.get(dep_id) {
-                if !matches!(dep_result.status, StepStatus::Success) {
-                    warn!("⛔ Step '{}' blocked by failed dependency '{}'", step.id, dep_id);
-                    all_deps_ok = false;
+    loop {
+        // Settle or start ready steps, by priority then declaration, while slots
+        // are free. With `max_parallel == 1` this is the plain serial loop.
+        let mut next = 0;
+        while next < pending.len() && in_flight.len() < max_parallel {
+            let node_idx = pending[next];
+            let step = &graph[node_idx].step;
+
+            // Already settled along with a failed ancestor (see `skip_descendants`)
+            if results.contains_key(&step.id) {
+                pending.remove(next);
+                continue;
+            }
+
+            // Wait until every dependency has a result (unknown IDs are
+            // reported as missing below rather than waited on forever)
+            let ready = step
+                .upstream_ids()
+                .all(|dep| results.contains_key(dep) || !step_ids.contains(dep.as_str()));
+            if !ready {
+                next += 1;
+                continue;
+            }
+            pending.remove(next);
+            // Settling this step may have readied one that comes before it
+            next = 0;
+
+            let resumed_run = options.resume_from.as_ref().map_or("", |history| history.run_id.as_str());
+            let previous_result = options
+                .resume_from
+                .as_ref()
+                .and_then(|previous| previous.step_results.get(&step.id));
+            // Resuming reruns everything but successes; `only_failed` only failures.
+            // A truncated output isn't the real one, so that step reruns too.
+            let carried_over = previous_result.filter(|result| match result.status {
+                StepStatus::Success => true,
+                StepStatus::Failed(..) => false,
+                _ => options.only_failed,
+            });
+            let carried_over = match carried_over {
+                Some(previous) if previous.output_bytes.is_some() => {
+                    info!("🔁 Step '{}' runs again: its output in run {resumed_run} was truncated", step.id);
+                    None
+                }
+                other => other,
+            };
+            if let Some(previous) = carried_over {
+                // Skipped back then over a dependency that has succeeded now:
+                // `only_failed` still doesn't run it, so don't claim the old reason
+                let satisfied_by = |step_results: &HashMap<String, StepResult>| {
+                    step.depends_on.iter().all(|dep| {
+                        dep.step_id()
+                            .and_then(|id| step_results.get(id))
+                            .is_some_and(|result| dep.is_satisfied_by(&result.status))
+                    })
+                };
+                let resumed = options.resume_from.as_ref().map(|history| &history.step_results);
+                let unblocked = resumed.is_some_and(|then| !satisfied_by(then)) && satisfied_by(&results);
+                if let (StepStatus::Skipped(earlier), true) = (&previous.status, unblocked) {
+                    warn!(
+                        "⚠️ Step '{}' was skipped in run {resumed_run} and isn't rerun, though its dependencies \
+                         succeeded now; resume without only-failed to run it",
+                        step.id
+                    );
+                    let result = StepResult::skipped(format!("Not rerun (skipped in run {resumed_run}: {earlier})"))
+                        .because("skipped (not rerun, though its dependencies succeeded now)");
+                    record(&mut results, &levels, events, &step.id, result);
+                    continue;
                 }
-            } else {
-                // This should never happen if DAG is valid
-                warn!("⚠️ Missing result for dependency '{}'", dep_id);
-                all_deps_ok = false;
+
+                let outcome = match previous.status {
+                    StepStatus::Success => "succeeded",
+                    _ => previous.status.state(),
+                };
+                info!("⏩ Step '{}' already {outcome} in the resumed run", step.id);
+                if let (Some(key), Some(output)) = (&step.idempotency_key, &previous.output) {
+                    idempotent_outputs.insert(key.clone(), output.clone());
+                }
+                let reason = format!("carried over ({outcome} in run {resumed_run})");
+                record(&mut results, &levels, events, &step.id, previous.clone().because(reason));
+                continue;
+            }
+            if options.only_failed && previous_result.is_none() {
+                let result = StepResult::skipped(format!("Not part of run {resumed_run}"))
+                    .because(format!("skipped (not in run {resumed_run})"));
+                record(&mut results, &levels, events, &step.id, result);
+                continue;
+            }
+
+            if aborted {
+                record(&mut results, &levels, events, &step.id, StepResult::skipped("Run aborted").because("skipped (run aborted)"));
+                continue;
+            }
+
+            if !cancelled && (&mut cancel_signal).now_or_never().is_some() {
+                warn!("🛑 Run {run_id} cancelled before step '{}'", step.id);
+                cancelled = true;
+            }
+            if cancelled {
+                record(&mut results, &levels, events, &step.id, StepResult::cancelled().because("cancelled (run cancelled before it started)"));
+                continue;
+            }
+
+            if timed_out || deadline.is_some_and(|deadline| tokio::time::Instant::now() >= deadline) {
+                timed_out = true;
+                record(&mut results, &levels, events, &step.id, StepResult::failed(FailureKind::Cancelled, FLOW_TIMEOUT).because("failed (flow timeout before it started)"));
+                continue;
+            }
+
+            // Enforce dependency rules — don’t run if any parent failed or was
+            // skipped, unless the edge only asks for completion. A failure
+            // skips the step (and so, in turn, its own dependents) rather
+            // than failing it too; only a malformed graph fails it.
+            let mut missing_dep: Option<&String> = None;
+            let mut skipped_dep: Option<&String> = None;
+            // First dependency that blocks the step, and what happened to it
+            let mut blocked_by: Option<(&String, &str)> = None;
+            for dep in &step.depends_on {
+                // The graph's copy of a step has its labels expanded
+                let Some(dep_id) = dep.step_id() else {
+                    continue;
+                };
+                match results.get(dep_id).map(|r| &r.status) {
+                    Some(status) if dep.is_satisfied_by(status) => {}
+                    Some(StepStatus::Skipped(_)) => {
+                        info!("⏭️ Step '{}' skipped because dependency '{}' was skipped", step.id, dep_id);
+                        skipped_dep = Some(dep_id);
+                    }
+                    Some(status) => {
+                        warn!("⛔ Step '{}' blocked by failed dependency '{}'", step.id, dep_id);
+                        blocked_by.get_or_insert((dep_id, status.state()));
+                    }
+                    None => {
+                        // This should never happen if DAG is valid
+                        warn!("⚠️ Missing result for dependency '{}'", dep_id);
+                        missing_dep.get_or_insert(dep_id);
+                    }
+                }
+            }
+
+            if let Some(dep_id) = missing_dep {
+                let result = StepResult::failed(FailureKind::DependencyBlocked, format!("Missing dependency '{dep_id}'")).because(format!("blocked (dep '{dep_id}' missing)"));
+                record(&mut results, &levels, events, &step.id, result);
+                continue;
+            }
+
+            if let Some((dep_id, state)) = blocked_by {
+                let result = StepResult::skipped(format!("Dependency '{dep_id}' {state}"))
+                    .because(format!("skipped (dep '{dep_id}' {state})"));
+                record(&mut results, &levels, events, &step.id, result);
+                continue;
             }
-        }
 
-        if !all_deps_ok {
-            // Mark step as blocked
-            results.insert(
-                step.id.clone(),
-                StepResult {
-                    status: StepStatus::Failed("Blocked by failed dependencies".into()),
-                    output: None,
+            if let Some(dep_id) = skipped_dep {
+                let result = StepResult::skipped(format!("Dependency '{dep_id}' was skipped"))
+                    .because(format!("skipped (dep '{dep_id}' skipped)"));
+                record(&mut results, &levels, events, &step.id, result);
+                continue;
+            }
+
+            // An error-handling branch only runs once something it watches failed
+            if !step.on_failure.is_empty()
+                && !step
+                    .on_failure
+                    .iter()
+                    .any(|id| matches!(results.get(id).map(|r| &r.status), Some(StepStatus::Failed(..))))
+            {
+                info!("⏭️ Step '{}' skipped: none of its on_failure steps failed", step.id);
+                let result = StepResult::skipped("No on_failure step failed").because("skipped (nothing failed)");
+                record(&mut results, &levels, events, &step.id, result);
+                continue;
+            }
+
+            // Disabled steps stand in for a success: dependents run as usual
+            if !step.enabled {
+                info!("⏭️ Step '{}' is disabled", step.id);
+                record(&mut results, &levels, events, &step.id, StepResult::skipped(DISABLED).because("skipped (disabled)"));
+                continue;
+            }
+
+            // Everything the step can see from earlier steps
+            let outputs: HashMap<String, String> = results
+                .iter()
+                .filter_map(|(id, result)| result.output.clone().map(|output| (id.clone(), output)))
+                .collect();
+
+            if let Some(expr) = &step.when {
+                match condition::evaluate(expr, &outputs) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        info!("⏭️ Step '{}' skipped: condition `{expr}` is false", step.id);
+                        record(&mut results, &levels, events, &step.id, StepResult::skipped("condition false").because("skipped (condition false)"));
+                        continue;
+                    }
+                    Err(err) => {
+                        warn!("❌ Step '{}' has an invalid condition: {err}", step.id);
+                        let result = StepResult::failed(FailureKind::ConfigError, format!("Invalid condition: {err}")).because("failed (invalid condition)");
+                        record(&mut results, &levels, events, &step.id, result);
+                        continue;
+                    }
+                }
+            }
+
+            if let Some(output) = step.idempotency_key.as_ref().and_then(|key| idempotent_outputs.get(key)) {
+                info!("♻️ Step '{}' reuses the cached result for its idempotency key", step.id);
+                let result = StepResult::success(output.clone()).because("reused (an earlier step had the same idempotency key)");
+                record(&mut results, &levels, events, &step.id, result);
+                continue;
+            }
+
+            let template_ctx = TemplateContext {
+                inputs: &inputs,
+                outputs: &outputs,
+                item: None,
+                env: &options.env_access,
+            };
+
+            // A `for_each` step fans out over its list; no list, no run
+            let items = match &step.for_each {
+                Some(expr) => match for_each_items(expr, &template_ctx) {
+                    Ok(items) => Some(items),
+                    Err(err) => {
+                        warn!("❌ Step '{}' has an invalid for_each: {err}", step.id);
+                        let result = StepResult::failed(FailureKind::ConfigError, err).because("failed (invalid for_each)");
+                        record(&mut results, &levels, events, &step.id, result);
+                        continue;
+                    }
                 },
+                None => None,
+            };
+
+            // --- Start the actual step ---
+            let mut step_def = step.clone();
+            step_def.timeout_seconds = step.timeout_seconds.or(options.default_step_timeout);
+            if step_def.retry.is_none() {
+                step_def.retry = options.default_retry.clone();
+            }
+
+            step_def.config = template::render(&flow.step_config(step), &template_ctx);
+            if let Some(compensation) = &mut step_def.compensation {
+                compensation.config = template::render(&compensation.config, &template_ctx);
+            }
+
+            // Give the caller (e.g. the interactive debugger) a say before running,
+            // on a blocking thread since gates may wait on stdin
+            if let Some(gate) = &options.step_gate {
+                let (gate, gated) = (gate.clone(), step_def.clone());
+                let decision = tokio::task::spawn_blocking(move || gate(&gated))
+                    .await
+                    .unwrap_or(StepDecision::Abort);
+                match decision {
+                    StepDecision::Run => {}
+                    StepDecision::Skip => {
+                        info!("⏭️ Step '{}' skipped by user", step.id);
+                        record(&mut results, &levels, events, &step.id, StepResult::skipped("Skipped by user").because("skipped (by user)"));
+                        continue;
+                    }
+                    StepDecision::Abort => {
+                        warn!("🛑 Run aborted by user before step '{}'", step.id);
+                        aborted = true;
+                        record(&mut results, &levels, events, &step.id, StepResult::skipped("Run aborted").because("skipped (aborted by user)"));
+                        continue;
+                    }
+                }
+            }
+
+            if let (Some(cache), Some(key)) = (&cache, cache_key(&step_def, items.as_deref())) {
+                if let Some(output) = cache.get(&key) {
+                    info!("💾 Step '{}' reuses its cached output", step.id);
+                    if let Some(idempotency_key) = &step.idempotency_key {
+                        idempotent_outputs.insert(idempotency_key.clone(), output.clone());
+                    }
+                    let result = StepResult {
+                        from_cache: true,
+                        ..StepResult::success(output.clone())
+                    };
+                    record(&mut results, &levels, events, &step.id, result.because("reused (cached by an earlier run)"));
+                    continue;
+                }
+                cache_keys.insert(step.id.clone(), key);
+            }
+
+            if let Some(events) = events {
+                events(RunEvent::StepStarted {
+                    step_id: step.id.clone(),
+                    config: step_def.config.clone(),
+                });
+            }
+
+            let ctx = StepContext {
+                run_id: run_id.clone(),
+                flow_id: flow.id.clone(),
+                step: step_def,
+                outputs,
+                inputs: inputs.clone(),
+                base_dir: flow.base_dir.clone().unwrap_or_else(|| PathBuf::from(".")),
+                flow_file: flow.file.clone(),
+                http_client: options.registry.http_client().clone(),
+                inherited: executor.inherited.clone(),
+            };
+
+            let span = info_span!("step", id = %step.id, kind = %step.kind);
+            #[cfg(feature = "otel")]
+            crate::telemetry::annotate_step(&span, &flow.id, &run_id, &step.kind);
+            running.push(node_idx);
+            let step_run = run_step(
+                node_idx,
+                ctx,
+                items,
+                &executor,
+                kind_limits.get(&step.kind),
+                retry_budget.as_ref(),
+                max_parallel,
             );
-            continue;
+            in_flight.push(step_run.instrument(span));
+        }
+
+        // Settle what new failures rule out right away, rather than once
+        // unrelated branches those steps also wait on are done. A deliberate
+        // stop or timeout records its own reason for what's left instead.
+        if !aborted && !cancelled && !timed_out {
+            let failed: Vec<String> = results
+                .iter()
+                .filter(|(id, result)| matches!(result.status, StepStatus::Failed(..)) && !failures_seen.contains(*id))
+                .map(|(id, _)| id.clone())
+                .collect();
+            for step_id in failed {
+                skip_descendants(&graph, &step_id, &mut results, &levels, events);
+                failures_seen.insert(step_id);
+            }
         }
 
-        // --- Run the actual step (simulated for now) ---
-        info!("▶️ Running step '{}': {}", step.id, step.kind);
+        if in_flight.is_empty() {
+            // Nothing running means every remaining step was ready and settled
+            debug_assert!(pending.is_empty(), "steps left with unresolvable dependencies");
+            break;
+        }
 
-        match simulate_step_execution(&step.id, &step.kind).await {
-            Ok(output) => {
-                info!("✅ Step '{}' succeeded", step.id);
-                results.insert(
-                    step.id.clone(),
-                    StepResult {
-                        status: StepStatus::Success,
-                        output: Some(output),
-                    },
-                );
+        // Wait for a step to finish, the run to be cancelled, or the deadline
+        let flow_deadline = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
             }
-            Err(err) => {
-                warn!("❌ Step '{}' failed: {err}", step.id);
-                results.insert(
-                    step.id.clone(),
-                    StepResult {
-                        status: StepStatus::Failed(err),
-                        output: None,
-                    },
-                );
+        };
+        let wake = if cancelled {
+            // Noticed while settling ready steps — stop whatever is still running
+            Wake::Cancelled
+        } else {
+            tokio::select! {
+                Some((node_idx, result)) = in_flight.next() => Wake::Finished(node_idx, result),
+                _ = &mut cancel_signal => Wake::Cancelled,
+                _ = flow_deadline => Wake::TimedOut,
+            }
+        };
+
+        match wake {
+            Wake::Finished(node_idx, result) => {
+                let step = &graph[node_idx].step;
+                running.retain(|idx| *idx != node_idx);
+                if let (Some(key), Some(output)) = (&step.idempotency_key, &result.output) {
+                    idempotent_outputs.insert(key.clone(), output.clone());
+                }
+                if let (Some(cache), Some(key)) = (&mut cache, cache_keys.get(&step.id)) {
+                    if let (StepStatus::Success, Some(output)) = (&result.status, &result.output) {
+                        cache.insert(key.clone(), output.clone());
+                    }
+                }
+                record(&mut results, &levels, events, &step.id, result);
+            }
+            Wake::Cancelled => {
+                // Dropping the futures stops the handlers mid-flight
+                cancelled = true;
+                in_flight.clear();
+                for node_idx in running.drain(..) {
+                    warn!("🛑 Run {run_id} cancelled while step '{}' was running", graph[node_idx].step.id);
+                    let result = StepResult::cancelled().because("cancelled (run cancelled while it was running)");
+                    record(&mut results, &levels, events, &graph[node_idx].step.id, result);
+                }
+            }
+            Wake::TimedOut => {
+                timed_out = true;
+                in_flight.clear();
+                for node_idx in running.drain(..) {
+                    warn!("⏰ Flow timeout reached while step '{}' was running", graph[node_idx].step.id);
+                    let result = StepResult::failed(FailureKind::Cancelled, FLOW_TIMEOUT).because("failed (flow timeout while it was running)");
+                    record(&mut results, &levels, events, &graph[node_idx].step.id, result);
+                }
             }
         }
     }
 
     // Determine if the flow completed fully or partially failed
-    let has_failures = results.values().any(|r| matches!(r.status, StepStatus::Failed(_)));
+    let has_failures = results.values().any(|r| matches!(r.status, StepStatus::Failed(..)));
 
-    let status = if has_failures {
+    let status = if aborted {
+        RunStatus::Failed("Aborted by user".into())
+    } else if cancelled {
+        RunStatus::Failed(CANCELLED.into())
+    } else if timed_out {
+        RunStatus::Failed(FLOW_TIMEOUT.into())
+    } else if has_failures {
         RunStatus::Failed("At least one step failed".into())
     } else {
         RunStatus::Success
     };
 
-    Ok(RunHistory {
+    // A deliberate stop (abort or cancel) leaves things as they are; a
+    // timeout cleans up whatever side effects made it in time
+    let rollback_wanted = timed_out || (flow.rollback_on_failure && has_failures);
+    let rollback = if rollback_wanted && !aborted && !cancelled && !options.dry_run {
+        roll_back(flow, &graph, &results, &inputs, &run_id, &executor).await
+    } else {
+        Vec::new()
+    };
+
+    if let Some(cache) = &mut cache {
+        // An undone step's cached output no longer reflects the world
+        for compensated in &rollback {
+            if let Some(key) = cache_keys.get(&compensated.step_id) {
+                cache.remove(key);
+            }
+        }
+        if let Err(err) = cache.save() {
+            warn!("⚠️ Could not save the step cache: {err}");
+        }
+    }
+
+    if let Some(max_bytes) = options.max_output_bytes {
+        for result in results.values_mut() {
+            result.truncate_output(max_bytes);
+        }
+    }
+
+    let mut history = RunHistory {
         run_id,
         flow_id: flow.id.clone(),
         status,
         step_results: results,
-    })
+        started_at,
+        finished_at: Utc::now(),
+        digest: String::new(),
+        rollback,
+        note: flow.nodes.is_empty().then(|| EMPTY_FLOW_NOTE.to_string()),
+        dry_run: options.dry_run,
+    };
+    history.digest = history.compute_digest();
+
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_run(&history);
+
+    if let Some(notify) = &flow.notify {
+        notify_run(options.registry.http_client(), notify, &history).await;
+    }
+
+    Ok(history)
+}
+
+/// What the scheduler woke up for while steps were in flight
+enum Wake {
+    Finished(NodeIndex, StepResult),
+    Cancelled,
+    TimedOut,
+}
+
+/// Executes one started step to its final result — once, or once per item
+/// for `for_each` steps (see `run_for_each`)
+async fn run_step(
+    node_idx: NodeIndex,
+    ctx: StepContext,
+    items: Option<Vec<serde_yaml::Value>>,
+    executor: &Executor<'_>,
+    kind_limit: Option<&Semaphore>,
+    retry_budget: Option<&AtomicUsize>,
+    max_parallel: usize,
+) -> (NodeIndex, StepResult) {
+    let result = match items {
+        Some(items) => run_for_each(&ctx, items, executor, kind_limit, retry_budget, max_parallel).await,
+        None => execute_once(&ctx, executor, kind_limit, retry_budget).await,
+    };
+    (node_idx, result)
+}
+
+/// The list a `for_each` expression stands for: a list value (e.g. an
+/// input), or text holding a YAML/JSON list (e.g. a step's output)
+fn for_each_items(expr: &str, template_ctx: &TemplateContext) -> Result<Vec<serde_yaml::Value>, String> {
+    let not_a_list = || format!("`for_each: {expr}` is not a list");
+    match template::render(&serde_yaml::Value::String(expr.to_string()), template_ctx) {
+        serde_yaml::Value::Sequence(items) => Ok(items),
+        serde_yaml::Value::String(text) => match serde_yaml::from_str(&text) {
+            Ok(serde_yaml::Value::Sequence(items)) => Ok(items),
+            _ => Err(not_a_list()),
+        },
+        _ => Err(not_a_list()),
+    }
+}
+
+/// Runs a `for_each` step once per item, with `{{ item }}` filled into its
+/// config. Each item takes a permit of the step's kind if that's capped,
+/// then one of the run's slots (`Executor::slots`), so however many items
+/// and steps are in flight, at most `max_parallel` execute at once.
+///
+/// Succeeds with the items' outputs as a JSON list (in item order) if every
+/// item succeeds; otherwise fails, naming the items that failed.
+async fn run_for_each(
+    ctx: &StepContext,
+    items: Vec<serde_yaml::Value>,
+    executor: &Executor<'_>,
+    kind_limit: Option<&Semaphore>,
+    retry_budget: Option<&AtomicUsize>,
+    max_parallel: usize,
+) -> StepResult {
+    let total = items.len();
+    info!("🔀 Step '{}' runs once for each of {total} items", ctx.step.id);
+
+    // Inputs and outputs were already filled in when the step started
+    let (no_inputs, no_outputs) = (BTreeMap::new(), HashMap::new());
+    let results: Vec<StepResult> = futures::stream::iter(items)
+        .map(|item| {
+            let template_ctx = TemplateContext {
+                inputs: &no_inputs,
+                outputs: &no_outputs,
+                item: Some(&item),
+                env: executor.env_access,
+            };
+            let mut item_ctx = ctx.clone();
+            item_ctx.step.config = template::render(&ctx.step.config, &template_ctx);
+            if let Some(compensation) = &mut item_ctx.step.compensation {
+                compensation.config = template::render(&compensation.config, &template_ctx);
+            }
+            async move { execute_once(&item_ctx, executor, kind_limit, retry_budget).await }
+        })
+        .buffered(max_parallel)
+        .collect()
+        .await;
+
+    let failures: Vec<(FailureKind, String)> = results
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, result)| match &result.status {
+            StepStatus::Failed(kind, reason) => Some((*kind, format!("item {idx}: {reason}"))),
+            _ => None,
+        })
+        .collect();
+    let diagnostics: Vec<String> = results
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, result)| result.diagnostics.as_ref().map(|text| format!("item {idx}: {text}")))
+        .collect();
+
+    let result = if failures.is_empty() {
+        let outputs: Vec<&str> = results.iter().map(|result| result.output.as_deref().unwrap_or_default()).collect();
+        StepResult::success(serde_json::to_string(&outputs).unwrap_or_default())
+            .because(format!("ran for all {total} items"))
+    } else {
+        // The first failed item's kind stands for the step
+        let failed = failures.len();
+        let reasons: Vec<&str> = failures.iter().map(|(_, reason)| reason.as_str()).collect();
+        StepResult::failed(failures[0].0, format!("{failed} of {total} items failed: {}", reasons.join("; ")))
+            .because(format!("failed ({failed} of {total} items failed)"))
+    };
+    StepResult {
+        diagnostics: (!diagnostics.is_empty()).then(|| diagnostics.join("\n")),
+        attempts: results.iter().map(|result| result.attempts).sum(),
+        change: results.iter().filter_map(|result| result.change).reduce(Change::combine),
+        ..result
+    }
+}
+
+/// Executes a step (or one `for_each` item) to its result: waits for a
+/// permit of its kind (if capped) and then for a run slot, runs it with
+/// retries (while `retry_budget` lasts), and compensates on timeout if asked
+async fn execute_once(
+    ctx: &StepContext,
+    executor: &Executor<'_>,
+    kind_limit: Option<&Semaphore>,
+    retry_budget: Option<&AtomicUsize>,
+) -> StepResult {
+    // Never closed, so `acquire` can't fail. Always kind first, then slot,
+    // so nothing holds a slot while waiting on its kind.
+    let _permit = match kind_limit {
+        Some(limit) => limit.acquire().await.ok(),
+        None => None,
+    };
+    let _slot = executor.slots.acquire().await.ok();
+
+    if executor.dry_run {
+        return dry_run_step(ctx, executor).await;
+    }
+
+    let step = &ctx.step;
+    info!("▶️ Running step '{}': {}", step.id, step.kind);
+    #[cfg(feature = "metrics")]
+    let started = std::time::Instant::now();
+    let (outcome, attempts) = execute_with_retries(ctx, executor, retry_budget).await;
+    let tries = if attempts > 1 { format!(" after {attempts} attempts") } else { String::new() };
+    let result = match outcome {
+        Err(StepError::TimedOut(secs)) if step.compensate_on_timeout && step.compensation.is_some() => {
+            // The handler may have been cut off mid-side-effect — clean up right away
+            warn!("⏱️ Step '{}' timed out after {secs}s, compensating now", step.id);
+            let compensation = step.compensation.as_ref().expect("checked above");
+            let note = match run_compensation(ctx, compensation, executor).await {
+                Ok(_) => format!("compensation '{}' succeeded", compensation.kind),
+                Err(err) => format!("compensation '{}' failed: {err}", compensation.kind),
+            };
+            StepResult::failed(FailureKind::Timeout, format!("{} ({note})", StepError::TimedOut(secs)))
+                .because(format!("failed{tries} (timeout, compensated)"))
+        }
+        Ok(handled) => {
+            info!("✅ Step '{}' succeeded", step.id);
+            StepResult {
+                diagnostics: handled.diagnostics,
+                ..StepResult::success(handled.output)
+            }
+            .because(format!("ran (all deps ok){tries}"))
+        }
+        Err(err) => {
+            warn!("❌ Step '{}' failed: {err}", step.id);
+            let (kind, cause) = match err {
+                StepError::TimedOut(_) => (FailureKind::Timeout, "timeout"),
+                StepError::Failed(_) => (FailureKind::HandlerError, "handler error"),
+            };
+            StepResult::failed(kind, err.to_string()).because(format!("failed{tries} ({cause})"))
+        }
+    };
+
+    #[cfg(feature = "otel")]
+    crate::telemetry::record_step_status(&tracing::Span::current(), &result.status);
+    #[cfg(feature = "metrics")]
+    crate::metrics::record_step(&step.kind, &result.status, started.elapsed());
+
+    StepResult { attempts, ..result }
+}
+
+/// Asks the step's handler whether running it would change anything,
+/// without running it; kinds without a handler can't tell. The step
+/// succeeds without output unless the check itself fails.
+async fn dry_run_step(ctx: &StepContext, executor: &Executor<'_>) -> StepResult {
+    let step = &ctx.step;
+    let verdict = match executor.registry.get(&step.kind) {
+        Some(handler) => handler.would_change(ctx).await,
+        None => Ok(Change::Unknown),
+    };
+
+    match verdict {
+        Ok(change) => {
+            info!("🔎 Step '{}' (dry run): {change}", step.id);
+            StepResult {
+                output: None,
+                change: Some(change),
+                ..StepResult::success(String::new())
+            }
+            .because(format!("dry run ({change})"))
+        }
+        Err(err) => {
+            warn!("❌ Step '{}' failed its dry run: {err}", step.id);
+            StepResult::failed(FailureKind::HandlerError, err).because("failed (dry run)")
+        }
+    }
+}
+
+/// Resolves when the run should be cancelled: `options.cancel` is triggered,
+/// or Ctrl-C arrives with `cancel_on_ctrl_c` set. Otherwise never resolves.
+async fn wait_for_cancel(options: &RunOptions) {
+    let on_token = async {
+        match &options.cancel {
+            Some(cancel) => cancel.cancelled().await,
+            None => std::future::pending().await,
+        }
+    };
+
+    let on_ctrl_c = async {
+        if options.cancel_on_ctrl_c && tokio::signal::ctrl_c().await.is_ok() {
+            return;
+        }
+        std::future::pending::<()>().await
+    };
+
+    tokio::select! {
+        _ = on_token => {}
+        _ = on_ctrl_c => {}
+    }
+}
+
+/// Takes the process-wide lock for a concurrency group, waiting or failing
+/// according to `policy` if another run currently holds it
+async fn acquire_concurrency_group(
+    group: &str,
+    policy: OnConflict,
+    flow_id: &str,
+) -> anyhow::Result<OwnedMutexGuard<()>> {
+    static GROUPS: OnceLock<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>> = OnceLock::new();
+
+    let lock = GROUPS
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .entry(group.to_string())
+        .or_default()
+        .clone();
+
+    if let Ok(guard) = lock.clone().try_lock_owned() {
+        return Ok(guard);
+    }
+
+    match policy {
+        OnConflict::Queue => {
+            info!("⏳ Flow '{flow_id}' waiting for concurrency group '{group}'");
+            Ok(lock.lock_owned().await)
+        }
+        OnConflict::Reject => Err(anyhow::anyhow!(
+            "Flow '{flow_id}' rejected: concurrency group '{group}' is busy"
+        )),
+    }
+}
+
+/// For `RunOptions::only_failed`: every step that failed in `previous` must
+/// have had its dependencies satisfied then, or rerunning it alone can't work
+fn check_rerunnable(graph: &StepGraph, previous: &RunHistory) -> anyhow::Result<()> {
+    for node in graph.node_weights() {
+        let step = &node.step;
+        let failed = previous
+            .step_results
+            .get(&step.id)
+            .is_some_and(|result| matches!(result.status, StepStatus::Failed(..)));
+        if !failed {
+            continue;
+        }
+
+        let unmet = step
+            .depends_on
+            .iter()
+            .filter_map(|dep| Some((dep, dep.step_id()?)))
+            .find(|(dep, dep_id)| {
+                !previous
+                    .step_results
+                    .get(*dep_id)
+                    .is_some_and(|result| dep.is_satisfied_by(&result.status))
+            });
+        if let Some((_, dep_id)) = unmet {
+            return Err(anyhow::anyhow!(
+                "Step '{}' can't be rerun on its own: its dependency '{}' didn't succeed in run {}",
+                step.id,
+                dep_id,
+                previous.run_id
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Why a step execution failed
+#[derive(Debug)]
+enum StepError {
+    /// The handler returned an error
+    Failed(HandlerError),
+    /// The handler exceeded the step's `timeout_seconds`
+    TimedOut(u64),
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::Failed(reason) => write!(f, "{reason}"),
+            StepError::TimedOut(secs) => write!(f, "Timed out after {secs}s"),
+        }
+    }
+}
+
+/// How steps get executed within a run: registered handlers first, the
+/// simulator for every other kind
+struct Executor<'a> {
+    registry: &'a HandlerRegistry,
+    /// Range the simulator's latency is drawn from, in milliseconds
+    sim_latency_ms: (u64, u64),
+    /// Token buckets for kinds in the flow's `rate_limits`
+    rate_limits: HashMap<String, DefaultDirectRateLimiter>,
+    /// Ask handlers what they would do instead of executing (see `dry_run_step`)
+    dry_run: bool,
+    /// Environment variables `{{ env.<VAR> }}` may read; `for_each` items
+    /// render the config again, so they need the same restriction
+    env_access: &'a EnvAccess,
+    /// `max_parallel` permits, one held by every step or `for_each` item
+    /// while it executes, so items of parallel `for_each` steps share the
+    /// run's limit instead of each getting their own
+    slots: Semaphore,
+    /// Handed to every step, for handlers that start runs of their own
+    inherited: InheritedOptions,
+}
+
+/// Runs a single step through its registered handler, falling back to the
+/// simulator for kinds nobody registered. Enforces `timeout_seconds` and
+/// the kind's rate limit.
+async fn execute_step(ctx: &StepContext, executor: &Executor<'_>) -> Result<HandlerOutput, StepError> {
+    let step = &ctx.step;
+
+    // Waiting for a rate-limited turn doesn't count against the timeout
+    if let Some(limiter) = executor.rate_limits.get(&step.kind) {
+        limiter.until_ready().await;
+    }
+
+    let execution = async {
+        let result = match executor.registry.get(&step.kind) {
+            Some(handler) => handler.execute(ctx).await,
+            None => simulate_step_execution(&step.id, &step.kind, executor.sim_latency_ms)
+                .await
+                .map(HandlerOutput::from)
+                .map_err(HandlerError::from),
+        };
+        result.map_err(StepError::Failed)
+    };
+
+    match step.timeout_seconds {
+        Some(secs) => tokio::time::timeout(Duration::from_secs(secs), execution)
+            .await
+            .unwrap_or(Err(StepError::TimedOut(secs))),
+        None => execution.await,
+    }
+}
+
+/// Runs a step, retrying per its `retry` policy: up to `max_attempts` tries,
+/// `backoff_seconds` apart, but only while the failure is retriable (see
+/// `HandlerError::retriable`; timeouts always are) and matches `retry_on`,
+/// and the run's `retry_budget` (if any) has retries left — each retry takes
+/// one. Also returns how many attempts were made.
+async fn execute_with_retries(
+    ctx: &StepContext,
+    executor: &Executor<'_>,
+    retry_budget: Option<&AtomicUsize>,
+) -> (Result<HandlerOutput, StepError>, usize) {
+    let Some(policy) = &ctx.step.retry else {
+        return (execute_step(ctx, executor).await, 1);
+    };
+
+    let max_attempts = policy.max_attempts.max(1);
+    let mut attempt = 1;
+    loop {
+        let err = match execute_step(ctx, executor).await {
+            Ok(output) => return (Ok(output), attempt),
+            Err(err) => err,
+        };
+
+        let message = err.to_string();
+        let retryable = match (&err, &policy.retry_on) {
+            (StepError::Failed(HandlerError { retriable: false, .. }), _) => false,
+            (_, Some(patterns)) => patterns.iter().any(|pattern| message.contains(pattern.as_str())),
+            (_, None) => true,
+        };
+
+        if !retryable {
+            warn!("🚫 Step '{}' failed with a non-retryable error: {message}", ctx.step.id);
+            return (Err(err), attempt);
+        }
+        if attempt >= max_attempts {
+            return (Err(err), attempt);
+        }
+        let take_retry = |budget: &AtomicUsize| budget.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1));
+        if retry_budget.is_some_and(|budget| take_retry(budget).is_err()) {
+            warn!("💸 Step '{}' failed and the run's retry budget is used up, not retrying: {message}", ctx.step.id);
+            return (Err(err), attempt);
+        }
+
+        warn!(
+            attempt,
+            max_attempts,
+            "🔁 Step '{}' failed (attempt {attempt}/{max_attempts}), retrying in {}s: {message}",
+            ctx.step.id,
+            policy.backoff_seconds
+        );
+        sleep(Duration::from_secs(policy.backoff_seconds)).await;
+        attempt += 1;
+    }
+}
+
+/// Undoes a failed run: runs the `compensation` of every step that
+/// succeeded, in reverse topological order of the succeeded steps — a step
+/// is only compensated once everything that depended on it has been
+///
+/// Steps whose output came from the step cache did nothing in this run, so
+/// they have nothing to undo.
+///
+/// Compensations run one at a time; one failing is recorded and the rest
+/// still run. Configs are templated like the step's, with the final outputs.
+async fn roll_back(
+    flow: &Flow,
+    graph: &StepGraph,
+    results: &HashMap<String, StepResult>,
+    inputs: &BTreeMap<String, serde_yaml::Value>,
+    run_id: &str,
+    executor: &Executor<'_>,
+) -> Vec<Compensated> {
+    let succeeded = graph.filter_map(
+        |_, node| {
+            // A cached output means this run did nothing that needs undoing
+            let result = results.get(&node.step.id).filter(|result| !result.from_cache);
+            (result.map(|result| &result.status) == Some(&StepStatus::Success)).then_some(&node.step)
+        },
+        |_, _| Some(()),
+    );
+    let mut order = toposort(&succeeded, None).expect("subgraph of an acyclic graph");
+    order.reverse();
+
+    let outputs: HashMap<String, String> = results
+        .iter()
+        .filter_map(|(id, result)| result.output.clone().map(|output| (id.clone(), output)))
+        .collect();
+    let template_ctx = TemplateContext {
+        inputs,
+        outputs: &outputs,
+        item: None,
+        env: executor.env_access,
+    };
+
+    let mut rolled_back = Vec::new();
+    for idx in order {
+        let step = succeeded[idx];
+        let Some(compensation) = &step.compensation else {
+            continue;
+        };
+        let compensation = Compensation {
+            config: template::render(&compensation.config, &template_ctx),
+            ..compensation.clone()
+        };
+        let ctx = StepContext {
+            run_id: run_id.to_string(),
+            flow_id: flow.id.clone(),
+            step: step.clone(),
+            outputs: outputs.clone(),
+            inputs: inputs.clone(),
+            base_dir: flow.base_dir.clone().unwrap_or_else(|| PathBuf::from(".")),
+            flow_file: flow.file.clone(),
+            http_client: executor.registry.http_client().clone(),
+            inherited: executor.inherited.clone(),
+        };
+
+        let status = match run_compensation(&ctx, &compensation, executor).await {
+            Ok(_) => StepStatus::Success,
+            Err(err) => {
+                warn!("⚠️ Compensation for step '{}' failed: {err}", step.id);
+                StepStatus::Failed(FailureKind::HandlerError, err.to_string())
+            }
+        };
+        rolled_back.push(Compensated {
+            step_id: step.id.clone(),
+            kind: compensation.kind,
+            status,
+        });
+    }
+    rolled_back
+}
+
+/// Runs a step's compensation through the executor, as if it were a step
+/// with the compensation's kind, config, and `retry` (same ID, timeout, and
+/// context). On failure, the error is the last attempt's.
+async fn run_compensation(
+    ctx: &StepContext,
+    compensation: &Compensation,
+    executor: &Executor<'_>,
+) -> Result<HandlerOutput, StepError> {
+    let mut comp_ctx = ctx.clone();
+    comp_ctx.step.kind = compensation.kind.clone();
+    comp_ctx.step.config = compensation.config.clone();
+    comp_ctx.step.retry = compensation.retry.clone();
+    comp_ctx.step.compensation = None;
+
+    info!("↩️ Compensating step '{}' with '{}'", ctx.step.id, compensation.kind);
+    // Undoing side effects shouldn't be cut short by the run's retry budget
+    let (outcome, _attempts) = execute_with_retries(&comp_ctx, executor, None).await;
+    outcome
 }
 
 /// Simulates executing a step by sleeping + returning fake output
@@ -150,9 +1984,12 @@ pub async fn run_flow(flow: &Flow, graph: StepGraph) -> anyhow::Result<RunHistor
 /// - Log to persistent run history
 ///
 /// This is a placeholder to show how the engine behaves.
-async fn simulate_step_execution(id: &str, kind: &str) -> Result<String, String> {
-    let delay_ms = thread_rng().gen_range(100..300); // Simulate random latency
-    sleep(Duration::from_millis(delay_ms)).await;
+async fn simulate_step_execution(id: &str, kind: &str, latency_ms: (u64, u64)) -> Result<String, String> {
+    let (min, max) = latency_ms;
+    let delay_ms = thread_rng().gen_range(min..=max.max(min)); // Simulate random latency
+    if delay_ms > 0 {
+        sleep(Duration::from_millis(delay_ms)).await;
+    }
 
     // You can trigger a forced failure by setting kind = "fail_test" in YAML
     if kind == "fail_test" {
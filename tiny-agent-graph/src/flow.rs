@@ -1,26 +1,301 @@
 #![allow(dead_code)] // Allow unused code during incremental development
 
-use serde::Deserialize;
-use std::collections::HashMap;
-use std::path::Path;
+use crate::handlers::HandlerRegistry;
+use crate::notify::NotifyConfig;
+use crate::template::{self, Reference};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use petgraph::algo::tarjan_scc;
 use petgraph::graph::{Graph, NodeIndex};
+use petgraph::unionfind::UnionFind;
+use petgraph::Direction;
+use thiserror::Error;
 use tracing::{debug, warn};
 
+/// Everything that can go wrong while loading or validating a flow
+///
+/// Embedders can match on the variant; the CLI just bubbles it up through
+/// `anyhow` (any `std::error::Error` converts via `?`).
+#[derive(Debug, Error)]
+pub enum FlowError {
+    /// The flow file couldn't be read
+    #[error("Could not read flow file: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The YAML is malformed or doesn't match the flow schema
+    #[error("Invalid flow YAML: {0}")]
+    Parse(#[from] serde_yaml::Error),
+
+    /// A flow URL couldn't be fetched (network error or non-2xx answer)
+    #[error("Could not fetch flow from {url}: {reason}")]
+    Fetch { url: String, reason: String },
+
+    /// Dependencies loop back on themselves; `path` walks the loop in
+    /// execution order, starting and ending at `step_id`
+    ///
+    /// `remove` is a `(dependency, step)` edge that breaks it: `step`'s
+    /// dependency on a step declared after it.
+    #[error(
+        "Flow contains a cycle: {}; consider removing dependency '{}' -> '{}'",
+        path.join(" -> "),
+        remove.0,
+        remove.1
+    )]
+    Cycle {
+        step_id: String,
+        path: Vec<String>,
+        remove: (String, String),
+    },
+
+    /// Two steps share the same ID
+    #[error("Duplicate step ID '{id}'")]
+    DuplicateId { id: String },
+
+    /// A step depends on a step that doesn't exist
+    #[error("Step '{step}' depends on unknown step '{dep}'")]
+    UnknownDependency { step: String, dep: String },
+
+    /// A step lists itself in `depends_on`
+    #[error("Step '{step}' depends on itself")]
+    SelfDependency { step: String },
+
+    /// A step ID was referenced (e.g. by `--from`) but isn't in the flow
+    #[error("Unknown step '{id}'")]
+    UnknownStep { id: String },
+
+    /// A label was selected (e.g. `--select-tag`) that no step carries
+    #[error("No step is labeled '{label}'")]
+    UnknownLabel { label: String },
+
+    /// A step's `config_file` couldn't be read or parsed
+    #[error("Step '{step}': could not load config file {path:?}: {reason}")]
+    ConfigFile {
+        step: String,
+        path: PathBuf,
+        reason: String,
+    },
+
+    /// A value was given for an input the flow doesn't declare
+    #[error("Flow does not declare an input named '{name}'")]
+    UnknownInput { name: String },
+
+    /// A declared input has no default and no value was given
+    #[error("Missing value for input '{name}'")]
+    MissingInput { name: String },
+
+    /// A profile was selected (`--profile`) that the flow doesn't define
+    #[error(
+        "Flow '{flow}' has no profile '{profile}' (defined: {})",
+        if .defined.is_empty() { "none".to_string() } else { .defined.join(", ") }
+    )]
+    UnknownProfile {
+        flow: String,
+        profile: String,
+        defined: Vec<String>,
+    },
+
+    /// A profile sets an input the flow doesn't declare
+    #[error("Profile '{profile}' sets input '{name}', which the flow does not declare")]
+    UnknownProfileInput { profile: String, name: String },
+
+    /// A file with a `flows:` list names the same flow twice
+    #[error("Duplicate flow ID '{id}'")]
+    DuplicateFlowId { id: String },
+
+    /// A flow was requested by ID (e.g. `--flow`) but the file doesn't have it
+    #[error("Unknown flow '{id}'")]
+    UnknownFlow { id: String },
+
+    /// The file holds several flows and none was picked
+    #[error("File contains several flows, pick one of: {}", .ids.join(", "))]
+    AmbiguousFlow { ids: Vec<String> },
+
+    /// A `flows:` list with nothing in it
+    #[error("File contains no flows")]
+    NoFlows,
+
+    /// The flow has no steps (only an error when asked for, see
+    /// `validate_not_empty`)
+    #[error("Flow '{id}' has no steps")]
+    EmptyFlow { id: String },
+
+    /// The flow is bigger than the caller allows (see `validate_step_count`)
+    #[error("Flow '{id}' has {steps} steps, more than the limit of {max}")]
+    TooManySteps { id: String, steps: usize, max: usize },
+
+    /// The flow declares a format `version` newer than this build understands
+    #[error("Flow '{flow}' uses format version {version}, but only up to {FLOW_FORMAT_VERSION} is supported")]
+    UnsupportedVersion { flow: String, version: u32 },
+
+    /// Steps whose kind has no registered handler (see `validate_kinds`)
+    #[error("Steps with unregistered kinds: {}", .0.join(", "))]
+    UnknownKinds(Vec<String>),
+
+    /// Step configs that don't match their kind's schema (see `validate_configs`)
+    #[error("Invalid step config: {}", .0.join("; "))]
+    InvalidConfig(Vec<String>),
+
+    /// Placeholders that can never resolve (see `validate_templates`)
+    #[error("Invalid template references: {}", .0.join("; "))]
+    InvalidTemplates(Vec<String>),
+}
+
+/// Newest flow format `version` this build understands
+pub const FLOW_FORMAT_VERSION: u32 = 1;
+
+/// How long fetching a flow from a URL may take (see `load_flows_from_url`)
+const FLOW_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest flow fetched from a URL, in bytes
+const MAX_FLOW_URL_BYTES: usize = 1024 * 1024;
+
 /// Represents a complete agent flow, as loaded from a YAML definition
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct Flow {
     /// Unique identifier for the flow (used for scheduling, runs, etc.)
     pub id: String,
 
+    /// Flow format version (see `FLOW_FORMAT_VERSION`); unset means 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<u32>,
+
     /// Optional human-readable description (not used functionally)
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub description: Option<String>,
 
     /// The list of steps that make up this flow
     pub nodes: Vec<Step>,
+
+    /// Optional mutual-exclusion group: runs of flows sharing a group never
+    /// execute at the same time within one process (see `RunOptions::on_conflict`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency_group: Option<String>,
+
+    /// Parameters given at invocation (`--input name=value`), with their
+    /// defaults; `null` means the input is required. Steps reference them in
+    /// config as `{{ inputs.<name> }}`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, serde_yaml::Value>,
+
+    /// Per-kind caps on steps running at once, e.g. `{ db_upsert: 2 }`
+    /// (only matters with `RunOptions::max_parallel` above 1)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub concurrency: BTreeMap<String, usize>,
+
+    /// Per-kind rate caps, e.g. `{ http_get: { per_second: 5 } }`: each
+    /// handler call of a capped kind waits for its turn. Unlike `concurrency`
+    /// this bounds how often steps start, not how many run at once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rate_limits: BTreeMap<String, RateLimit>,
+
+    /// Base config per step kind, e.g. `{ http_get: { timeout_seconds: 30 } }`;
+    /// deep-merged under each step's own `config` (see `Flow::step_config`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defaults: BTreeMap<String, serde_yaml::Value>,
+
+    /// Named variants of `defaults` and `inputs` (e.g. `dev`, `prod`), one of
+    /// which may be picked per run (see `Flow::apply_profile`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, Profile>,
+
+    /// Optional webhook to tell when a run finishes (see `notify::notify_run`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notify: Option<NotifyConfig>,
+
+    /// When a run fails, undo the steps that succeeded by running their
+    /// `compensation`, dependents first (see `engine::roll_back`)
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub rollback_on_failure: bool,
+
+    /// Upper bound for a whole run of this flow, in seconds
+    /// (`RunOptions::flow_timeout_seconds` takes precedence). A run that
+    /// times out is always rolled back, `rollback_on_failure` or not.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+
+    /// Directory relative paths in this flow resolve against — the flow
+    /// file's directory, set at load time (`None`: the current directory)
+    #[serde(skip)]
+    pub base_dir: Option<PathBuf>,
+
+    /// The file this flow was loaded from, set at load time (`None` for
+    /// flows parsed from a string or fetched from a URL)
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+impl Flow {
+    /// Checks the flow's wiring without building a graph to run: step IDs
+    /// are unique, and no step depends on itself, on an unknown step, or
+    /// (transitively) on a step that depends on it
+    ///
+    /// Useful for flows put together in code; `build_step_graph` makes the
+    /// same checks.
+    pub fn validate(&self) -> Result<(), FlowError> {
+        connect_steps(self).map(|_| ())
+    }
+
+    /// Applies the profile called `name`: its `defaults` are deep-merged over
+    /// the flow's, kind by kind, and its `inputs` replace the flow's input
+    /// defaults. Fails if the flow has no such profile, or if the profile
+    /// sets an input the flow doesn't declare (the flow is left as it was).
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), FlowError> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            return Err(FlowError::UnknownProfile {
+                flow: self.id.clone(),
+                profile: name.to_string(),
+                defined: self.profiles.keys().cloned().collect(),
+            });
+        };
+        if let Some(undeclared) = profile.inputs.keys().find(|input| !self.inputs.contains_key(*input)) {
+            return Err(FlowError::UnknownProfileInput {
+                profile: name.to_string(),
+                name: undeclared.clone(),
+            });
+        }
+
+        for (kind, overlay) in profile.defaults {
+            let base = self.defaults.remove(&kind).unwrap_or_default();
+            self.defaults.insert(kind, merge_config(base, overlay));
+        }
+        self.inputs.extend(profile.inputs);
+        Ok(())
+    }
+
+    /// The config a step runs with: its kind's `defaults` with the step's
+    /// own `config` merged on top (step values win)
+    pub fn step_config(&self, step: &Step) -> serde_yaml::Value {
+        match self.defaults.get(&step.kind) {
+            Some(defaults) => merge_config(defaults.clone(), step.config.clone()),
+            None => step.config.clone(),
+        }
+    }
+}
+
+/// One entry of `Flow::profiles`
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Profile {
+    /// Base config per step kind, merged over the flow's `defaults`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defaults: BTreeMap<String, serde_yaml::Value>,
+
+    /// Input defaults that replace the flow's (only for declared inputs)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub inputs: BTreeMap<String, serde_yaml::Value>,
+}
+
+/// A file holding several related flows under a top-level `flows:` list
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct FlowFile {
+    pub flows: Vec<Flow>,
 }
 
 /// A single step in a flow (represented as a node in the DAG)
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Step {
     /// Unique step ID within this flow
     pub id: String,
@@ -28,29 +303,214 @@ pub struct Step {
     /// Type of handler to invoke (e.g. "http_get", "db_upsert")
     pub kind: String,
 
-    /// Step IDs this one depends on (DAG edges)
-    #[serde(default)]
-    pub depends_on: Vec<String>,
+    /// Optional human-readable description, shown in reports (not used
+    /// functionally)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// Optional stage (e.g. `build`, `deploy`) grouping steps in reports;
+    /// doesn't affect scheduling (see `engine::stage_tallies`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+
+    /// Steps this one depends on (DAG edges): plain step IDs, or
+    /// `{ step: a, on: completion }` to run even if `a` didn't succeed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<Dependency>,
 
     /// Arbitrary config passed to the step at runtime
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "serde_yaml::Value::is_null")]
     pub config: serde_yaml::Value,
 
+    /// Optional YAML file (relative to the flow file) merged under `config`
+    /// at load time; keys set inline in `config` win
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config_file: Option<String>,
+
     /// Optional retry configuration
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub retry: Option<RetryPolicy>,
 
     /// Optional idempotency key to enable safe retries
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub idempotency_key: Option<String>,
 
+    /// Let the step cache (`engine::RunOptions::cache_dir`) reuse this
+    /// step's output across runs. Steps with an `idempotency_key` opt in
+    /// implicitly; others always run, since skipping them would skip their
+    /// side effects.
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub cache: bool,
+
     /// Optional compensation logic (for rollback flows)
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub compensation: Option<Compensation>,
+
+    /// Optional per-step timeout; the step fails if its handler takes longer
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_seconds: Option<u64>,
+
+    /// Optional condition, e.g. `steps.check.output == "ready"`; when it's
+    /// false the step is skipped (see `condition::evaluate`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
+
+    /// Run `compensation` immediately if this step times out, to undo a
+    /// side effect the handler may have left half-applied
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub compensate_on_timeout: bool,
+
+    /// Rough expected duration, used by `critical_path` (unset counts as 0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub estimated_seconds: Option<u64>,
+
+    /// Set to `false` to switch the step off without touching the flow's
+    /// wiring. A disabled step is recorded as `Skipped("disabled")` but counts
+    /// as satisfied, so its dependents still run (it produces no output).
+    #[serde(default = "default_enabled", skip_serializing_if = "is_true")]
+    pub enabled: bool,
+
+    /// Free-form tags (e.g. `[critical, nightly]`) for selecting a subset of
+    /// steps to run (see `StepSelection::tags`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<String>,
+
+    /// Run the step once per item of a list, e.g. `"{{ inputs.files }}"`
+    /// (a step's output works too if it's a YAML/JSON list). Each run sees
+    /// its item as `{{ item }}` in config; see `engine::run_for_each`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub for_each: Option<String>,
+
+    /// Error-handling branch: steps whose failure triggers this one. The step
+    /// waits for them like `depends_on`, then runs only if at least one of
+    /// them failed, and is skipped otherwise (e.g. to notify or clean up).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub on_failure: Vec<String>,
+
+    /// When several steps are ready but there's no room to start them all,
+    /// higher priority starts first (ties go to the earlier-declared step)
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: i32,
+}
+
+impl Step {
+    /// IDs of every step this one waits for: `depends_on`, then `on_failure`
+    ///
+    /// Label dependencies only count once `build_step_graph` has expanded
+    /// them, so ask the graph's copy of the step.
+    pub fn upstream_ids(&self) -> impl Iterator<Item = &String> {
+        self.depends_on
+            .iter()
+            .filter_map(Dependency::step_id)
+            .chain(&self.on_failure)
+    }
+}
+
+/// One `depends_on` entry — which step, and when it counts as satisfied
+///
+/// In YAML either a plain step ID (`on: success`), `{ step, on }`, or
+/// `{ match_label, on }` for every step carrying a label.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(from = "DependencySpec", into = "DependencySpec")]
+pub struct Dependency {
+    /// What is depended on
+    pub target: DependencyTarget,
+
+    pub on: DependOn,
+}
+
+/// What a `Dependency` points at
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DependencyTarget {
+    /// The step with this ID
+    Step(String),
+    /// Every other step with this label; `build_step_graph` replaces the
+    /// entry with one per matching step
+    Label(String),
+}
+
+impl Dependency {
+    /// ID of the step depended on; `None` for a label, which only the
+    /// graph's copy of a step has expanded into step IDs
+    pub fn step_id(&self) -> Option<&String> {
+        match &self.target {
+            DependencyTarget::Step(id) => Some(id),
+            DependencyTarget::Label(_) => None,
+        }
+    }
+}
+
+impl From<&str> for Dependency {
+    fn from(step: &str) -> Self {
+        step.to_string().into()
+    }
+}
+
+impl From<String> for Dependency {
+    fn from(step: String) -> Self {
+        Dependency {
+            target: DependencyTarget::Step(step),
+            on: DependOn::Success,
+        }
+    }
+}
+
+/// When a dependency counts as satisfied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependOn {
+    /// Only once the step succeeded (or is disabled) — failures and skips block
+    #[default]
+    Success,
+    /// Once the step finished, however it went (e.g. for cleanup steps)
+    Completion,
+}
+
+/// How a `Dependency` is written in YAML: the short form for the default
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum DependencySpec {
+    Step(String),
+    Detailed {
+        step: String,
+        #[serde(default)]
+        on: DependOn,
+    },
+    Label {
+        match_label: String,
+        #[serde(default)]
+        on: DependOn,
+    },
+}
+
+impl From<DependencySpec> for Dependency {
+    fn from(spec: DependencySpec) -> Self {
+        match spec {
+            DependencySpec::Step(step) => step.into(),
+            DependencySpec::Detailed { step, on } => Dependency {
+                target: DependencyTarget::Step(step),
+                on,
+            },
+            DependencySpec::Label { match_label, on } => Dependency {
+                target: DependencyTarget::Label(match_label),
+                on,
+            },
+        }
+    }
+}
+
+impl From<Dependency> for DependencySpec {
+    fn from(dep: Dependency) -> Self {
+        match (dep.target, dep.on) {
+            (DependencyTarget::Label(match_label), on) => DependencySpec::Label { match_label, on },
+            (DependencyTarget::Step(step), DependOn::Success) => DependencySpec::Step(step),
+            (DependencyTarget::Step(step), on) => DependencySpec::Detailed { step, on },
+        }
+    }
 }
 
 /// Optional retry policy per step (attempts, backoff, etc.)
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct RetryPolicy {
     /// Maximum number of attempts (default = 1)
     pub max_attempts: usize,
@@ -58,17 +518,35 @@ pub struct RetryPolicy {
     /// Backoff between attempts, in seconds
     #[serde(default = "default_backoff")]
     pub backoff_seconds: u64,
+
+    /// Only retry failures whose message contains one of these substrings
+    /// (e.g. "timed out", "503"); `None` retries on any retriable failure.
+    /// Errors the handler marks as not retriable are never retried.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_on: Option<Vec<String>>,
+}
+
+/// One kind's entry in `Flow::rate_limits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RateLimit {
+    /// Handler calls allowed per second, spaced evenly (no bursts)
+    pub per_second: NonZeroU32,
 }
 
 /// Compensation step definition (used to rollback if needed)
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Compensation {
     /// Handler kind to invoke during compensation
     pub kind: String,
 
     /// Config passed to the compensating handler
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "serde_yaml::Value::is_null")]
     pub config: serde_yaml::Value,
+
+    /// Optional retries for the compensation itself (the step's own `retry`
+    /// doesn't apply to it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
 }
 
 /// Fallback backoff delay if none specified in RetryPolicy
@@ -76,6 +554,107 @@ fn default_backoff() -> u64 {
     5
 }
 
+/// Lets serialization omit flags that are off (the default)
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Lets serialization omit a priority left at the default
+fn is_zero(value: &i32) -> bool {
+    *value == 0
+}
+
+/// Steps are enabled unless they say otherwise
+fn default_enabled() -> bool {
+    true
+}
+
+/// Lets serialization omit flags that are on (the default)
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// Which dependency to drop to break the cycle along `path`, as
+/// `(dependency, step)`
+///
+/// Candidates are the cycle's back-edges — a step depending on one declared
+/// after it (every cycle has one). The earliest-declared such step wins,
+/// preferring an edge whose removal leaves no cycle at all in the graph.
+fn suggest_removal(graph: &StepGraph, path: &[String]) -> (String, String) {
+    let position = |id: &str| find_step(graph, id).map_or(usize::MAX, NodeIndex::index);
+
+    let mut back_edges: Vec<(&String, &String)> = path
+        .windows(2)
+        .map(|pair| (&pair[0], &pair[1]))
+        .filter(|(dep, step)| position(dep) > position(step))
+        .collect();
+    back_edges.sort_by_key(|(_, step)| position(step));
+
+    let breaks_all = |(dep, step): &(&String, &String)| {
+        let mut pruned = graph.clone();
+        let edge = find_step(graph, dep).zip(find_step(graph, step)).and_then(|(from, to)| pruned.find_edge(from, to));
+        if let Some(edge) = edge {
+            pruned.remove_edge(edge);
+        }
+        !petgraph::algo::is_cyclic_directed(&pruned)
+    };
+    let (dep, step) = back_edges
+        .iter()
+        .find(|edge| breaks_all(edge))
+        .or(back_edges.first())
+        .copied()
+        .unwrap_or((&path[0], &path[1]));
+    (dep.clone(), step.clone())
+}
+
+/// Step IDs around one cycle through `node`, e.g. `[a, b, c, a]`
+///
+/// The walk starts at the earliest-declared step of `node`'s strongly
+/// connected component and takes the shortest way back to it, so the
+/// reported cycle doesn't depend on where the cycle was first noticed.
+fn cycle_path(graph: &StepGraph, node: NodeIndex) -> Vec<String> {
+    let component: HashSet<NodeIndex> = tarjan_scc(graph)
+        .into_iter()
+        .find(|component| component.contains(&node))
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    let start = component.iter().copied().min().unwrap_or(node);
+
+    // Breadth-first within the component until we're back at `start`
+    let mut came_from: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    let mut queue = VecDeque::from([start]);
+    'search: while let Some(current) = queue.pop_front() {
+        let mut next: Vec<NodeIndex> = graph
+            .neighbors_directed(current, Direction::Outgoing)
+            .filter(|idx| component.contains(idx))
+            .collect();
+        next.sort();
+        for idx in next {
+            if came_from.contains_key(&idx) {
+                continue;
+            }
+            came_from.insert(idx, current);
+            if idx == start {
+                break 'search;
+            }
+            queue.push_back(idx);
+        }
+    }
+
+    let mut path = vec![graph[start].step.id.clone()];
+    let mut current = start;
+    while let Some(&previous) = came_from.get(&current) {
+        path.push(graph[previous].step.id.clone());
+        current = previous;
+        if current == start {
+            break;
+        }
+    }
+    path.reverse();
+    path
+}
+
 /// Internal graph node type — wraps a Step
 #[derive(Debug, Clone)]
 pub struct StepNode {
@@ -88,75 +667,1040 @@ pub type StepGraph = Graph<StepNode, ()>;
 /// Public function to load a flow definition from disk
 /// - Parses YAML into typed `Flow`
 /// - Builds a validated, acyclic execution DAG from the flow
-pub fn load_flow(path: &Path) -> anyhow::Result<(Flow, StepGraph)> {
+///
+/// Files with several flows (`flows:`) only load if they hold exactly one;
+/// use `load_flows` + `select_flow` to pick among them.
+pub fn load_flow(path: &Path) -> Result<(Flow, StepGraph), FlowError> {
+    select_flow(load_flows(path)?, None)
+}
+
+/// Like `load_flow`, but a flow without steps — probably a mistake, which
+/// `load_flow` only warns about — is an error
+pub fn load_flow_strict(path: &Path) -> Result<(Flow, StepGraph), FlowError> {
+    let (flow, graph) = load_flow(path)?;
+    validate_not_empty(&flow)?;
+    Ok((flow, graph))
+}
+
+/// Loads every flow in a file — a single flow or a `flows:` list (`FlowFile`)
+pub fn load_flows(path: &Path) -> Result<Vec<(Flow, StepGraph)>, FlowError> {
     let yaml = std::fs::read_to_string(path)?;
-    let flow: Flow = serde_yaml::from_str(&yaml)?;
-    let dag = build_step_graph(&flow)?;
-    Ok((flow, dag))
+    let mut flows = load_flows_from_str(&yaml, path.parent().unwrap_or(Path::new(".")))?;
+    for (flow, _) in &mut flows {
+        flow.file = Some(path.to_path_buf());
+    }
+    Ok(flows)
+}
+
+/// True if a flow argument is an `http://` or `https://` URL rather than a path
+pub fn is_flow_url(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Like `load_flows`, but fetches the YAML from an HTTP(S) URL; relative
+/// `config_file` paths resolve against the current directory. Gives up
+/// after `FLOW_FETCH_TIMEOUT` or once the body exceeds `MAX_FLOW_URL_BYTES`.
+pub async fn load_flows_from_url(url: &str) -> Result<Vec<(Flow, StepGraph)>, FlowError> {
+    let fetch_error = |reason: String| FlowError::Fetch { url: url.to_string(), reason };
+
+    let client = reqwest::Client::builder()
+        .timeout(FLOW_FETCH_TIMEOUT)
+        .build()
+        .map_err(|err| fetch_error(err.to_string()))?;
+    let mut response = client.get(url).send().await.map_err(|err| fetch_error(err.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(fetch_error(format!("server answered {status}")));
+    }
+
+    let too_large = || fetch_error(format!("flow is larger than {MAX_FLOW_URL_BYTES} bytes"));
+    if response.content_length().is_some_and(|len| len > MAX_FLOW_URL_BYTES as u64) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(|err| fetch_error(err.to_string()))? {
+        body.extend_from_slice(&chunk);
+        if body.len() > MAX_FLOW_URL_BYTES {
+            return Err(too_large());
+        }
+    }
+    let yaml = String::from_utf8(body).map_err(|err| fetch_error(err.to_string()))?;
+
+    load_flows_from_str(&yaml, Path::new("."))
+}
+
+/// Like `load_flows`, but for YAML that's already in memory; relative
+/// `config_file` paths resolve against `base_dir`
+pub fn load_flows_from_str(yaml: &str, base_dir: &Path) -> Result<Vec<(Flow, StepGraph)>, FlowError> {
+    let flows = parse_flows(yaml)?;
+
+    let mut seen = HashSet::new();
+    let mut loaded = Vec::with_capacity(flows.len());
+    for mut flow in flows {
+        if !seen.insert(flow.id.clone()) {
+            return Err(FlowError::DuplicateFlowId { id: flow.id });
+        }
+        check_version(&flow)?;
+        if flow.nodes.is_empty() {
+            warn!("⚠️ Flow '{}' has no steps; running it will do nothing", flow.id);
+        }
+        flow.base_dir = Some(base_dir.to_path_buf());
+        resolve_config_files(&mut flow, base_dir)?;
+        let dag = build_step_graph(&flow)?;
+        loaded.push((flow, dag));
+    }
+
+    Ok(loaded)
+}
+
+/// Rejects flows written for a newer format; older or unversioned ones load
+/// with a warning (they're read as version 1)
+fn check_version(flow: &Flow) -> Result<(), FlowError> {
+    match flow.version {
+        Some(version) if version > FLOW_FORMAT_VERSION => {
+            Err(FlowError::UnsupportedVersion { flow: flow.id.clone(), version })
+        }
+        Some(FLOW_FORMAT_VERSION) => Ok(()),
+        Some(version) => {
+            warn!("⚠️ Flow '{}' has unknown format version {version}; reading it as version 1", flow.id);
+            Ok(())
+        }
+        None => {
+            warn!("⚠️ Flow '{}' has no `version`; assuming version 1", flow.id);
+            Ok(())
+        }
+    }
+}
+
+/// Like `load_flow`, but for flow YAML (or JSON) that isn't in a file, e.g.
+/// an HTTP request body. Relative `config_file` paths resolve against the
+/// current directory.
+pub fn load_flow_from_str(yaml: &str) -> Result<(Flow, StepGraph), FlowError> {
+    select_flow(load_flows_from_str(yaml, Path::new("."))?, None)
+}
+
+/// Picks one flow out of `load_flows`: the one named `id`, or the only one
+pub fn select_flow(
+    flows: Vec<(Flow, StepGraph)>,
+    id: Option<&str>,
+) -> Result<(Flow, StepGraph), FlowError> {
+    if let Some(id) = id {
+        return flows
+            .into_iter()
+            .find(|(flow, _)| flow.id == id)
+            .ok_or_else(|| FlowError::UnknownFlow { id: id.to_string() });
+    }
+
+    match flows.len() {
+        0 => Err(FlowError::NoFlows),
+        1 => Ok(flows.into_iter().next().expect("length checked")),
+        _ => Err(FlowError::AmbiguousFlow {
+            ids: flows.into_iter().map(|(flow, _)| flow.id).collect(),
+        }),
+    }
+}
+
+/// Parses flow YAML that is either a single `Flow` or a `FlowFile`
+///
+/// Goes through a `Value` first so errors point at the right schema instead
+/// of an untagged "did not match any variant".
+fn parse_flows(yaml: &str) -> Result<Vec<Flow>, FlowError> {
+    let value: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+    if value.get("flows").is_some() {
+        let file: FlowFile = serde_yaml::from_value(value)?;
+        Ok(file.flows)
+    } else {
+        Ok(vec![serde_yaml::from_value(value)?])
+    }
+}
+
+/// Re-serializes a flow definition in canonical form, so equivalent flows
+/// produce identical text:
+/// - steps in topological order, ties broken by step ID
+/// - `depends_on` and `on_failure` lists sorted (and deduplicated)
+/// - config mapping keys sorted, recursively
+/// - fields left at their defaults omitted
+///
+/// `config_file` references are kept as-is rather than inlined.
+///
+/// A `flows:` file is normalized flow by flow, keeping the flows' order.
+pub fn normalize_flow(yaml: &str) -> Result<String, FlowError> {
+    let value: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+    if value.get("flows").is_none() {
+        let flow = canonical_flow(serde_yaml::from_value(value)?)?;
+        return Ok(serde_yaml::to_string(&flow)?);
+    }
+
+    let file: FlowFile = serde_yaml::from_value(value)?;
+    let flows = file
+        .flows
+        .into_iter()
+        .map(canonical_flow)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(serde_yaml::to_string(&FlowFile { flows })?)
+}
+
+/// Rearranges a single flow into the canonical form of `normalize_flow`
+fn canonical_flow(mut flow: Flow) -> Result<Flow, FlowError> {
+    // Only a valid DAG has a topological order to speak of; its steps have
+    // their label dependencies expanded
+    let graph = build_step_graph(&flow)?;
+    let upstream: HashMap<&str, Vec<&String>> = graph
+        .node_weights()
+        .map(|node| (node.step.id.as_str(), node.step.upstream_ids().collect()))
+        .collect();
+
+    let mut remaining = std::mem::take(&mut flow.nodes);
+    let mut placed: HashSet<String> = HashSet::new();
+
+    while !remaining.is_empty() {
+        // The smallest ID among steps whose dependencies are all placed
+        let next = remaining
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| upstream[step.id.as_str()].iter().all(|dep| placed.contains(*dep)))
+            .min_by(|(_, a), (_, b)| a.id.cmp(&b.id))
+            .map(|(idx, _)| idx)
+            .expect("validated flow is acyclic");
+
+        let mut step = remaining.swap_remove(next);
+        step.depends_on.sort();
+        step.depends_on.dedup();
+        step.on_failure.sort();
+        step.on_failure.dedup();
+        step.config = sort_config_keys(std::mem::take(&mut step.config));
+        if let Some(compensation) = &mut step.compensation {
+            compensation.config = sort_config_keys(std::mem::take(&mut compensation.config));
+        }
+
+        placed.insert(step.id.clone());
+        flow.nodes.push(step);
+    }
+
+    for defaults in flow.defaults.values_mut() {
+        *defaults = sort_config_keys(std::mem::take(defaults));
+    }
+    for profile in flow.profiles.values_mut() {
+        for defaults in profile.defaults.values_mut() {
+            *defaults = sort_config_keys(std::mem::take(defaults));
+        }
+    }
+
+    Ok(flow)
+}
+
+/// Sorts mapping keys at every level of a config (sequence order is kept)
+fn sort_config_keys(value: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
+
+    match value {
+        Value::Mapping(mapping) => {
+            let mut entries: Vec<(Value, Value)> = mapping
+                .into_iter()
+                .map(|(key, value)| (key, sort_config_keys(value)))
+                .collect();
+            entries.sort_by_cached_key(|(key, _)| match key.as_str() {
+                Some(key) => key.to_string(),
+                None => serde_yaml::to_string(key).unwrap_or_default(),
+            });
+            Value::Mapping(entries.into_iter().collect())
+        }
+        Value::Sequence(items) => Value::Sequence(items.into_iter().map(sort_config_keys).collect()),
+        other => other,
+    }
+}
+
+/// Merges each step's `config_file` (resolved against `base_dir`) under its
+/// inline `config`
+fn resolve_config_files(flow: &mut Flow, base_dir: &Path) -> Result<(), FlowError> {
+    for step in &mut flow.nodes {
+        let Some(file) = &step.config_file else {
+            continue;
+        };
+
+        let path = base_dir.join(file);
+        let config_error = |reason: String| FlowError::ConfigFile {
+            step: step.id.clone(),
+            path: path.clone(),
+            reason,
+        };
+
+        let contents = std::fs::read_to_string(&path).map_err(|err| config_error(err.to_string()))?;
+        let from_file: serde_yaml::Value =
+            serde_yaml::from_str(&contents).map_err(|err| config_error(err.to_string()))?;
+
+        debug!("📎 Step '{}' loads config from {:?}", step.id, path);
+        step.config = merge_config(from_file, std::mem::take(&mut step.config));
+    }
+
+    Ok(())
+}
+
+/// Deep-merges two configs: mappings are merged key by key (recursively),
+/// anything else in `overlay` replaces `base`, and a null overlay keeps `base`
+pub fn merge_config(base: serde_yaml::Value, overlay: serde_yaml::Value) -> serde_yaml::Value {
+    use serde_yaml::Value;
+
+    match (base, overlay) {
+        (Value::Mapping(mut base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                let merged = match base.remove(&key) {
+                    Some(existing) => merge_config(existing, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Mapping(base)
+        }
+        (base, Value::Null) => base,
+        (_, overlay) => overlay,
+    }
+}
+
+/// Converts the flow into an executable DAG of `StepNode`s, after the
+/// checks of `Flow::validate`
+///
+/// Exposed for tests, embedders, and scheduler usage.
+pub fn build_step_graph(flow: &Flow) -> Result<StepGraph, FlowError> {
+    let graph = connect_steps(flow)?;
+
+    debug!(
+        "✅ Loaded flow '{}' with {} steps",
+        flow.id,
+        graph.node_count()
+    );
+
+    Ok(graph)
 }
 
-/// Converts the flow into an executable DAG of `StepNode`s
+/// The DAG behind `build_step_graph` and `Flow::validate`
 /// - Verifies node uniqueness
-/// - Connects dependencies
+/// - Expands label dependencies (see `expand_label_dependencies`)
+/// - Connects dependencies (rejecting self and unknown ones)
 /// - Detects and rejects cycles
-///
-/// This function is exposed internally for tests and scheduler usage.
-pub(crate) fn build_step_graph(flow: &Flow) -> anyhow::Result<StepGraph> {
+fn connect_steps(flow: &Flow) -> Result<StepGraph, FlowError> {
     let mut graph = StepGraph::new();
     let mut node_indices: HashMap<String, NodeIndex> = HashMap::new();
 
     // Insert each step as a node
     for step in &flow.nodes {
         let index = graph.add_node(StepNode { step: step.clone() });
-        node_indices.insert(step.id.clone(), index);
+        if node_indices.insert(step.id.clone(), index).is_some() {
+            return Err(FlowError::DuplicateId { id: step.id.clone() });
+        }
+    }
+
+    // Now that every step is known, labels can be resolved to steps
+    for node in graph.node_weights_mut() {
+        if node.step.depends_on.iter().any(|dep| dep.step_id().is_none()) {
+            node.step.depends_on = expand_label_dependencies(&node.step, &flow.nodes);
+        }
     }
 
     // Add edges for each declared dependency
-    for step in &flow.nodes {
-        let from_idx = node_indices.get(&step.id).unwrap();
+    let mut edges = Vec::new();
+    for from_idx in graph.node_indices() {
+        let step = &graph[from_idx].step;
+
+        for dep in step.upstream_ids() {
+            if dep == &step.id {
+                return Err(FlowError::SelfDependency { step: step.id.clone() });
+            }
 
-        for dep in &step.depends_on {
             match node_indices.get(dep) {
-                Some(dep_idx) => {
-                    graph.add_edge(*dep_idx, *from_idx, ());
-                }
+                Some(dep_idx) => edges.push((*dep_idx, from_idx)),
                 None => {
-                    // Log missing dependency but don't panic — will likely cause step to block
-                    warn!("⚠ Step '{}' depends on unknown step '{}'", step.id, dep);
+                    return Err(FlowError::UnknownDependency {
+                        step: step.id.clone(),
+                        dep: dep.clone(),
+                    });
                 }
             }
         }
     }
+    for (dep_idx, from_idx) in edges {
+        graph.add_edge(dep_idx, from_idx, ());
+    }
 
     // Validate DAG is acyclic (required for safe topological execution)
     if let Err(cycle) = petgraph::algo::toposort(&graph, None) {
-        return Err(anyhow::anyhow!(
-            "Flow '{}' contains a cycle at step '{}'",
-            flow.id,
-            graph[cycle.node_id()].step.id
-        ));
+        let path = cycle_path(&graph, cycle.node_id());
+        return Err(FlowError::Cycle {
+            step_id: path[0].clone(),
+            remove: suggest_removal(&graph, &path),
+            path,
+        });
     }
 
-    debug!(
-        "✅ Loaded flow '{}' with {} steps",
-        flow.id,
-        graph.node_count()
-    );
-
     Ok(graph)
 }
 
+/// A step's `depends_on` with each label entry replaced by one entry
+/// (same `on`) per step carrying that label, in declaration order. The step
+/// itself is never included, and a step already listed isn't listed twice.
+fn expand_label_dependencies(step: &Step, steps: &[Step]) -> Vec<Dependency> {
+    let mut expanded: Vec<Dependency> = Vec::new();
+    for dep in &step.depends_on {
+        let DependencyTarget::Label(label) = &dep.target else {
+            if !expanded.contains(dep) {
+                expanded.push(dep.clone());
+            }
+            continue;
+        };
+
+        let matching: Vec<&Step> = steps
+            .iter()
+            .filter(|other| other.id != step.id && other.labels.contains(label))
+            .collect();
+        if matching.is_empty() {
+            warn!("⚠️ Step '{}' depends on label '{label}', but no other step has it", step.id);
+        }
+        for other in matching {
+            let dep = Dependency {
+                target: DependencyTarget::Step(other.id.clone()),
+                on: dep.on,
+            };
+            if !expanded.contains(&dep) {
+                expanded.push(dep);
+            }
+        }
+    }
+    expanded
+}
+
+/// Finds the graph node for a step ID
+pub fn find_step(graph: &StepGraph, step_id: &str) -> Option<NodeIndex> {
+    graph.node_indices().find(|idx| graph[*idx].step.id == step_id)
+}
+
+/// IDs of every step that `step_id` transitively depends on (excluding itself)
+pub fn ancestors(graph: &StepGraph, step_id: &str) -> Result<HashSet<String>, FlowError> {
+    let start = find_step(graph, step_id).ok_or_else(|| FlowError::UnknownStep { id: step_id.into() })?;
+    Ok(reachable(graph, start, Direction::Incoming))
+}
+
+/// IDs of every step that transitively depends on `step_id` (excluding itself)
+pub fn descendants(graph: &StepGraph, step_id: &str) -> Result<HashSet<String>, FlowError> {
+    let start = find_step(graph, step_id).ok_or_else(|| FlowError::UnknownStep { id: step_id.into() })?;
+    Ok(reachable(graph, start, Direction::Outgoing))
+}
+
+/// Walks edges from `start` in one direction, collecting step IDs
+fn reachable(graph: &StepGraph, start: NodeIndex, direction: Direction) -> HashSet<String> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![start];
+
+    while let Some(idx) = stack.pop() {
+        for next in graph.neighbors_directed(idx, direction) {
+            if seen.insert(graph[next].step.id.clone()) {
+                stack.push(next);
+            }
+        }
+    }
+
+    seen
+}
+
+/// IDs of steps cut off from the rest of the flow (in declaration order)
+///
+/// Heuristic: the largest weakly connected component (ties go to the one
+/// holding the first-declared step) is the flow proper, and every step
+/// outside it is reported. Several roots feeding into that component are
+/// ordinary parallel starts and aren't flagged. If no component has more
+/// than one step (e.g. a flow of independent one-off steps), there's no
+/// "main" part to be cut off from, so nothing is reported.
+pub fn unreachable_steps(graph: &StepGraph) -> Vec<String> {
+    let mut components = UnionFind::new(graph.node_count());
+    for edge in graph.edge_indices() {
+        let (from, to) = graph.edge_endpoints(edge).expect("edge from this graph");
+        components.union(from.index(), to.index());
+    }
+
+    let labels = components.into_labeling();
+    let mut sizes: HashMap<usize, usize> = HashMap::new();
+    for label in &labels {
+        *sizes.entry(*label).or_default() += 1;
+    }
+
+    // `max_by_key` keeps the last maximum, so walk in reverse to favor early steps
+    let Some(main) = labels.iter().rev().copied().max_by_key(|label| sizes[label]) else {
+        return Vec::new();
+    };
+    if sizes[&main] < 2 {
+        return Vec::new();
+    }
+
+    graph
+        .node_indices()
+        .filter(|idx| labels[idx.index()] != main)
+        .map(|idx| graph[idx].step.id.clone())
+        .collect()
+}
+
+/// Returns a copy of the graph containing only the steps in `keep`
+///
+/// Dependencies on pruned steps are dropped from the kept steps, so they
+/// count as satisfied rather than blocking (e.g. `--from` mid-flow).
+/// Dependencies on steps that never existed are left alone.
+pub fn prune_graph(graph: &StepGraph, keep: &HashSet<String>) -> StepGraph {
+    let known: HashSet<&str> = graph.node_weights().map(|node| node.step.id.as_str()).collect();
+
+    graph.filter_map(
+        |_, node| {
+            keep.contains(&node.step.id).then(|| {
+                let mut node = node.clone();
+                node.step
+                    .depends_on
+                    .retain(|dep| dep.step_id().is_some_and(|id| keep.contains(id) || !known.contains(id.as_str())));
+                node
+            })
+        },
+        |_, _| Some(()),
+    )
+}
+
+/// The chain of steps with the largest total `estimated_seconds`, and that total
+///
+/// This is the lower bound on the run's duration however parallel it goes.
+/// Steps without an estimate count as 0. Ties go to the chain with more
+/// steps, then to the step declared first in `flow.nodes`. An empty graph
+/// gives an empty path.
+pub fn critical_path(flow: &Flow, graph: &StepGraph) -> (Vec<String>, u64) {
+    let position: HashMap<&str, usize> = flow
+        .nodes
+        .iter()
+        .enumerate()
+        .map(|(idx, step)| (step.id.as_str(), idx))
+        .collect();
+    let declared = |idx: NodeIndex| position.get(graph[idx].step.id.as_str()).copied().unwrap_or(usize::MAX);
+
+    let Ok(sorted) = petgraph::algo::toposort(graph, None) else {
+        return (Vec::new(), 0);
+    };
+
+    // Longest (total, step count) ending at each step, and the dependency it came through
+    let mut best: HashMap<NodeIndex, (u64, usize)> = HashMap::new();
+    let mut via: HashMap<NodeIndex, NodeIndex> = HashMap::new();
+    for idx in &sorted {
+        let best_dep = graph
+            .neighbors_directed(*idx, Direction::Incoming)
+            .max_by_key(|dep| (best[dep], std::cmp::Reverse(declared(*dep))));
+        let (upstream, steps) = best_dep.map(|dep| best[&dep]).unwrap_or((0, 0));
+        if let Some(dep) = best_dep {
+            via.insert(*idx, dep);
+        }
+        best.insert(*idx, (upstream + graph[*idx].step.estimated_seconds.unwrap_or(0), steps + 1));
+    }
+
+    let Some(mut last) = sorted
+        .iter()
+        .copied()
+        .max_by_key(|idx| (best[idx], std::cmp::Reverse(declared(*idx))))
+    else {
+        return (Vec::new(), 0);
+    };
+
+    let duration = best[&last].0;
+    let mut path = vec![graph[last].step.id.clone()];
+    while let Some(dep) = via.get(&last) {
+        path.push(graph[*dep].step.id.clone());
+        last = *dep;
+    }
+    path.reverse();
+
+    (path, duration)
+}
+
+/// Structural numbers about a flow's DAG (see `flow_stats`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FlowStats {
+    pub nodes: usize,
+    pub edges: usize,
+    /// Steps without dependencies
+    pub roots: usize,
+    /// Steps nothing depends on
+    pub leaves: usize,
+    /// Steps on the longest dependency chain (0 for an empty graph)
+    pub max_depth: usize,
+    /// Most steps depending directly on a single step
+    pub max_fan_out: usize,
+}
+
+/// Counts nodes, edges, roots, and leaves, and measures the longest chain
+/// and the widest fan-out of a step graph
+pub fn flow_stats(graph: &StepGraph) -> FlowStats {
+    let count = |idx: NodeIndex, direction| graph.neighbors_directed(idx, direction).count();
+
+    FlowStats {
+        nodes: graph.node_count(),
+        edges: graph.edge_count(),
+        roots: graph.node_indices().filter(|idx| count(*idx, Direction::Incoming) == 0).count(),
+        leaves: graph.node_indices().filter(|idx| count(*idx, Direction::Outgoing) == 0).count(),
+        // Steps on the longest chain, one more than its last step's depth
+        max_depth: step_depths(graph).values().max().map_or(0, |depth| depth + 1),
+        max_fan_out: graph.node_indices().map(|idx| count(idx, Direction::Outgoing)).max().unwrap_or(0),
+    }
+}
+
+/// Depth of each step in the DAG: 0 for roots, otherwise one more than its
+/// deepest dependency — i.e. the length of the longest chain of
+/// dependencies leading to it. Empty if the graph has a cycle.
+pub fn step_depths(graph: &StepGraph) -> HashMap<NodeIndex, usize> {
+    let mut depths: HashMap<NodeIndex, usize> = HashMap::new();
+    for idx in petgraph::algo::toposort(graph, None).unwrap_or_default() {
+        let depth = graph
+            .neighbors_directed(idx, Direction::Incoming)
+            .map(|dep| depths[&dep] + 1)
+            .max()
+            .unwrap_or(0);
+        depths.insert(idx, depth);
+    }
+    depths
+}
+
+/// Step IDs grouped by scheduling wave: roots first, then each step one
+/// level past its deepest dependency (within a level, sorted by ID, so the
+/// plan reads the same however the file is laid out). Steps in the same
+/// level can all run at once.
+pub fn execution_levels(graph: &StepGraph) -> Vec<Vec<String>> {
+    let depths = step_depths(graph);
+
+    let mut levels: Vec<Vec<String>> = Vec::new();
+    for idx in graph.node_indices() {
+        let Some(&at) = depths.get(&idx) else { continue };
+        if levels.len() <= at {
+            levels.resize_with(at + 1, Vec::new);
+        }
+        levels[at].push(graph[idx].step.id.clone());
+    }
+    for level in &mut levels {
+        level.sort();
+    }
+    levels
+}
+
+/// SHA-256 over the DAG's structure: step IDs with their kinds, and the
+/// dependency edges, each in sorted order
+///
+/// Anything else (config, retries, YAML key order, declaration order) is
+/// left out, so the hash only changes when the shape of the flow does.
+pub fn graph_hash(graph: &StepGraph) -> String {
+    let (steps, edges) = structure(graph);
+
+    let mut hasher = Sha256::new();
+    for (id, kind) in steps {
+        hasher.update(format!("step\0{id}\0{kind}\0"));
+    }
+    for (dep, dependent) in edges {
+        hasher.update(format!("edge\0{dep}\0{dependent}\0"));
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// One structural difference between two versions of a flow (see
+/// `diff_graphs`); displays as a line like `+ step 'x'` or `- edge a->b`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphChange {
+    AddedStep(String),
+    RemovedStep(String),
+    ChangedKind { step: String, old: String, new: String },
+    AddedEdge(String, String),
+    RemovedEdge(String, String),
+}
+
+impl std::fmt::Display for GraphChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphChange::AddedStep(id) => write!(f, "+ step '{id}'"),
+            GraphChange::RemovedStep(id) => write!(f, "- step '{id}'"),
+            GraphChange::ChangedKind { step, old, new } => write!(f, "~ step '{step}' kind {old} -> {new}"),
+            GraphChange::AddedEdge(dep, dependent) => write!(f, "+ edge {dep}->{dependent}"),
+            GraphChange::RemovedEdge(dep, dependent) => write!(f, "- edge {dep}->{dependent}"),
+        }
+    }
+}
+
+/// What changed in the DAG's structure from `old` to `new`: removed steps,
+/// then added steps and steps whose kind changed, then removed and added
+/// dependency edges, each sorted by step ID
+///
+/// Like `graph_hash`, it only looks at step IDs, kinds, and edges.
+pub fn diff_graphs(old: &StepGraph, new: &StepGraph) -> Vec<GraphChange> {
+    let (old_steps, old_edges) = structure(old);
+    let (new_steps, new_edges) = structure(new);
+    let mut changes = Vec::new();
+
+    for id in old_steps.keys().filter(|id| !new_steps.contains_key(*id)) {
+        changes.push(GraphChange::RemovedStep(id.to_string()));
+    }
+    for (id, kind) in &new_steps {
+        match old_steps.get(id) {
+            None => changes.push(GraphChange::AddedStep(id.to_string())),
+            Some(old_kind) if old_kind != kind => changes.push(GraphChange::ChangedKind {
+                step: id.to_string(),
+                old: old_kind.to_string(),
+                new: kind.to_string(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (dep, dependent) in old_edges.difference(&new_edges) {
+        changes.push(GraphChange::RemovedEdge(dep.to_string(), dependent.to_string()));
+    }
+    for (dep, dependent) in new_edges.difference(&old_edges) {
+        changes.push(GraphChange::AddedEdge(dep.to_string(), dependent.to_string()));
+    }
+    changes
+}
+
+/// The DAG's step kinds by ID and its (dependency, dependent) edges, sorted
+fn structure(graph: &StepGraph) -> (BTreeMap<&str, &str>, BTreeSet<(&str, &str)>) {
+    let steps = graph
+        .node_weights()
+        .map(|node| (node.step.id.as_str(), node.step.kind.as_str()))
+        .collect();
+    let edges = graph
+        .edge_indices()
+        .filter_map(|edge| graph.edge_endpoints(edge))
+        .map(|(dep, dependent)| (graph[dep].step.id.as_str(), graph[dependent].step.id.as_str()))
+        .collect();
+    (steps, edges)
+}
+
+/// What a rendered graph shows for each step (see `to_ascii_tree`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelMode {
+    /// The step ID, e.g. `fetch`
+    Id,
+    /// The step kind, e.g. `http_get`
+    Kind,
+    /// Both, e.g. `fetch (http_get)`
+    IdKind,
+    /// The step's `description`, or its ID if it has none
+    Description,
+}
+
+impl LabelMode {
+    /// The label for `step`
+    pub fn label(self, step: &Step) -> String {
+        match self {
+            LabelMode::Id => step.id.clone(),
+            LabelMode::Kind => step.kind.clone(),
+            LabelMode::IdKind => format!("{} ({})", step.id, step.kind),
+            LabelMode::Description => step.description.clone().unwrap_or_else(|| step.id.clone()),
+        }
+    }
+}
+
+impl std::str::FromStr for LabelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(LabelMode::Id),
+            "kind" => Ok(LabelMode::Kind),
+            "id_kind" => Ok(LabelMode::IdKind),
+            "description" => Ok(LabelMode::Description),
+            other => Err(format!("unknown label mode '{other}' (expected id, kind, id_kind or description)")),
+        }
+    }
+}
+
+/// The flow as an indented tree for the terminal: each root (a step with no
+/// dependencies) at the left edge, with the steps depending on it beneath,
+/// each shown as `labels` says
+///
+/// Roots and each step's dependents are listed by step ID, so the output
+/// is byte-for-byte the same for the same DAG. A step reachable along
+/// several paths (e.g. the bottom of a diamond) is drawn in full the first
+/// time only; later mentions are marked `(*)`.
+pub fn to_ascii_tree(graph: &StepGraph, labels: LabelMode) -> String {
+    let mut tree = AsciiTree {
+        graph,
+        labels,
+        shown: HashSet::new(),
+        repeated: false,
+        out: String::new(),
+    };
+    let mut roots: Vec<NodeIndex> = graph.externals(Direction::Incoming).collect();
+    roots.sort_by_key(|idx| &graph[*idx].step.id);
+    for root in roots {
+        tree.draw(root, "", None);
+    }
+    if tree.repeated {
+        tree.out.push_str("(*) already shown above\n");
+    }
+    tree.out
+}
+
+/// State for `to_ascii_tree`
+struct AsciiTree<'a> {
+    graph: &'a StepGraph,
+    labels: LabelMode,
+    shown: HashSet<NodeIndex>,
+    repeated: bool,
+    out: String,
+}
+
+impl AsciiTree<'_> {
+    /// Draws `idx` below `prefix`, then its dependents; `last` says whether
+    /// it's its parent's final child (`None` for a root)
+    fn draw(&mut self, idx: NodeIndex, prefix: &str, last: Option<bool>) {
+        let (connector, child_prefix) = match last {
+            None => ("", String::new()),
+            Some(true) => ("└── ", format!("{prefix}    ")),
+            Some(false) => ("├── ", format!("{prefix}│   ")),
+        };
+        self.out.push_str(&format!("{prefix}{connector}{}", self.labels.label(&self.graph[idx].step)));
+        if !self.shown.insert(idx) {
+            self.repeated = true;
+            self.out.push_str(" (*)\n");
+            return;
+        }
+        self.out.push('\n');
+
+        // By ID; a step listed in both `depends_on` and `on_failure` once
+        let mut children: Vec<NodeIndex> = self.graph.neighbors_directed(idx, Direction::Outgoing).collect();
+        children.sort_by_key(|child| &self.graph[*child].step.id);
+        children.dedup();
+        let count = children.len();
+        for (position, child) in children.into_iter().enumerate() {
+            self.draw(child, &child_prefix, Some(position + 1 == count));
+        }
+    }
+}
+
+/// Which part of a flow to run (all fields empty = the whole flow)
+///
+/// - `step`: that step plus everything it depends on
+/// - `from`: that step plus everything depending on it
+/// - `to`: that step plus everything it depends on
+/// - `tags`: steps carrying any of these `labels`, plus everything they
+///   depend on
+///
+/// `from` and `to` can be combined to run the slice between two steps; every
+/// field that is set narrows the selection further.
+#[derive(Debug, Clone, Default)]
+pub struct StepSelection {
+    pub step: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub tags: Vec<String>,
+}
+
+impl StepSelection {
+    /// True if nothing is selected, i.e. the whole flow runs
+    pub fn is_empty(&self) -> bool {
+        self.step.is_none() && self.from.is_none() && self.to.is_none() && self.tags.is_empty()
+    }
+
+    /// Prunes `graph` down to the selected steps
+    pub fn apply(&self, graph: &StepGraph) -> Result<StepGraph, FlowError> {
+        let mut keep: HashSet<String> = graph.node_weights().map(|node| node.step.id.clone()).collect();
+
+        for target in self.step.iter().chain(self.to.iter()) {
+            let mut upstream = ancestors(graph, target)?;
+            upstream.insert(target.clone());
+            keep.retain(|id| upstream.contains(id));
+        }
+
+        if let Some(from) = &self.from {
+            let mut downstream = descendants(graph, from)?;
+            downstream.insert(from.clone());
+            keep.retain(|id| downstream.contains(id));
+        }
+
+        if !self.tags.is_empty() {
+            let mut tagged = HashSet::new();
+            for tag in &self.tags {
+                let labeled: Vec<&Step> = graph
+                    .node_weights()
+                    .map(|node| &node.step)
+                    .filter(|step| step.labels.contains(tag))
+                    .collect();
+                if labeled.is_empty() {
+                    return Err(FlowError::UnknownLabel { label: tag.clone() });
+                }
+                for step in labeled {
+                    tagged.extend(ancestors(graph, &step.id)?);
+                    tagged.insert(step.id.clone());
+                }
+            }
+            keep.retain(|id| tagged.contains(id));
+        }
+
+        Ok(prune_graph(graph, &keep))
+    }
+}
+
+/// Combines a flow's declared inputs (and defaults) with the given values
+///
+/// Fails on values for undeclared inputs and on required inputs left unset.
+pub fn resolve_inputs(
+    flow: &Flow,
+    provided: &BTreeMap<String, serde_yaml::Value>,
+) -> Result<BTreeMap<String, serde_yaml::Value>, FlowError> {
+    if let Some(name) = provided.keys().find(|name| !flow.inputs.contains_key(*name)) {
+        return Err(FlowError::UnknownInput { name: name.clone() });
+    }
+
+    flow.inputs
+        .iter()
+        .map(|(name, default)| match provided.get(name).unwrap_or(default) {
+            serde_yaml::Value::Null => Err(FlowError::MissingInput { name: name.clone() }),
+            value => Ok((name.clone(), value.clone())),
+        })
+        .collect()
+}
+
+/// Opt-in check that every step's `kind` has a registered handler
+///
+/// Without it, a typo'd kind silently falls back to the simulator, so this
+/// is the way to catch mistakes before a real run. Lists all offenders at once.
+pub fn validate_kinds(flow: &Flow, registry: &HandlerRegistry) -> Result<(), FlowError> {
+    let unknown: Vec<String> = flow
+        .nodes
+        .iter()
+        .filter(|step| !registry.contains(&step.kind))
+        .map(|step| format!("'{}' (kind '{}')", step.id, step.kind))
+        .collect();
+
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(FlowError::UnknownKinds(unknown))
+    }
+}
+
+/// Safety valve for generated or untrusted flows: refuses one with more than
+/// `max` steps, before anything runs
+pub fn validate_step_count(flow: &Flow, max: usize) -> Result<(), FlowError> {
+    if flow.nodes.len() > max {
+        Err(FlowError::TooManySteps {
+            id: flow.id.clone(),
+            steps: flow.nodes.len(),
+            max,
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Refuses a flow without steps, which loading only warns about (the CLI's
+/// `--strict`)
+pub fn validate_not_empty(flow: &Flow) -> Result<(), FlowError> {
+    if flow.nodes.is_empty() {
+        Err(FlowError::EmptyFlow { id: flow.id.clone() })
+    } else {
+        Ok(())
+    }
+}
+
+/// Checks every step's `config` against the JSON Schema registered for its
+/// kind (kinds without one are skipped). Lists all problems at once, each
+/// naming the step and config field.
+///
+/// Config is checked as written (merged over the kind's `defaults`), before
+/// `{{ ... }}` placeholders are filled in.
+pub fn validate_configs(flow: &Flow, registry: &HandlerRegistry) -> Result<(), FlowError> {
+    let problems: Vec<String> = flow
+        .nodes
+        .iter()
+        .flat_map(|step| match registry.validate_config(&step.kind, &flow.step_config(step)) {
+            Ok(()) => Vec::new(),
+            Err(problems) => problems
+                .into_iter()
+                .map(|problem| format!("step '{}': {problem}", step.id))
+                .collect(),
+        })
+        .collect();
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(FlowError::InvalidConfig(problems))
+    }
+}
+
+/// Checks the `{{ ... }}` placeholders in every step's config (and its
+/// compensation's) without running anything: `steps.<id>.output` must name
+/// a step the referencing step depends on, directly or not, and
+/// `inputs.<name>` an input the flow declares. Lists all problems at once.
+///
+/// An inline sub-flow (`kind: subflow` with `flow:`) is left alone — its
+/// placeholders refer to its own steps.
+pub fn validate_templates(flow: &Flow, graph: &StepGraph) -> Result<(), FlowError> {
+    let mut problems = Vec::new();
+
+    for step in &flow.nodes {
+        let mut config = flow.step_config(step);
+        if step.kind == "subflow" {
+            if let serde_yaml::Value::Mapping(mapping) = &mut config {
+                mapping.remove("flow");
+            }
+        }
+        let mut references = template::references(&config);
+        if let Some(for_each) = &step.for_each {
+            references.extend(template::references(&serde_yaml::Value::String(for_each.clone())));
+        }
+        if let Some(compensation) = &step.compensation {
+            references.extend(template::references(&compensation.config));
+        }
+        if references.is_empty() {
+            continue;
+        }
+
+        let upstream = ancestors(graph, &step.id)?;
+        for reference in references {
+            let problem = match reference {
+                Reference::Input(name) if !flow.inputs.contains_key(&name) => {
+                    format!("step '{}': input '{name}' is not declared", step.id)
+                }
+                Reference::StepOutput(id) if find_step(graph, &id).is_none() => {
+                    format!("step '{}': step '{id}' does not exist", step.id)
+                }
+                Reference::StepOutput(id) if !upstream.contains(&id) => {
+                    format!("step '{}': step '{id}' is not one of its dependencies, so its output isn't available yet", step.id)
+                }
+                _ => continue,
+            };
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(FlowError::InvalidTemplates(problems))
+    }
+}
+
 /// Default implementation of Step for test cases or stubs
 impl Default for Step {
     fn default() -> Self {
         Step {
             id: String::new(),
             kind: "noop".into(),
+            description: None,
+            stage: None,
             depends_on: vec![],
             config: serde_yaml::Value::Null,
+            config_file: None,
             retry: None,
             idempotency_key: None,
+            cache: false,
             compensation: None,
+            timeout_seconds: None,
+            when: None,
+            compensate_on_timeout: false,
+            estimated_seconds: None,
+            enabled: true,
+            labels: vec![],
+            for_each: None,
+            on_failure: vec![],
+            priority: 0,
         }
     }
 }
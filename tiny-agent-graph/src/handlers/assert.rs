@@ -0,0 +1,71 @@
+use super::shell::scalar_to_string;
+use super::{Change, HandlerError, HandlerOutput, StepContext, StepHandler};
+use async_trait::async_trait;
+use serde_yaml::Value;
+
+/// Checks a condition inline and fails the step if it doesn't hold
+/// (`kind: assert`)
+///
+/// Config (templated like any other, so operands can be upstream outputs):
+/// - `left`, `right`: the values to compare (strings, numbers, or booleans)
+/// - `op`: `eq`, `ne`, or `contains` (`left` contains `right`)
+///
+/// Values are compared as text, so `1` equals `"1"`. A failed assertion
+/// isn't retried: the same operands would fail it again.
+pub struct AssertHandler;
+
+#[async_trait]
+impl StepHandler for AssertHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let config = &ctx.step.config;
+        let left = operand(config, "left", &ctx.step.id)?;
+        let right = operand(config, "right", &ctx.step.id)?;
+        let op = config["op"]
+            .as_str()
+            .ok_or_else(|| HandlerError::permanent(format!("Step '{}' needs an `op` string in its config", ctx.step.id)))?;
+
+        let (holds, expectation) = match op {
+            "eq" => (left == right, "to equal"),
+            "ne" => (left != right, "not to equal"),
+            "contains" => (left.contains(&right), "to contain"),
+            other => {
+                return Err(HandlerError::permanent(format!(
+                    "Unknown assert op '{other}' (expected eq, ne, or contains)"
+                )))
+            }
+        };
+
+        if holds {
+            Ok(format!("{left:?} {op} {right:?}").into())
+        } else {
+            Err(HandlerError::permanent(format!(
+                "Assertion failed: expected {left:?} {expectation} {right:?}"
+            )))
+        }
+    }
+
+    async fn would_change(&self, _ctx: &StepContext) -> Result<Change, String> {
+        Ok(Change::Unchanged)
+    }
+}
+
+/// What `assert` steps accept in `config`
+pub(super) fn config_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["left", "op", "right"],
+        "properties": {
+            "left": { "type": ["string", "number", "boolean"] },
+            "op": { "enum": ["eq", "ne", "contains"] },
+            "right": { "type": ["string", "number", "boolean"] }
+        }
+    })
+}
+
+fn operand(config: &Value, field: &str, step_id: &str) -> Result<String, HandlerError> {
+    match &config[field] {
+        Value::Null => Err(HandlerError::permanent(format!("Step '{step_id}' needs `{field}` in its config"))),
+        value => scalar_to_string(value)
+            .ok_or_else(|| HandlerError::permanent(format!("Unsupported value in `{field}`: {value:?}"))),
+    }
+}
@@ -0,0 +1,116 @@
+use super::{Change, HandlerError, HandlerOutput, StepContext, StepHandler};
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use std::time::Duration;
+
+/// How the registry's shared HTTP client is built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpClientConfig {
+    /// Idle connections kept open per host for reuse
+    pub pool_max_idle_per_host: usize,
+
+    /// Timeout for requests from steps without their own `timeout_seconds`
+    pub timeout_seconds: Option<u64>,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        HttpClientConfig {
+            pool_max_idle_per_host: 16,
+            timeout_seconds: Some(30),
+        }
+    }
+}
+
+impl HttpClientConfig {
+    /// A client with this pool size and default timeout
+    pub fn build(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder().pool_max_idle_per_host(self.pool_max_idle_per_host);
+        if let Some(seconds) = self.timeout_seconds {
+            builder = builder.timeout(Duration::from_secs(seconds));
+        }
+        builder.build().map_err(|err| format!("Cannot build HTTP client: {err}"))
+    }
+}
+
+/// Fetches a URL (`kind: http_get`)
+///
+/// Config:
+/// - `url`: what to fetch (required)
+/// - `headers`: map of extra request headers (optional)
+///
+/// Uses the registry's shared client, so steps reuse pooled connections. The
+/// response body becomes the step output and the status its diagnostics; a
+/// non-2xx status fails the step (retriably for 5xx, 408 and 429 only).
+/// The step's `timeout_seconds` (if any) overrides the client's default
+/// timeout.
+pub struct HttpGetHandler;
+
+#[async_trait]
+impl StepHandler for HttpGetHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let config = &ctx.step.config;
+
+        let url = config["url"]
+            .as_str()
+            .ok_or_else(|| HandlerError::permanent(format!("Step '{}' needs a `url` string in its config", ctx.step.id)))?;
+
+        let mut request = ctx.http_client.get(url);
+        if let Some(headers) = config["headers"].as_mapping() {
+            for (name, value) in headers {
+                match (name.as_str(), value.as_str()) {
+                    (Some(name), Some(value)) => request = request.header(name, value),
+                    _ => {
+                        return Err(HandlerError::permanent(format!(
+                            "Unsupported header in `headers`: {name:?}: {value:?}"
+                        )))
+                    }
+                }
+            }
+        }
+        if let Some(seconds) = ctx.step.timeout_seconds {
+            request = request.timeout(Duration::from_secs(seconds));
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|err| format!("GET {url} failed: {err}"))?;
+        let status = response.status();
+        let body = response
+            .text()
+            .await
+            .map_err(|err| format!("Cannot read response from {url}: {err}"))?;
+
+        if status.is_success() {
+            return Ok(HandlerOutput::from(body).with_diagnostics(format!("HTTP {}", status.as_u16())));
+        }
+
+        // Server errors, timeouts and throttling may clear up; other client errors won't
+        let message = format!("GET {url} returned HTTP {}: {}", status.as_u16(), body.trim());
+        let retriable = status.is_server_error()
+            || status == StatusCode::REQUEST_TIMEOUT
+            || status == StatusCode::TOO_MANY_REQUESTS;
+        Err(HandlerError { message, retriable })
+    }
+
+    /// A GET only reads
+    async fn would_change(&self, _ctx: &StepContext) -> Result<Change, String> {
+        Ok(Change::Unchanged)
+    }
+}
+
+/// What `http_get` steps accept in `config`
+pub(super) fn config_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["url"],
+        "properties": {
+            "url": { "type": "string" },
+            "headers": {
+                "type": "object",
+                "additionalProperties": { "type": "string" }
+            }
+        }
+    })
+}
@@ -0,0 +1,345 @@
+#![allow(dead_code)] // Not every handler is wired into the CLI yet
+
+mod assert;
+mod http;
+#[cfg(feature = "publish")]
+pub mod publish;
+mod script;
+mod shell;
+mod sleep;
+mod subflow;
+
+pub use assert::AssertHandler;
+pub use http::{HttpClientConfig, HttpGetHandler};
+pub use script::ScriptHandler;
+pub use shell::ShellHandler;
+pub use sleep::SleepHandler;
+pub use subflow::SubflowHandler;
+
+use crate::engine::InheritedOptions;
+use crate::flow::Step;
+use async_trait::async_trait;
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Everything a handler gets to see about the step it executes
+#[derive(Debug, Clone)]
+pub struct StepContext {
+    /// ID of the run this step belongs to
+    pub run_id: String,
+
+    /// ID of the flow being executed
+    pub flow_id: String,
+
+    /// The step definition (kind, config, ...)
+    pub step: Step,
+
+    /// Outputs of steps that already succeeded in this run, by step ID
+    pub outputs: HashMap<String, String>,
+
+    /// The run's resolved flow inputs (`Flow::inputs` plus given values)
+    pub inputs: BTreeMap<String, serde_yaml::Value>,
+
+    /// Where relative paths in the step's config point from: the flow
+    /// file's directory (see `Flow::base_dir`)
+    pub base_dir: PathBuf,
+
+    /// The file the flow was loaded from, if any (see `Flow::file`)
+    pub flow_file: Option<PathBuf>,
+
+    /// The registry's shared HTTP client (see `HandlerRegistry::http_client`);
+    /// clones share one connection pool
+    pub http_client: reqwest::Client,
+
+    /// Settings of the enclosing run that nested runs (sub-flows) keep:
+    /// registry, cancellation, dry run, parallelism, cache, ...
+    pub inherited: InheritedOptions,
+}
+
+impl StepContext {
+    /// `path` as given if absolute, otherwise relative to `base_dir`
+    pub fn resolve_path(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.base_dir.join(path)
+    }
+}
+
+/// What a handler hands back for a step that succeeded
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandlerOutput {
+    /// The step's result — what later steps see as its output
+    pub output: String,
+
+    /// Side-channel info kept apart from the output (e.g. command stderr,
+    /// HTTP status)
+    pub diagnostics: Option<String>,
+}
+
+impl HandlerOutput {
+    /// Attaches diagnostics, unless they're empty
+    pub fn with_diagnostics(mut self, diagnostics: impl Into<String>) -> Self {
+        let diagnostics = diagnostics.into();
+        self.diagnostics = (!diagnostics.is_empty()).then_some(diagnostics);
+        self
+    }
+}
+
+impl From<String> for HandlerOutput {
+    fn from(output: String) -> Self {
+        HandlerOutput {
+            output,
+            diagnostics: None,
+        }
+    }
+}
+
+impl From<&str> for HandlerOutput {
+    fn from(output: &str) -> Self {
+        output.to_string().into()
+    }
+}
+
+/// Why a handler couldn't execute its step
+///
+/// Plain strings convert into retriable errors, so handlers only need to
+/// say so when trying again can't help (`HandlerError::permanent`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HandlerError {
+    /// Human-readable reason; becomes the step's failure reason
+    pub message: String,
+
+    /// Whether another attempt might succeed (e.g. an HTTP 503 or a dropped
+    /// connection, but not a 404 or a bad config). The engine never retries
+    /// errors that aren't, whatever the step's `retry` policy says.
+    pub retriable: bool,
+}
+
+impl HandlerError {
+    /// An error worth retrying
+    pub fn retriable(message: impl Into<String>) -> Self {
+        HandlerError {
+            message: message.into(),
+            retriable: true,
+        }
+    }
+
+    /// An error that would happen again on every attempt
+    pub fn permanent(message: impl Into<String>) -> Self {
+        HandlerError {
+            message: message.into(),
+            retriable: false,
+        }
+    }
+}
+
+impl From<String> for HandlerError {
+    fn from(message: String) -> Self {
+        HandlerError::retriable(message)
+    }
+}
+
+impl From<&str> for HandlerError {
+    fn from(message: &str) -> Self {
+        HandlerError::retriable(message)
+    }
+}
+
+impl fmt::Display for HandlerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// What a dry run says a step would do (see `StepHandler::would_change`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Change {
+    /// Running the step would change some state
+    #[serde(rename = "would-change")]
+    Changed,
+    /// Running the step would leave everything as it is
+    #[serde(rename = "no-change")]
+    Unchanged,
+    /// The handler can't tell (e.g. it doesn't support dry runs)
+    #[serde(rename = "unknown")]
+    Unknown,
+}
+
+impl Change {
+    /// The verdict for several runs of a step (e.g. `for_each` items): a
+    /// change if any would change something, no change only if none would
+    pub fn combine(self, other: Change) -> Change {
+        match (self, other) {
+            (Change::Changed, _) | (_, Change::Changed) => Change::Changed,
+            (Change::Unchanged, Change::Unchanged) => Change::Unchanged,
+            _ => Change::Unknown,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Change::Changed => "would-change",
+            Change::Unchanged => "no-change",
+            Change::Unknown => "unknown",
+        })
+    }
+}
+
+/// A pluggable implementation of a step `kind`
+///
+/// Returns the step's output (plus any diagnostics) on success, or a
+/// `HandlerError` saying why not and whether retrying could help. Timeouts
+/// are enforced by the engine, so handlers don't need to.
+#[async_trait]
+pub trait StepHandler: Send + Sync {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError>;
+
+    /// Dry run (`RunOptions::dry_run`): whether `execute` would change any
+    /// state, found out without changing it. Handlers that can't tell keep
+    /// this default.
+    async fn would_change(&self, _ctx: &StepContext) -> Result<Change, String> {
+        Ok(Change::Unknown)
+    }
+}
+
+/// Maps step kinds (e.g. "shell") to the handlers that execute them
+///
+/// Kinds without a registered handler fall back to the engine's simulator.
+/// A kind may also have a JSON Schema its steps' `config` must match (see
+/// `flow::validate_configs`). It also owns the HTTP client that every HTTP
+/// step in a run shares.
+#[derive(Clone, Default)]
+pub struct HandlerRegistry {
+    handlers: HashMap<String, Arc<dyn StepHandler>>,
+    schemas: HashMap<String, Arc<Validator>>,
+    http_client: reqwest::Client,
+}
+
+impl HandlerRegistry {
+    /// Empty registry — every step is simulated
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with all built-in handlers registered
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        registry.register("assert", AssertHandler);
+        registry.register("http_get", HttpGetHandler);
+        registry.register("script", ScriptHandler);
+        registry.register("shell", ShellHandler);
+        registry.register("sleep", SleepHandler);
+        registry.register("subflow", SubflowHandler);
+        registry
+            .register_schema("assert", assert::config_schema())
+            .expect("built-in schema is valid");
+        registry
+            .register_schema("http_get", http::config_schema())
+            .expect("built-in schema is valid");
+        registry
+            .register_schema("script", script::config_schema())
+            .expect("built-in schema is valid");
+        registry
+            .register_schema("shell", shell::config_schema())
+            .expect("built-in schema is valid");
+        registry
+            .register_schema("sleep", sleep::config_schema())
+            .expect("built-in schema is valid");
+        registry
+    }
+
+    /// Replaces the shared HTTP client with one built from `config`
+    pub fn with_http_client(mut self, config: &HttpClientConfig) -> Result<Self, String> {
+        self.http_client = config.build()?;
+        Ok(self)
+    }
+
+    /// The client HTTP handlers send their requests through
+    pub fn http_client(&self) -> &reqwest::Client {
+        &self.http_client
+    }
+
+    /// Registers (or replaces) the handler for `kind`
+    pub fn register(
+        &mut self,
+        kind: impl Into<String>,
+        handler: impl StepHandler + 'static,
+    ) -> &mut Self {
+        self.handlers.insert(kind.into(), Arc::new(handler));
+        self
+    }
+
+    /// Removes the handler (and schema) for `kind`, if any
+    pub fn unregister(&mut self, kind: &str) -> &mut Self {
+        self.handlers.remove(kind);
+        self.schemas.remove(kind);
+        self
+    }
+
+    /// Sets (or replaces) the JSON Schema that `kind` steps' config must
+    /// match; fails if `schema` itself isn't a valid schema
+    pub fn register_schema(&mut self, kind: impl Into<String>, schema: serde_json::Value) -> Result<&mut Self, String> {
+        let validator = jsonschema::validator_for(&schema).map_err(|err| format!("Invalid config schema: {err}"))?;
+        self.schemas.insert(kind.into(), Arc::new(validator));
+        Ok(self)
+    }
+
+    /// Checks `config` against the schema for `kind`, listing every problem
+    /// with the config field it's about. Kinds without a schema always pass.
+    pub fn validate_config(&self, kind: &str, config: &serde_yaml::Value) -> Result<(), Vec<String>> {
+        let Some(validator) = self.schemas.get(kind) else {
+            return Ok(());
+        };
+
+        // A missing `config` is checked as an empty object, so `required` still fires
+        let instance = match config {
+            serde_yaml::Value::Null => serde_json::json!({}),
+            config => serde_json::to_value(config).map_err(|err| vec![err.to_string()])?,
+        };
+
+        let problems: Vec<String> = validator
+            .iter_errors(&instance)
+            .map(|err| {
+                let path = err.instance_path.to_string();
+                if path.is_empty() {
+                    err.to_string()
+                } else {
+                    format!("`{}`: {err}", path.trim_start_matches('/').replace('/', "."))
+                }
+            })
+            .collect();
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Looks up the handler for `kind`, if any
+    pub fn get(&self, kind: &str) -> Option<Arc<dyn StepHandler>> {
+        self.handlers.get(kind).cloned()
+    }
+
+    /// Whether a handler is registered for `kind`
+    pub fn contains(&self, kind: &str) -> bool {
+        self.handlers.contains_key(kind)
+    }
+}
+
+impl fmt::Debug for HandlerRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut kinds: Vec<&String> = self.handlers.keys().collect();
+        kinds.sort();
+        let mut schemas: Vec<&String> = self.schemas.keys().collect();
+        schemas.sort();
+        f.debug_struct("HandlerRegistry")
+            .field("kinds", &kinds)
+            .field("schemas", &schemas)
+            .finish()
+    }
+}
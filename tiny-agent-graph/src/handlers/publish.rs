@@ -0,0 +1,105 @@
+use super::{Change, HandlerError, HandlerOutput, StepContext, StepHandler};
+use async_trait::async_trait;
+use serde_yaml::Value;
+use std::sync::{Arc, Mutex};
+
+/// Where `publish` steps send their messages (AMQP, Kafka, ...)
+///
+/// Implementations deliver `payload` to `topic` and answer with the
+/// broker's acknowledgement ID; an `Err` is the reason it wasn't accepted.
+#[async_trait]
+pub trait MessageBroker: Send + Sync {
+    async fn publish(&self, topic: &str, payload: &str) -> Result<String, String>;
+}
+
+/// A broker that keeps every message in memory, in publish order — for
+/// tests and local runs
+#[derive(Debug, Default)]
+pub struct InMemoryBroker {
+    messages: Mutex<Vec<(String, String)>>,
+}
+
+impl InMemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every `(topic, payload)` published so far
+    pub fn messages(&self) -> Vec<(String, String)> {
+        self.messages.lock().expect("broker lock poisoned").clone()
+    }
+}
+
+#[async_trait]
+impl MessageBroker for InMemoryBroker {
+    async fn publish(&self, topic: &str, payload: &str) -> Result<String, String> {
+        let mut messages = self.messages.lock().expect("broker lock poisoned");
+        messages.push((topic.to_string(), payload.to_string()));
+        Ok(format!("ack-{}", messages.len()))
+    }
+}
+
+/// Publishes a message to a broker (`kind: publish`)
+///
+/// Config (templated, so the payload can be an upstream output):
+/// - `topic`: where to publish (required)
+/// - `payload`: the message (required); anything but a string is sent as JSON
+///
+/// The broker's acknowledgement ID becomes the step output; a rejected
+/// publish fails the step. Not a built-in, since it needs a broker:
+/// register it with `HandlerRegistry::register("publish", PublishHandler::new(broker))`
+/// (and `PublishHandler::config_schema`).
+pub struct PublishHandler {
+    broker: Arc<dyn MessageBroker>,
+}
+
+impl PublishHandler {
+    pub fn new(broker: Arc<dyn MessageBroker>) -> Self {
+        PublishHandler { broker }
+    }
+
+    /// What `publish` steps accept in `config`, for
+    /// `HandlerRegistry::register_schema`
+    pub fn config_schema() -> serde_json::Value {
+        serde_json::json!({
+            "type": "object",
+            "required": ["topic", "payload"],
+            "properties": {
+                "topic": { "type": "string" }
+            }
+        })
+    }
+}
+
+#[async_trait]
+impl StepHandler for PublishHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let config = &ctx.step.config;
+
+        let topic = config["topic"]
+            .as_str()
+            .ok_or_else(|| HandlerError::permanent(format!("Step '{}' needs a `topic` string in its config", ctx.step.id)))?;
+        let payload = match &config["payload"] {
+            Value::Null => {
+                return Err(HandlerError::permanent(format!(
+                    "Step '{}' needs a `payload` in its config",
+                    ctx.step.id
+                )))
+            }
+            Value::String(payload) => payload.clone(),
+            payload => serde_json::to_string(payload)
+                .map_err(|err| HandlerError::permanent(format!("Cannot encode `payload`: {err}")))?,
+        };
+
+        let ack = self
+            .broker
+            .publish(topic, &payload)
+            .await
+            .map_err(|err| format!("Publishing to '{topic}' failed: {err}"))?;
+        Ok(ack.into())
+    }
+
+    async fn would_change(&self, _ctx: &StepContext) -> Result<Change, String> {
+        Ok(Change::Changed)
+    }
+}
@@ -0,0 +1,116 @@
+use super::{HandlerError, HandlerOutput, StepContext, StepHandler};
+use async_trait::async_trait;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope};
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Budget for scripts on steps without their own `timeout_seconds`
+const DEFAULT_SCRIPT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Evaluates an embedded Rhai script (`kind: script`)
+///
+/// Config:
+/// - `script`: Rhai source (required); its final value becomes the output
+///
+/// In scope: `steps` (map of prior step id → output), `inputs` (the flow's
+/// resolved inputs), `run_id`, `flow_id`.
+/// The script runs on a blocking thread and is terminated once it exceeds
+/// the step timeout, so a runaway loop can't pin the thread forever.
+pub struct ScriptHandler;
+
+#[async_trait]
+impl StepHandler for ScriptHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let script = ctx.step.config["script"]
+            .as_str()
+            .ok_or_else(|| HandlerError::permanent(format!("Step '{}' needs a `script` string in its config", ctx.step.id)))?
+            .to_string();
+
+        let timeout = ctx
+            .step
+            .timeout_seconds
+            .map_or(DEFAULT_SCRIPT_TIMEOUT, Duration::from_secs);
+        let outputs = ctx.outputs.clone();
+        let inputs = ctx.inputs.clone();
+        let run_id = ctx.run_id.clone();
+        let flow_id = ctx.flow_id.clone();
+
+        // Rhai values aren't Send, so everything script-related lives on the blocking thread
+        tokio::task::spawn_blocking(move || eval_script(&script, outputs, inputs, run_id, flow_id, timeout))
+            .await
+            .map_err(|err| format!("Script task failed: {err}"))?
+            .map(HandlerOutput::from)
+    }
+}
+
+/// What `script` steps accept in `config`
+pub(super) fn config_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["script"],
+        "properties": {
+            "script": { "type": "string" }
+        }
+    })
+}
+
+fn eval_script(
+    script: &str,
+    outputs: HashMap<String, String>,
+    inputs: BTreeMap<String, Value>,
+    run_id: String,
+    flow_id: String,
+    timeout: Duration,
+) -> Result<String, HandlerError> {
+    let deadline = Instant::now() + timeout;
+
+    let mut engine = Engine::new();
+    engine.on_progress(move |_ops| (Instant::now() >= deadline).then_some(Dynamic::UNIT));
+
+    let steps: Map = outputs
+        .into_iter()
+        .map(|(id, output)| (id.into(), Dynamic::from(output)))
+        .collect();
+    let inputs: Map = inputs
+        .into_iter()
+        .map(|(name, value)| (name.into(), to_dynamic(value)))
+        .collect();
+
+    let mut scope = Scope::new();
+    scope.push_constant("steps", steps);
+    scope.push_constant("inputs", inputs);
+    scope.push_constant("run_id", run_id);
+    scope.push_constant("flow_id", flow_id);
+
+    engine
+        .eval_with_scope::<Dynamic>(&mut scope, script)
+        .map(|value| value.to_string())
+        .map_err(|err| match *err {
+            EvalAltResult::ErrorTerminated(..) => HandlerError::retriable(format!("Script timed out after {timeout:?}")),
+            // Same script, same upstream outputs: it would fail the same way again
+            other => HandlerError::permanent(format!("Script error: {other}")),
+        })
+}
+
+/// A flow input as a Rhai value; lists and maps convert item by item, and
+/// map entries with non-string keys are dropped
+fn to_dynamic(value: Value) -> Dynamic {
+    match value {
+        Value::Null => Dynamic::UNIT,
+        Value::Bool(flag) => flag.into(),
+        Value::Number(number) => match number.as_i64() {
+            Some(int) => int.into(),
+            None => number.as_f64().map_or(Dynamic::UNIT, Dynamic::from),
+        },
+        Value::String(text) => text.into(),
+        Value::Sequence(items) => Dynamic::from_array(items.into_iter().map(to_dynamic).collect()),
+        Value::Mapping(entries) => Dynamic::from_map(
+            entries
+                .into_iter()
+                .filter_map(|(key, value)| Some((key.as_str()?.into(), to_dynamic(value))))
+                .collect(),
+        ),
+        Value::Tagged(tagged) => to_dynamic(tagged.value),
+    }
+}
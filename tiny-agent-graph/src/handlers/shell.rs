@@ -0,0 +1,95 @@
+use super::{HandlerError, HandlerOutput, StepContext, StepHandler};
+use async_trait::async_trait;
+use serde_yaml::Value;
+use tokio::process::Command;
+
+/// Runs a local command (`kind: shell`)
+///
+/// Config:
+/// - `command`: program to run (required)
+/// - `args`: list of arguments (optional)
+/// - `cwd`: working directory (optional; relative to the flow file's
+///   directory). Without it the command runs in the process's own.
+///
+/// Stdout becomes the step output and stderr its diagnostics; a non-zero exit
+/// fails the step with stderr (retriably; a command that can't start doesn't
+/// get retried).
+/// The child is killed if the engine drops the step (e.g. on timeout).
+pub struct ShellHandler;
+
+#[async_trait]
+impl StepHandler for ShellHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let config = &ctx.step.config;
+
+        let program = config["command"]
+            .as_str()
+            .ok_or_else(|| HandlerError::permanent(format!("Step '{}' needs a `command` string in its config", ctx.step.id)))?;
+
+        let args = match &config["args"] {
+            Value::Null => Vec::new(),
+            Value::Sequence(items) => items
+                .iter()
+                .map(|item| {
+                    scalar_to_string(item)
+                        .ok_or_else(|| HandlerError::permanent(format!("Unsupported value in `args`: {item:?}")))
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            other => return Err(HandlerError::permanent(format!("`args` must be a list, got {other:?}"))),
+        };
+
+        let mut command = Command::new(program);
+        command.args(&args).kill_on_drop(true);
+        if let Some(cwd) = config["cwd"].as_str() {
+            command.current_dir(ctx.resolve_path(cwd));
+        }
+
+        let output = command
+            .output()
+            .await
+            .map_err(|err| HandlerError::permanent(format!("Failed to start '{program}': {err}")))?;
+
+        if output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout).trim_end().to_string();
+            let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+            Ok(HandlerOutput::from(stdout).with_diagnostics(stderr))
+        } else {
+            let code = output
+                .status
+                .code()
+                .map_or_else(|| "a signal".to_string(), |code| format!("code {code}"));
+            Err(format!(
+                "'{program}' exited with {code}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into())
+        }
+    }
+}
+
+/// What `shell` steps accept in `config`
+pub(super) fn config_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["command"],
+        "properties": {
+            "command": { "type": "string" },
+            "args": {
+                "type": "array",
+                "items": { "type": ["string", "number", "boolean"] }
+            },
+            "cwd": { "type": "string" }
+        }
+    })
+}
+
+/// Renders a YAML scalar as plain text (a command-line argument, an
+/// assertion operand)
+pub(super) fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,42 @@
+use super::{Change, HandlerError, HandlerOutput, StepContext, StepHandler};
+use async_trait::async_trait;
+use std::time::Duration;
+
+/// Waits for a while, then succeeds (`kind: sleep`) — handy for testing
+/// timeouts and pacing a flow
+///
+/// Config:
+/// - `seconds`: how long to wait (required; fractions allowed)
+///
+/// The output is e.g. "slept 2s". A sleep longer than the step's
+/// `timeout_seconds` fails with a timeout like any other step.
+pub struct SleepHandler;
+
+#[async_trait]
+impl StepHandler for SleepHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let seconds = ctx.step.config["seconds"]
+            .as_f64()
+            .ok_or_else(|| HandlerError::permanent(format!("Step '{}' needs a `seconds` number in its config", ctx.step.id)))?;
+        let duration = Duration::try_from_secs_f64(seconds)
+            .map_err(|err| HandlerError::permanent(format!("Invalid `seconds` {seconds}: {err}")))?;
+
+        tokio::time::sleep(duration).await;
+        Ok(format!("slept {seconds}s").into())
+    }
+
+    async fn would_change(&self, _ctx: &StepContext) -> Result<Change, String> {
+        Ok(Change::Unchanged)
+    }
+}
+
+/// What `sleep` steps accept in `config`
+pub(super) fn config_schema() -> serde_json::Value {
+    serde_json::json!({
+        "type": "object",
+        "required": ["seconds"],
+        "properties": {
+            "seconds": { "type": "number", "minimum": 0 }
+        }
+    })
+}
@@ -0,0 +1,95 @@
+use super::{HandlerError, HandlerOutput, StepContext, StepHandler};
+use crate::engine::{run_flow_with_options, RunStatus, StepStatus};
+use crate::flow::{build_step_graph, load_flow, Flow};
+use async_trait::async_trait;
+use std::path::PathBuf;
+
+tokio::task_local! {
+    /// Flow files currently being run as sub-flows, outermost first
+    static INCLUDE_CHAIN: Vec<PathBuf>;
+}
+
+/// Runs another flow as a single step (`kind: subflow`)
+///
+/// Config (one of):
+/// - `path`: flow file to load (relative to the parent flow's directory)
+/// - `flow`: the flow definition inline
+///
+/// The sub-flow runs with the parent run's registry, cancellation, dry-run
+/// mode, parallelism and cache (see `InheritedOptions`).
+///
+/// The sub-flow's `RunHistory` (as JSON) becomes the step output; if the
+/// sub-flow fails, so does the step. A file that (indirectly) includes itself
+/// fails the step instead of recursing forever.
+pub struct SubflowHandler;
+
+#[async_trait]
+impl StepHandler for SubflowHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let config = &ctx.step.config;
+        let mut chain = INCLUDE_CHAIN.try_with(Clone::clone).unwrap_or_default();
+        // The top-level flow's own file starts the chain, so a flow including
+        // itself fails here instead of running its steps a second time
+        if chain.is_empty() {
+            chain.extend(ctx.flow_file.as_ref().and_then(|file| file.canonicalize().ok()));
+        }
+
+        let (flow, graph) = match (config["path"].as_str(), config.get("flow")) {
+            (Some(path), None) => {
+                let path = ctx.resolve_path(path);
+                let canonical = path
+                    .canonicalize()
+                    .map_err(|err| HandlerError::permanent(format!("Cannot open sub-flow {path:?}: {err}")))?;
+                if chain.contains(&canonical) {
+                    chain.push(canonical);
+                    let cycle: Vec<String> = chain.iter().map(|file| file.display().to_string()).collect();
+                    return Err(HandlerError::permanent(format!("Sub-flow cycle: {}", cycle.join(" -> "))));
+                }
+                chain.push(canonical);
+                load_flow(&path).map_err(|err| HandlerError::permanent(format!("Cannot load sub-flow {path:?}: {err}")))?
+            }
+            (None, Some(inline)) => {
+                let mut flow: Flow = serde_yaml::from_value(inline.clone())
+                    .map_err(|err| HandlerError::permanent(format!("Invalid inline sub-flow: {err}")))?;
+                flow.base_dir = Some(ctx.base_dir.clone());
+                let graph = build_step_graph(&flow)
+                    .map_err(|err| HandlerError::permanent(format!("Invalid inline sub-flow: {err}")))?;
+                (flow, graph)
+            }
+            _ => {
+                return Err(HandlerError::permanent(format!(
+                    "Step '{}' needs exactly one of `path` or `flow` in its config",
+                    ctx.step.id
+                )))
+            }
+        };
+
+        let history = INCLUDE_CHAIN
+            .scope(chain, run_flow_with_options(&flow, graph, &ctx.inherited.run_options()))
+            .await
+            .map_err(|err| format!("Sub-flow '{}' could not run: {err}", flow.id))?;
+
+        match &history.status {
+            RunStatus::Success => serde_json::to_string(&history)
+                .map(HandlerOutput::from)
+                .map_err(|err| err.to_string().into()),
+            RunStatus::Failed(reason) => {
+                let mut failed: Vec<String> = history
+                    .step_results
+                    .iter()
+                    .filter_map(|(step_id, result)| match &result.status {
+                        StepStatus::Failed(_, why) => Some(format!("{step_id} ({why})")),
+                        _ => None,
+                    })
+                    .collect();
+                failed.sort();
+                Err(format!(
+                    "Sub-flow '{}' failed ({reason}); failed steps: {}",
+                    flow.id,
+                    failed.join(", ")
+                )
+                .into())
+            }
+        }
+    }
+}
@@ -1,2 +1,14 @@
+pub mod cache;
+pub mod condition;
 pub mod engine;
-pub mod flow;
\ No newline at end of file
+pub mod flow;
+pub mod handlers;
+pub mod lint;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod notify;
+pub mod persistence;
+pub mod server;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod template;
\ No newline at end of file
@@ -0,0 +1,136 @@
+#![allow(dead_code)] // Only the `lint` subcommand uses this so far
+
+use crate::flow::{self, Flow, StepGraph};
+use petgraph::Direction;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Dependency chains longer than this (in steps) get a warning
+pub const MAX_CHAIN_LENGTH: usize = 10;
+
+/// How serious a lint finding is — only errors fail `lint`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A single best-practice violation found in a flow
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Name of the rule that fired (e.g. "unreachable-step")
+    pub rule: &'static str,
+    pub severity: Severity,
+    /// The step the finding is about, if it's about one
+    pub step: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]", self.severity, self.rule)?;
+        if let Some(step) = &self.step {
+            write!(f, " step '{step}'")?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+/// A lint rule: inspects a loaded flow and reports what it finds
+pub type LintRule = fn(&Flow, &StepGraph) -> Vec<Finding>;
+
+/// Every rule `lint_flow` runs, in reporting order
+pub const RULES: &[LintRule] = &[
+    fail_test_steps,
+    network_steps_without_retry,
+    unreachable_steps,
+    long_dependency_chains,
+];
+
+/// Runs all `RULES` against a flow; an empty result means a clean flow
+pub fn lint_flow(flow: &Flow, graph: &StepGraph) -> Vec<Finding> {
+    RULES.iter().flat_map(|rule| rule(flow, graph)).collect()
+}
+
+/// True if any finding is severe enough to fail the lint
+pub fn has_errors(findings: &[Finding]) -> bool {
+    findings.iter().any(|finding| finding.severity == Severity::Error)
+}
+
+/// `fail_test` steps always fail — fine for trying things out, never for real runs
+pub fn fail_test_steps(flow: &Flow, _graph: &StepGraph) -> Vec<Finding> {
+    flow.nodes
+        .iter()
+        .filter(|step| step.kind == "fail_test")
+        .map(|step| Finding {
+            rule: "fail-test-step",
+            severity: Severity::Error,
+            step: Some(step.id.clone()),
+            message: "kind 'fail_test' always fails; remove it before running for real".into(),
+        })
+        .collect()
+}
+
+/// Network calls (`http*` kinds) fail transiently, so they should have a `retry`
+pub fn network_steps_without_retry(flow: &Flow, _graph: &StepGraph) -> Vec<Finding> {
+    flow.nodes
+        .iter()
+        .filter(|step| step.kind.starts_with("http") && step.retry.is_none())
+        .map(|step| Finding {
+            rule: "network-without-retry",
+            severity: Severity::Warning,
+            step: Some(step.id.clone()),
+            message: format!("network step (kind '{}') has no retry policy", step.kind),
+        })
+        .collect()
+}
+
+/// Steps cut off from the main part of the flow (see `flow::unreachable_steps`)
+/// — usually a forgotten `depends_on`
+pub fn unreachable_steps(_flow: &Flow, graph: &StepGraph) -> Vec<Finding> {
+    flow::unreachable_steps(graph)
+        .into_iter()
+        .map(|step| Finding {
+            rule: "unreachable-step",
+            severity: Severity::Warning,
+            step: Some(step),
+            message: "step is not connected to the rest of the flow".into(),
+        })
+        .collect()
+}
+
+/// Chains of more than `MAX_CHAIN_LENGTH` steps are slow and brittle: any
+/// failure blocks everything after it
+pub fn long_dependency_chains(_flow: &Flow, graph: &StepGraph) -> Vec<Finding> {
+    // Number of steps in the longest chain ending at each step (none if
+    // there's a cycle)
+    let chain_lengths: HashMap<_, usize> = flow::step_depths(graph)
+        .into_iter()
+        .map(|(idx, depth)| (idx, depth + 1))
+        .collect();
+
+    // Report only the ends of over-long chains, not every step along them
+    graph
+        .node_indices()
+        .filter(|idx| chain_lengths.get(idx).is_some_and(|&length| length > MAX_CHAIN_LENGTH))
+        .filter(|idx| graph.neighbors_directed(*idx, Direction::Outgoing).next().is_none())
+        .map(|idx| Finding {
+            rule: "long-dependency-chain",
+            severity: Severity::Warning,
+            step: Some(graph[idx].step.id.clone()),
+            message: format!(
+                "ends a chain of {} dependent steps (more than {MAX_CHAIN_LENGTH})",
+                chain_lengths[&idx]
+            ),
+        })
+        .collect()
+}
@@ -1,13 +1,54 @@
 // Top-level module declarations
+mod cache;    // Step outputs memoized across runs (`--cache-dir`)
 mod flow;     // Flow parsing and DAG building
 mod engine;   // DAG execution engine
+mod condition; // `when` expressions for conditional steps
+mod handlers; // Step handler trait + built-in handlers
+mod lint;     // Best-practice checks for flows
+#[cfg(feature = "metrics")]
+mod metrics;  // Prometheus counters and timings
+mod notify;   // Webhook notifications on run completion
+mod persistence; // SQLite run history store
+mod server;   // HTTP API for submitting and running flows
+#[cfg(feature = "otel")]
+mod telemetry; // OpenTelemetry span export
+mod template; // `{{ ... }}` placeholders in step config
 
 // Standard and third-party imports
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{info, error};
-use clap::{Parser, Subcommand};
-use flow::load_flow;
-use engine::{run_flow, StepStatus};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+use clap::{Args, Parser, Subcommand};
+use ::notify::{Event, RecursiveMode, Watcher};
+use nu_ansi_term::Color;
+use flow::{
+    critical_path, diff_graphs, execution_levels, flow_stats, graph_hash, is_flow_url, load_flows,
+    load_flows_from_url, normalize_flow, resolve_inputs, select_flow, to_ascii_tree,
+    validate_configs, validate_kinds, validate_not_empty, validate_step_count, validate_templates,
+    DependOn, DependencyTarget, Flow, FlowStats, GraphChange, LabelMode, RetryPolicy, Step,
+    StepGraph, StepSelection,
+};
+use handlers::HandlerRegistry;
+use engine::{
+    run_flow_with_options, stage_tallies, EventCallback, OnConflict, RunEvent, RunHistory, RunOptions, RunStatus,
+    StageTally, StepDecision, StepGate, StepStatus, DEFAULT_MAX_OUTPUT_BYTES,
+};
+use lint::{has_errors, lint_flow};
+use persistence::{RunRecord, SqliteStore};
+
+/// `run-flow --watch` waits for the file to be quiet this long before re-running
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
 
 /// CLI entrypoint using `clap` to define subcommands
 #[derive(Parser)]
@@ -15,64 +56,659 @@ use engine::{run_flow, StepStatus};
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// Log verbosity: off, error, warn, info, debug or trace
+    #[arg(long, global = true, default_value = "debug")]
+    log_level: LevelFilter,
+
+    /// Log output format on stderr: text or json
+    #[arg(long, global = true, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Print without colors (also the default when output isn't a terminal)
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Export run and step spans to this OTLP/gRPC endpoint
+    #[cfg(feature = "otel")]
+    #[arg(long, global = true, env = "OTEL_EXPORTER_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+}
+
+/// Whether stdout gets colored (see `--no-color`); set once in `main`
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// `text` in `color`, or as-is when colors are off
+fn paint(color: Color, text: impl fmt::Display) -> String {
+    if COLOR.load(Ordering::Relaxed) {
+        color.paint(text.to_string()).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Green for success, red for failure, yellow for steps that didn't run
+fn status_color(status: &StepStatus) -> Color {
+    match status {
+        StepStatus::Success => Color::Green,
+        StepStatus::Failed(..) => Color::Red,
+        StepStatus::Skipped(_) | StepStatus::Cancelled => Color::Yellow,
+    }
+}
+
+/// How log lines are rendered (see `--log-format`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    /// Human-readable lines
+    Text,
+    /// One JSON object per line, for log shippers
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format '{other}' (expected text or json)")),
+        }
+    }
+}
+
+/// When a finished run makes `run-flow` exit non-zero (see `--fail-on`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FailOn {
+    /// Never: a run that finished exits 0 whatever happened to its steps
+    None,
+    /// Only if the run failed and no step succeeded
+    Partial,
+    /// If any step failed
+    Any,
+}
+
+impl FailOn {
+    /// Whether `history` should end the process with a non-zero exit code
+    fn fails(self, history: &RunHistory) -> bool {
+        let failed = matches!(history.status, RunStatus::Failed(_));
+        match self {
+            FailOn::None => false,
+            FailOn::Partial => failed && !history.step_results.values().any(|result| result.status == StepStatus::Success),
+            FailOn::Any => failed,
+        }
+    }
+}
+
+impl FromStr for FailOn {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(FailOn::None),
+            "partial" => Ok(FailOn::Partial),
+            "any" => Ok(FailOn::Any),
+            other => Err(format!("unknown fail-on policy '{other}' (expected none, partial or any)")),
+        }
+    }
+}
+
+/// Arguments of `run-flow`
+#[derive(Args)]
+struct RunFlowArgs {
+    /// Path to the flow YAML file (e.g. config/catalog_check.yml), or an
+    /// http(s) URL to fetch it from
+    config: PathBuf,
+
+    /// Which flow to run when the file holds several (`flows:`)
+    #[arg(long = "flow", value_name = "ID")]
+    flow_id: Option<String>,
+
+    /// Apply this entry of the flow's `profiles` (e.g. prod) over its
+    /// defaults and inputs
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
+
+    /// Pause before each step and ask whether to run, skip, or abort
+    #[arg(long)]
+    interactive: bool,
+
+    /// Execute nothing: report whether each step would change state
+    /// (would-change, no-change or unknown)
+    #[arg(long)]
+    dry_run: bool,
+
+    /// What to do if the flow's concurrency group is busy: queue or reject
+    #[arg(long, default_value = "queue")]
+    on_conflict: OnConflict,
+
+    /// Refuse to run if any step kind has no registered handler
+    #[arg(long)]
+    strict_kinds: bool,
+
+    /// Run only this step and the steps it depends on
+    #[arg(long, conflicts_with_all = ["from", "to"])]
+    step: Option<String>,
+
+    /// Run this step and everything that depends on it
+    #[arg(long)]
+    from: Option<String>,
+
+    /// Run this step and everything it depends on
+    #[arg(long)]
+    to: Option<String>,
+
+    /// Run steps labeled LABEL and everything they depend on (repeatable;
+    /// the selections are combined)
+    #[arg(long = "select-tag", value_name = "LABEL")]
+    select_tags: Vec<String>,
+
+    /// Refuse to run a flow with more than N steps
+    #[arg(long, value_name = "N")]
+    max_steps: Option<usize>,
+
+    /// Refuse to run a flow without steps (otherwise just a warning)
+    #[arg(long)]
+    strict: bool,
+
+    /// Reuse step outputs cached in this directory by earlier runs, and
+    /// cache the outputs of steps that succeed (only steps with `cache: true`
+    /// or an `idempotency_key`)
+    #[arg(long, value_name = "PATH")]
+    cache_dir: Option<PathBuf>,
+
+    /// Rerun only the steps that failed in this saved run history (`--output`),
+    /// keeping every other step's result from it (truncated outputs aside,
+    /// whose steps run again). Steps skipped because of a failure stay
+    /// unrun; use `resume` to run them too.
+    #[arg(long, value_name = "HISTORY")]
+    only_failed: Option<PathBuf>,
+
+    /// Truncate step outputs longer than this many bytes in the printed and
+    /// saved results (later steps still get them in full)
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_OUTPUT_BYTES)]
+    max_output_bytes: usize,
+
+    /// Save the run history to this SQLite database after the run
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Timeout (seconds) for steps that don't set `timeout_seconds`
+    #[arg(long, value_name = "SECONDS")]
+    step_timeout_default: Option<u64>,
+
+    /// Retry steps that have no `retry` policy, up to this many attempts
+    #[arg(long, value_name = "N")]
+    default_max_attempts: Option<usize>,
+
+    /// Seconds between attempts for `--default-max-attempts` retries
+    #[arg(long, value_name = "SECONDS", default_value_t = 5)]
+    default_backoff_seconds: u64,
+
+    /// Most retries allowed across all steps of the run; after that, failing
+    /// steps aren't retried
+    #[arg(long, value_name = "N")]
+    retry_budget: Option<usize>,
+
+    /// Timeout (seconds) for the whole run
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Load KEY=VALUE lines from this file into the environment before the
+    /// run, for `{{ env.<KEY> }}` in step config (repeatable; variables
+    /// already set win unless `--env-file-override`)
+    #[arg(long = "env-file", value_name = "PATH")]
+    env_files: Vec<PathBuf>,
+
+    /// Let `--env-file` values replace variables already in the environment
+    #[arg(long, requires = "env_files")]
+    env_file_override: bool,
+
+    /// Value for a flow input, as NAME=VALUE (repeatable)
+    #[arg(long = "input", value_name = "NAME=VALUE", value_parser = parse_input)]
+    inputs: Vec<(String, String)>,
+
+    /// How many independent steps may run at once
+    #[arg(long, default_value_t = 1)]
+    max_parallel: usize,
+
+    /// Cap on simultaneous steps of one kind, as KIND=N (repeatable;
+    /// overrides the flow's `concurrency` block)
+    #[arg(long = "kind-limit", value_name = "KIND=N", value_parser = parse_kind_limit)]
+    kind_limits: Vec<(String, usize)>,
+
+    /// Print a single JSON summary to stdout instead of the human output
+    #[arg(long, conflicts_with = "interactive")]
+    json: bool,
+
+    /// Write the full run history to this file (see `resume`): YAML if it
+    /// ends in `.yml`/`.yaml`, JSON otherwise
+    #[arg(long, value_name = "PATH")]
+    output: Option<PathBuf>,
+
+    /// After the run, print one line per step saying why it ran, was
+    /// skipped, or failed
+    #[arg(long, conflicts_with = "json")]
+    explain: bool,
+
+    /// Print only the final status line (logs on stderr are unaffected)
+    #[arg(short, long, conflicts_with_all = ["json", "explain"])]
+    quiet: bool,
+
+    /// As each step starts, print its kind, dependencies, retry policy and
+    /// compensation; `-vv` also prints the config it runs with
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with_all = ["json", "quiet"])]
+    verbose: u8,
+
+    /// After the run, print how many steps of each `stage` succeeded,
+    /// failed, or were skipped
+    #[arg(long, conflicts_with_all = ["json", "quiet"])]
+    by_stage: bool,
+
+    /// Keep running: re-run the flow every time its file changes
+    #[arg(long)]
+    watch: bool,
+
+    /// When a finished run exits non-zero: none, partial (only if no step
+    /// succeeded), or any (any step failed)
+    #[arg(long, value_name = "POLICY", default_value = "none")]
+    fail_on: FailOn,
 }
 
 /// Available subcommands
 #[derive(Subcommand)]
 enum Commands {
     /// Load and execute a YAML-based flow definition
-    RunFlow {
-        /// Path to the flow YAML file (e.g. config/catalog_check.yml)
+    RunFlow(Box<RunFlowArgs>),
+
+    /// Re-run a flow from a saved run history (`run-flow --output`),
+    /// keeping the steps that already succeeded
+    Resume {
+        /// Path to the flow YAML file
+        config: PathBuf,
+
+        /// Run history (JSON, or YAML if `.yml`/`.yaml`) of the run to pick up from
+        history: PathBuf,
+
+        /// Write the new run history to this file (YAML or JSON by extension)
+        #[arg(long, value_name = "PATH")]
+        output: Option<PathBuf>,
+
+        /// Truncate step outputs longer than this many bytes in the printed and
+        /// saved results (later steps still get them in full)
+        #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_OUTPUT_BYTES)]
+        max_output_bytes: usize,
+    },
+
+    /// Print a flow in canonical form (topological step order, sorted keys)
+    Normalize {
+        /// Path to the flow YAML file
         config: PathBuf,
     },
+
+    /// Check that a flow loads and forms a valid DAG, without running it;
+    /// config placeholders may only use dependencies' outputs and declared inputs
+    Validate {
+        /// Path to the flow YAML file
+        config: PathBuf,
+
+        /// Which flow to check when the file holds several (`flows:`)
+        #[arg(long = "flow", value_name = "ID")]
+        flow_id: Option<String>,
+
+        /// Also print the longest chain of steps by `estimated_seconds`
+        #[arg(long)]
+        critical_path: bool,
+
+        /// Also print structural stats: steps, edges, roots, leaves, depth, fan-out
+        #[arg(long)]
+        stats: bool,
+
+        /// Also print the flow as a tree, from its root steps down
+        #[arg(long)]
+        tree: bool,
+
+        /// What the tree shows for each step: id, kind, id_kind or description
+        #[arg(long, value_name = "MODE", default_value = "id", requires = "tree")]
+        label: LabelMode,
+
+        /// Also print a hash of the flow's structure (step IDs, kinds, and
+        /// dependencies), to tell whether it changed between versions
+        #[arg(long)]
+        print_graph_hash: bool,
+
+        /// Treat a flow without steps as invalid (otherwise just a warning)
+        #[arg(long)]
+        strict: bool,
+    },
+
+    /// Show how a flow would be scheduled, without running anything: its
+    /// levels, the most steps running at once, and the critical path
+    Plan {
+        /// Path to the flow YAML file
+        config: PathBuf,
+
+        /// Which flow to plan when the file holds several (`flows:`)
+        #[arg(long = "flow", value_name = "ID")]
+        flow_id: Option<String>,
+
+        /// Also print the flow as a tree, from its root steps down
+        #[arg(long)]
+        tree: bool,
+
+        /// What the tree shows for each step: id, kind, id_kind or description
+        #[arg(long, value_name = "MODE", default_value = "id", requires = "tree")]
+        label: LabelMode,
+    },
+
+    /// Compare the structure of two versions of a flow: steps added,
+    /// removed, or changing kind, and dependency edges added or removed
+    Diff {
+        /// Path to the old flow YAML file
+        old: PathBuf,
+
+        /// Path to the new flow YAML file
+        new: PathBuf,
+    },
+
+    /// Check a flow for risky patterns; fails only on error-level findings
+    Lint {
+        /// Path to the flow YAML file
+        config: PathBuf,
+
+        /// Which flow to check when the file holds several (`flows:`)
+        #[arg(long = "flow", value_name = "ID")]
+        flow_id: Option<String>,
+    },
+
+    /// Serve an HTTP API: `POST /flows/run` runs a flow sent in the body,
+    /// `GET /flows/runs/:id` returns a stored run (with `--db`)
+    Serve {
+        /// Address to listen on; anything but loopback exposes the API to
+        /// the network
+        #[arg(long, default_value = "127.0.0.1")]
+        host: IpAddr,
+
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Let posted flows use the shell, script and subflow handlers, i.e.
+        /// run commands on this host
+        #[arg(long)]
+        allow_local_handlers: bool,
+
+        /// Environment variable posted flows may read with `{{ env.VAR }}`
+        /// (repeatable); they can't read any other
+        #[arg(long = "allow-env", value_name = "VAR")]
+        allow_env: Vec<String>,
+
+        /// Save runs to (and look them up in) this SQLite database
+        #[arg(long)]
+        db: Option<PathBuf>,
+
+        /// Truncate step outputs longer than this many bytes in the returned
+        /// and saved results (later steps still get them in full)
+        #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_OUTPUT_BYTES)]
+        max_output_bytes: usize,
+    },
+
+    /// List runs stored in a SQLite database (see `run-flow --db`)
+    History {
+        /// Path to the SQLite database
+        #[arg(long)]
+        db: PathBuf,
+
+        /// Only show runs of this flow
+        #[arg(long)]
+        flow_id: Option<String>,
+
+        /// Show at most this many runs (newest first)
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Show the step-by-step breakdown of a single run instead
+        #[arg(long, conflicts_with_all = ["flow_id", "limit"])]
+        run: Option<String>,
+    },
 }
 
 /// Async entrypoint with Tokio runtime
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    // Parse CLI arguments (e.g. `run-flow config/catalog_check.yml`)
+    let cli = Cli::parse();
+    COLOR.store(!cli.no_color && io::stdout().is_terminal(), Ordering::Relaxed);
+
     // Set up structured logging using the `tracing` crate
     // Logs will go to stderr (important for test output and shell scripts)
-    tracing_subscriber::fmt()
-        .with_env_filter("tiny_agent_graph=debug")
+    let logs = tracing_subscriber::fmt::layer()
         .with_writer(std::io::stderr) // ✅ Ensure logs go to stderr
-        .init();
+        .with_ansi(!cli.no_color && io::stderr().is_terminal());
+    let logs = match cli.log_format {
+        LogFormat::Text => logs.boxed(),
+        LogFormat::Json => logs.json().boxed(),
+    };
+    let subscriber = tracing_subscriber::registry()
+        .with(logs)
+        .with(EnvFilter::new(format!("tiny_agent_graph={}", cli.log_level)));
 
-    // Parse CLI arguments (e.g. `run-flow config/catalog_check.yml`)
-    let cli = Cli::parse();
+    #[cfg(not(feature = "otel"))]
+    subscriber.init();
 
-    match cli.command {
-        Commands::RunFlow { config } => {
-            info!("📄 Loading flow from {:?}", config);
+    // Spans also go to the OTLP collector, if one is configured
+    #[cfg(feature = "otel")]
+    let otel_provider = match &cli.otlp_endpoint {
+        Some(endpoint) => Some(telemetry::otlp_tracer_provider(endpoint)?),
+        None => None,
+    };
+    #[cfg(feature = "otel")]
+    subscriber.with(otel_provider.as_ref().map(telemetry::layer)).init();
 
-            match load_flow(&config) {
+    let result = run_command(cli.command).await;
+
+    // Flush whatever spans are still batched
+    #[cfg(feature = "otel")]
+    if let Some(provider) = otel_provider {
+        let _ = provider.shutdown();
+    }
+
+    result
+}
+
+/// Runs the chosen subcommand
+async fn run_command(command: Commands) -> anyhow::Result<()> {
+    match command {
+        Commands::RunFlow(args) => {
+            for path in &args.env_files {
+                let loaded = if args.env_file_override {
+                    dotenvy::from_path_override(path)
+                } else {
+                    dotenvy::from_path(path)
+                };
+                if let Err(err) = loaded {
+                    error!("❌ Could not load env file {:?}: {err}", path);
+                    std::process::exit(1);
+                }
+            }
+
+            if args.watch && args.config.to_str().is_some_and(is_flow_url) {
+                error!("❌ --watch needs a flow file, not a URL");
+                std::process::exit(1);
+            } else if args.watch {
+                watch_flow(&args).await?;
+            } else if !run_flow_once(&args).await? {
+                std::process::exit(1); // ❗ exit non-zero for CI/tests
+            }
+        }
+        Commands::Resume { config, history, output, max_output_bytes } => {
+            let previous = match read_history(&history) {
+                Ok(previous) => previous,
+                Err(err) => {
+                    error!("❌ Could not read run history {:?}: {err}", history);
+                    std::process::exit(1);
+                }
+            };
+
+            // The history says which flow it was, so `flows:` files need no `--flow`
+            let (flow, graph) = match load_flows(&config).and_then(|flows| select_flow(flows, Some(&previous.flow_id))) {
+                Ok(loaded) => loaded,
+                Err(err) => {
+                    error!("❌ Failed to load flow: {err}");
+                    std::process::exit(1);
+                }
+            };
+
+            println!("⏩ Resuming flow '{}' from run {}\n", flow.id, previous.run_id);
+            let options = RunOptions {
+                cancel_on_ctrl_c: true,
+                resume_from: Some(previous),
+                max_output_bytes: Some(max_output_bytes),
+                ..Default::default()
+            };
+            let result = run_flow_with_options(&flow, graph, &options).await?;
+
+            if let Some(output) = &output {
+                write_history(output, &result)?;
+            }
+            print_run_result(&flow, &result);
+        }
+        Commands::Normalize { config } => {
+            let normalized = std::fs::read_to_string(&config)
+                .map_err(flow::FlowError::from)
+                .and_then(|yaml| normalize_flow(&yaml));
+
+            match normalized {
+                Ok(yaml) => print!("{yaml}"),
+                Err(err) => {
+                    error!("❌ Failed to normalize flow: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Validate { config, flow_id, critical_path: show_critical_path, stats, tree, label, print_graph_hash, strict } => {
+            let loaded = load_flows(&config)
+                .and_then(|flows| select_flow(flows, flow_id.as_deref()))
+                .and_then(|(flow, graph)| {
+                    if strict {
+                        validate_not_empty(&flow)?;
+                    }
+                    validate_configs(&flow, &HandlerRegistry::with_builtins())?;
+                    validate_templates(&flow, &graph)?;
+                    Ok((flow, graph))
+                });
+            match loaded {
                 Ok((flow, graph)) => {
-                    println!("✅ Loaded flow '{}'", flow.id);
-                    println!("🔢 Total steps: {}\n", graph.node_count());
-
-                    let result = run_flow(&flow, graph).await?;
-
-                    println!("🎯 Final status: {:?}", result.status);
-                    println!("\n📋 Step results:");
-
-                    for (step_id, outcome) in result.step_results.iter() {
-                        match &outcome.status {
-                            StepStatus::Success => {
-                                println!("✅ {} → {}", step_id, outcome.output.as_deref().unwrap_or("✓"));
-                            }
-                            StepStatus::Failed(err) => {
-                                println!("❌ {} → Failed: {}", step_id, err);
-                            }
-                        }
+                    println!("✅ Flow '{}' is valid ({} steps)", flow.id, graph.node_count());
+
+                    if show_critical_path {
+                        let (path, seconds) = critical_path(&flow, &graph);
+                        println!("⏱️  Critical path ({seconds}s): {}", path.join(" → "));
+                    }
+
+                    if stats {
+                        print_flow_stats(&flow_stats(&graph));
+                    }
+
+                    if tree {
+                        print_tree(&graph, label);
                     }
 
-                    // Future:
-                    // - Export RunHistory to file (JSON/YAML)
-                    // - Record to SQLite
-                    // - Expose as an API (e.g. via MCP or HTTP)
+                    if print_graph_hash {
+                        println!("🔑 Graph hash: {}", graph_hash(&graph));
+                    }
                 }
                 Err(err) => {
-                    error!("❌ Failed to load flow: {err}");
-                    std::process::exit(1); // ❗ exit non-zero for CI/tests
+                    error!("❌ Invalid flow: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Plan { config, flow_id, tree, label } => {
+            match load_flows(&config).and_then(|flows| select_flow(flows, flow_id.as_deref())) {
+                Ok((flow, graph)) => {
+                    print_plan(&flow, &graph);
+                    if tree {
+                        print_tree(&graph, label);
+                    }
+                }
+                Err(err) => {
+                    error!("❌ Invalid flow: {err}");
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Diff { old, new } => {
+            let load_graph = |path: &PathBuf| load_flows(path).and_then(|flows| select_flow(flows, None)).map(|(_, graph)| graph);
+            let (old_graph, new_graph) = match load_graph(&old).and_then(|old_graph| Ok((old_graph, load_graph(&new)?))) {
+                Ok(graphs) => graphs,
+                Err(err) => {
+                    error!("❌ Invalid flow: {err}");
+                    std::process::exit(1);
+                }
+            };
+
+            let changes = diff_graphs(&old_graph, &new_graph);
+            if changes.is_empty() {
+                println!("✅ No structural changes");
+            }
+            for change in &changes {
+                let color = match change {
+                    GraphChange::AddedStep(_) | GraphChange::AddedEdge(..) => Color::Green,
+                    GraphChange::RemovedStep(_) | GraphChange::RemovedEdge(..) => Color::Red,
+                    GraphChange::ChangedKind { .. } => Color::Yellow,
+                };
+                println!("{}", paint(color, change));
+            }
+        }
+        Commands::Lint { config, flow_id } => {
+            let (flow, graph) = match load_flows(&config).and_then(|flows| select_flow(flows, flow_id.as_deref())) {
+                Ok(loaded) => loaded,
+                Err(err) => {
+                    error!("❌ Invalid flow: {err}");
+                    std::process::exit(1);
+                }
+            };
+
+            let findings = lint_flow(&flow, &graph);
+            if findings.is_empty() {
+                println!("✅ No lint findings for flow '{}'", flow.id);
+            }
+            for finding in &findings {
+                println!("{finding}");
+            }
+
+            if has_errors(&findings) {
+                std::process::exit(1);
+            }
+        }
+        Commands::Serve { host, port, db, allow_local_handlers, allow_env, max_output_bytes } => {
+            let store = match &db {
+                Some(db) => Some(SqliteStore::open(db).await?),
+                None => None,
+            };
+            let state = server::ServerState {
+                store,
+                allow_local_handlers,
+                allowed_env: allow_env,
+                max_output_bytes,
+            };
+            server::serve(SocketAddr::new(host, port), state).await?;
+        }
+        Commands::History { db, flow_id, limit, run } => {
+            let store = SqliteStore::open(&db).await?;
+
+            match run {
+                Some(run_id) => match store.get_run(&run_id).await? {
+                    Some(history) => print_run_details(&history),
+                    None => {
+                        error!("❌ No run '{run_id}' in {:?}", db);
+                        std::process::exit(1);
+                    }
+                },
+                None => {
+                    let runs = store.list_runs(flow_id.as_deref(), limit).await?;
+                    print_run_table(&runs);
                 }
             }
         }
@@ -80,3 +716,608 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Parses `--input NAME=VALUE` (the value may itself contain `=`)
+fn parse_input(raw: &str) -> Result<(String, String), String> {
+    match raw.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got '{raw}'")),
+    }
+}
+
+/// Parses `--kind-limit KIND=N`
+fn parse_kind_limit(raw: &str) -> Result<(String, usize), String> {
+    let (kind, limit) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected KIND=N, got '{raw}'"))?;
+    match limit.parse::<usize>() {
+        Ok(limit) if limit > 0 && !kind.is_empty() => Ok((kind.to_string(), limit)),
+        _ => Err(format!("expected KIND=N with N at least 1, got '{raw}'")),
+    }
+}
+
+/// Whether a history file is YAML (`.yml`/`.yaml`) rather than JSON
+fn is_yaml_path(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("yml") || ext.eq_ignore_ascii_case("yaml"))
+}
+
+/// Saves a run history as YAML for `.yml`/`.yaml` paths, pretty JSON
+/// otherwise (`resume` reads either)
+fn write_history(path: &Path, history: &RunHistory) -> anyhow::Result<()> {
+    let contents = if is_yaml_path(path) {
+        serde_yaml::to_string(history)?
+    } else {
+        serde_json::to_string_pretty(history)?
+    };
+    std::fs::write(path, contents)?;
+    info!("💾 Wrote run history to {:?}", path);
+    Ok(())
+}
+
+/// Loads a run history saved by `write_history`, in either format
+fn read_history(path: &Path) -> anyhow::Result<RunHistory> {
+    let contents = std::fs::read_to_string(path)?;
+    if is_yaml_path(path) {
+        Ok(serde_yaml::from_str(&contents)?)
+    } else {
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+/// One `run-flow`: load, run, and report the flow
+///
+/// Returns `false` if the flow couldn't be loaded or set up, or the run
+/// failed by `--fail-on` (already reported), so the caller decides whether
+/// that ends the process.
+async fn run_flow_once(args: &RunFlowArgs) -> anyhow::Result<bool> {
+    info!("📄 Loading flow from {:?}", args.config);
+
+    let flows = match args.config.to_str().filter(|source| is_flow_url(source)) {
+        Some(url) => load_flows_from_url(url).await,
+        None => load_flows(&args.config),
+    };
+    let (mut flow, graph) = match flows.and_then(|flows| select_flow(flows, args.flow_id.as_deref())) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            error!("❌ Failed to load flow: {err}");
+            return Ok(false);
+        }
+    };
+
+    if let Some(profile) = &args.profile {
+        if let Err(err) = flow.apply_profile(profile) {
+            error!("❌ Failed to load flow: {err}");
+            return Ok(false);
+        }
+    }
+
+    if let Some(max) = args.max_steps {
+        if let Err(err) = validate_step_count(&flow, max) {
+            error!("❌ Failed to load flow: {err}");
+            return Ok(false);
+        }
+    }
+
+    if args.strict {
+        if let Err(err) = validate_not_empty(&flow) {
+            error!("❌ Failed to load flow: {err}");
+            return Ok(false);
+        }
+    }
+
+    let previous = match &args.only_failed {
+        Some(path) => match read_history(path) {
+            Ok(previous) => Some(previous),
+            Err(err) => {
+                error!("❌ Could not read run history {:?}: {err}", path);
+                return Ok(false);
+            }
+        },
+        None => None,
+    };
+
+    let options = RunOptions {
+        step_gate: args.interactive.then(interactive_gate),
+        on_event: (args.verbose > 0).then(|| verbose_printer(&flow, args.verbose)),
+        dry_run: args.dry_run,
+        cache_dir: args.cache_dir.clone(),
+        max_output_bytes: Some(args.max_output_bytes),
+        only_failed: previous.is_some(),
+        resume_from: previous,
+        on_conflict: args.on_conflict,
+        selection: StepSelection {
+            step: args.step.clone(),
+            from: args.from.clone(),
+            to: args.to.clone(),
+            tags: args.select_tags.clone(),
+        },
+        default_step_timeout: args.step_timeout_default,
+        default_retry: args.default_max_attempts.map(|max_attempts| RetryPolicy {
+            max_attempts,
+            backoff_seconds: args.default_backoff_seconds,
+            retry_on: None,
+        }),
+        retry_budget: args.retry_budget,
+        flow_timeout_seconds: args.timeout,
+        cancel_on_ctrl_c: true,
+        inputs: args
+            .inputs
+            .iter()
+            .map(|(name, value)| (name.clone(), serde_yaml::Value::String(value.clone())))
+            .collect(),
+        max_parallel: args.max_parallel,
+        kind_limits: args.kind_limits.iter().cloned().collect(),
+        ..Default::default()
+    };
+
+    if let Err(err) = resolve_inputs(&flow, &options.inputs) {
+        error!("❌ Invalid inputs: {err}");
+        return Ok(false);
+    }
+
+    if args.strict_kinds {
+        if let Err(err) = validate_kinds(&flow, &options.registry) {
+            error!("❌ Failed to load flow: {err}");
+            return Ok(false);
+        }
+    }
+
+    if let Err(err) = validate_configs(&flow, &options.registry) {
+        error!("❌ Failed to load flow: {err}");
+        return Ok(false);
+    }
+
+    if !args.json && !args.quiet {
+        println!("✅ Loaded flow '{}'", flow.id);
+        println!("🔢 Total steps: {}\n", graph.node_count());
+        if !options.selection.is_empty() {
+            println!("✂️  Running a subset of steps: {:?}\n", options.selection);
+        }
+    }
+
+    let result = run_flow_with_options(&flow, graph, &options).await?;
+
+    if let Some(db) = &args.db {
+        SqliteStore::open(db).await?.save_run(&result).await?;
+        info!("💾 Saved run {} to {:?}", result.run_id, db);
+    }
+
+    if let Some(output) = &args.output {
+        write_history(output, &result)?;
+    }
+
+    let passed = !args.fail_on.fails(&result);
+    if !passed {
+        error!("❌ Run {} failed under the --fail-on policy", result.run_id);
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&run_summary_json(&flow, &result))?);
+        return Ok(passed);
+    }
+
+    if args.quiet {
+        print_final_status(&result);
+        return Ok(passed);
+    }
+
+    print_run_result(&flow, &result);
+    if args.by_stage {
+        print_stage_tallies(&stage_tallies(&flow, &result));
+    }
+    if args.explain {
+        print_explanation(&flow, &result);
+    }
+
+    if let Some(db) = &args.db {
+        println!("\n💾 Saved run {} to {:?}", result.run_id, db);
+    }
+    Ok(passed)
+}
+
+/// `run-flow --watch`: runs the flow, then again after every change to its
+/// file, until Ctrl-C
+///
+/// Bursts of events (editors often write a file in several steps) count as a
+/// single change. Load and run errors are reported and the watch goes on.
+async fn watch_flow(args: &RunFlowArgs) -> anyhow::Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = ::notify::recommended_watcher(move |event: ::notify::Result<Event>| {
+        if let Ok(event) = event {
+            if event.kind.is_modify() || event.kind.is_create() {
+                let _ = tx.send(event);
+            }
+        }
+    })?;
+
+    // Watch the directory rather than the file: saving by replacing the file
+    // (as many editors do) would silently end a watch on the file itself
+    let file_name = args.config.file_name().map(|name| name.to_os_string());
+    let dir = match args.config.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+    loop {
+        if let Err(err) = run_flow_once(args).await {
+            error!("❌ Run failed: {err}");
+        }
+        println!("\n👀 Watching {:?} for changes (Ctrl-C to stop)", args.config);
+
+        // Wait for a change to the flow file itself
+        loop {
+            let event = tokio::select! {
+                event = rx.recv() => event,
+                _ = tokio::signal::ctrl_c() => return Ok(()),
+            };
+            let Some(event) = event else {
+                return Ok(());
+            };
+            if event.paths.iter().any(|path| path.file_name() == file_name.as_deref()) {
+                break;
+            }
+        }
+
+        // Debounce: let the burst settle before loading the file again
+        while let Ok(Some(_)) = tokio::time::timeout(WATCH_DEBOUNCE, rx.recv()).await {}
+
+        println!("\n{}", "─".repeat(60));
+        println!("🔁 {:?} changed, running again\n", args.config);
+    }
+}
+
+/// Prints the one-line final status (all `--quiet` prints)
+fn print_final_status(result: &RunHistory) {
+    let color = match result.status {
+        RunStatus::Success => Color::Green,
+        RunStatus::Failed(_) => Color::Red,
+    };
+    println!("{}", paint(color, format!("🎯 Final status: {:?}", result.status)));
+}
+
+/// Prints the final status and each step's outcome
+fn print_run_result(flow: &Flow, result: &RunHistory) {
+    print_final_status(result);
+    if let Some(note) = &result.note {
+        println!("📝 {note}");
+    }
+    println!("\n📋 Step results:");
+
+    // With stages, results are grouped under them (in `stage_tallies` order)
+    let stages: Vec<Option<String>> = stage_tallies(flow, result).into_iter().map(|tally| tally.stage).collect();
+    let staged = stages.iter().any(Option::is_some);
+    let mut ordered = result.ordered_step_results();
+    if staged {
+        ordered.sort_by_key(|(step_id, _)| stages.iter().position(|stage| stage.as_deref() == stage_of(flow, step_id)));
+    }
+
+    let mut current_stage = None;
+    for (step_id, outcome) in ordered {
+        if staged && current_stage != Some(stage_of(flow, step_id)) {
+            current_stage = Some(stage_of(flow, step_id));
+            println!("📦 {}", current_stage.flatten().unwrap_or("(no stage)"));
+        }
+        let level = outcome.level;
+        let line = match &outcome.status {
+            StepStatus::Success => match outcome.change {
+                Some(change) => match describe(flow, step_id) {
+                    Some(description) => format!("✅ {} → {change} (level {level}) — {description}", step_id),
+                    None => format!("✅ {} → {change} (level {level})", step_id),
+                },
+                None if outcome.from_cache => {
+                    format!("✅ {} → {} (cached, level {level})", step_id, outcome.output.as_deref().unwrap_or("✓"))
+                }
+                None => format!("✅ {} → {} (level {level})", step_id, outcome.output.as_deref().unwrap_or("✓")),
+            },
+            StepStatus::Failed(kind, err) => format!("❌ {} → Failed ({kind}): {} (level {level})", step_id, err),
+            StepStatus::Skipped(reason) => format!("⏭️ {} → Skipped: {} (level {level})", step_id, reason),
+            StepStatus::Cancelled => format!("🛑 {} → Cancelled (level {level})", step_id),
+        };
+        println!("{}", paint(status_color(&outcome.status), line));
+    }
+
+    if !result.rollback.is_empty() {
+        println!("\n↩️ Rolled back:");
+        for compensated in &result.rollback {
+            let line = match &compensated.status {
+                StepStatus::Failed(_, err) => format!("❌ {} ({}) → Failed: {err}", compensated.step_id, compensated.kind),
+                _ => format!("✅ {} ({})", compensated.step_id, compensated.kind),
+            };
+            println!("{}", paint(status_color(&compensated.status), line));
+        }
+    }
+}
+
+/// Prints the numbers from `flow_stats` (`validate --stats`)
+fn print_flow_stats(stats: &FlowStats) {
+    println!("📊 Stats:");
+    println!("   steps:       {}", stats.nodes);
+    println!("   edges:       {}", stats.edges);
+    println!("   roots:       {}", stats.roots);
+    println!("   leaves:      {}", stats.leaves);
+    println!("   max depth:   {}", stats.max_depth);
+    println!("   max fan-out: {}", stats.max_fan_out);
+}
+
+/// Prints a flow's scheduling levels, peak parallelism, and estimated duration
+fn print_plan(flow: &Flow, graph: &StepGraph) {
+    let levels = execution_levels(graph);
+    println!("📋 Plan for flow '{}' ({} steps)", flow.id, graph.node_count());
+    for (level, steps) in levels.iter().enumerate() {
+        println!("   level {level} ({}): {}", steps.len(), steps.join(", "));
+    }
+
+    let max_parallelism = levels.iter().map(Vec::len).max().unwrap_or(0);
+    println!("🔀 Levels: {}, max parallelism: {max_parallelism}", levels.len());
+
+    let (path, seconds) = critical_path(flow, graph);
+    let serial: u64 = flow.nodes.iter().filter_map(|step| step.estimated_seconds).sum();
+    println!("⏱️  Critical path ({seconds}s): {}", path.join(" → "));
+    println!("⌛ Estimated duration: {seconds}s fully parallel, {serial}s serial");
+}
+
+/// Prints the flow as a tree (`--tree`)
+fn print_tree(graph: &StepGraph, labels: LabelMode) {
+    println!("🌳 Tree:");
+    for line in to_ascii_tree(graph, labels).lines() {
+        println!("   {line}");
+    }
+}
+
+/// Prints each step's `reason`, in declaration order (`--explain`)
+fn print_explanation(flow: &Flow, result: &RunHistory) {
+    println!("\n🔎 Why:");
+    for step in &flow.nodes {
+        if let Some(outcome) = result.step_results.get(&step.id) {
+            println!("   {} → {}", step.id, outcome.reason.as_deref().unwrap_or("(no reason recorded)"));
+        }
+    }
+}
+
+/// Short human label for a run status
+fn run_status_label(status: &RunStatus) -> &'static str {
+    match status {
+        RunStatus::Success => "success",
+        RunStatus::Failed(_) => "failed",
+    }
+}
+
+/// The flow's definition of a step
+fn declared<'a>(flow: &'a Flow, step_id: &str) -> Option<&'a Step> {
+    flow.nodes.iter().find(|step| step.id == step_id)
+}
+
+/// A step's `description`, if it has one
+fn describe<'a>(flow: &'a Flow, step_id: &str) -> Option<&'a str> {
+    declared(flow, step_id).and_then(|step| step.description.as_deref())
+}
+
+/// A step's `stage`, if it has one
+fn stage_of<'a>(flow: &'a Flow, step_id: &str) -> Option<&'a str> {
+    declared(flow, step_id).and_then(|step| step.stage.as_deref())
+}
+
+/// Prints the per-stage counts from `stage_tallies` (`--by-stage`)
+fn print_stage_tallies(tallies: &[StageTally]) {
+    println!("\n📦 By stage:");
+    for tally in tallies {
+        let mut line = format!(
+            "   {}: {} succeeded, {} failed, {} skipped",
+            tally.stage.as_deref().unwrap_or("(no stage)"),
+            tally.success,
+            tally.failed,
+            tally.skipped
+        );
+        if tally.cancelled > 0 {
+            line.push_str(&format!(", {} cancelled", tally.cancelled));
+        }
+        println!("{line}");
+    }
+}
+
+/// Machine-readable summary of a run for `run-flow --json` (steps sorted by ID)
+fn run_summary_json(flow: &Flow, history: &RunHistory) -> serde_json::Value {
+    let mut step_ids: Vec<&String> = history.step_results.keys().collect();
+    step_ids.sort();
+
+    let steps: Vec<serde_json::Value> = step_ids
+        .into_iter()
+        .map(|step_id| {
+            let result = &history.step_results[step_id];
+            serde_json::json!({
+                "id": step_id,
+                "description": describe(flow, step_id),
+                "stage": stage_of(flow, step_id),
+                "status": result.status,
+                "output": result.output,
+                "diagnostics": result.diagnostics,
+                "reason": result.reason,
+                "level": result.level,
+                "attempts": result.attempts,
+                "change": result.change,
+                "from_cache": result.from_cache,
+                "output_bytes": result.output_bytes,
+            })
+        })
+        .collect();
+
+    serde_json::json!({
+        "run_id": history.run_id,
+        "flow_id": history.flow_id,
+        "status": history.status,
+        "steps": steps,
+        "stages": stage_tallies(flow, history),
+    })
+}
+
+/// Prints stored runs as an aligned table
+fn print_run_table(runs: &[RunRecord]) {
+    if runs.is_empty() {
+        println!("No runs found.");
+        return;
+    }
+
+    let id_width = runs.iter().map(|r| r.run_id.len()).max().unwrap_or(0).max("RUN ID".len());
+    let flow_width = runs.iter().map(|r| r.flow_id.len()).max().unwrap_or(0).max("FLOW".len());
+
+    println!("{:<id_width$}  {:<flow_width$}  {:<7}  STARTED", "RUN ID", "FLOW", "STATUS");
+    for run in runs {
+        println!(
+            "{:<id_width$}  {:<flow_width$}  {:<7}  {}",
+            run.run_id,
+            run.flow_id,
+            run_status_label(&run.status),
+            run.started_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        );
+    }
+}
+
+/// Prints a single stored run with its step results (in topological order)
+fn print_run_details(history: &RunHistory) {
+    println!("🆔 Run: {}", history.run_id);
+    println!("📄 Flow: {}", history.flow_id);
+    println!("🎯 Status: {:?}", history.status);
+    println!(
+        "🕒 {} → {}",
+        history.started_at.format("%Y-%m-%d %H:%M:%S UTC"),
+        history.finished_at.format("%Y-%m-%d %H:%M:%S UTC"),
+    );
+    println!("\n📋 Step results:");
+
+    let ordered = history.ordered_step_results();
+    let width = ordered.iter().map(|(id, _)| id.len()).max().unwrap_or(0);
+    for (step_id, result) in ordered {
+        println!(
+            "  {:<width$}  {}  {}",
+            step_id,
+            paint(status_color(&result.status), format!("{:<7}", result.status.state())),
+            result
+                .status
+                .reason()
+                .or(result.output.as_deref())
+                .unwrap_or(""),
+        );
+    }
+}
+
+/// Event listener for `--verbose`: prints each step's definition as it
+/// starts, plus (from `-vv`) its config with placeholders filled in, and how
+/// many attempts it took once it's done
+fn verbose_printer(flow: &Flow, verbosity: u8) -> EventCallback {
+    let steps: HashMap<String, Step> = flow.nodes.iter().map(|step| (step.id.clone(), step.clone())).collect();
+
+    Arc::new(move |event| {
+        let (step_id, config) = match event {
+            RunEvent::StepStarted { step_id, config } => (step_id, config),
+            RunEvent::StepFinished { step_id, result } if result.attempts > 0 => {
+                let plural = if result.attempts == 1 { "" } else { "s" };
+                println!("🔍 {step_id}: {} after {} attempt{plural}", result.status.state(), result.attempts);
+                return;
+            }
+            _ => return,
+        };
+        let Some(step) = steps.get(&step_id) else {
+            return;
+        };
+
+        println!("🔍 {} ({})", step.id, step.kind);
+        if let Some(description) = &step.description {
+            println!("   description:  {description}");
+        }
+        println!("   depends on:   {}", describe_dependencies(step));
+        match &step.retry {
+            Some(retry) => println!(
+                "   retry:        {} attempts, {}s apart",
+                retry.max_attempts, retry.backoff_seconds
+            ),
+            None => println!("   retry:        none"),
+        }
+        match &step.compensation {
+            Some(compensation) => println!("   compensation: {}", compensation.kind),
+            None => println!("   compensation: none"),
+        }
+
+        if verbosity > 1 {
+            match serde_yaml::to_string(&config) {
+                Ok(_) if config.is_null() => println!("   config:       (none)"),
+                Ok(yaml) => {
+                    println!("   config:");
+                    for line in yaml.lines() {
+                        println!("     {line}");
+                    }
+                }
+                Err(err) => println!("   config:       (could not be rendered: {err})"),
+            }
+        }
+    })
+}
+
+/// A step's `depends_on` (noting `on: completion` edges) and `on_failure`
+/// lists as one line, e.g. `login, fetch (on completion); on failure of: store`
+fn describe_dependencies(step: &Step) -> String {
+    let mut parts: Vec<String> = Vec::new();
+    if !step.depends_on.is_empty() {
+        let deps: Vec<String> = step
+            .depends_on
+            .iter()
+            .map(|dep| {
+                let target = match &dep.target {
+                    DependencyTarget::Step(id) => id.clone(),
+                    DependencyTarget::Label(label) => format!("steps labeled '{label}'"),
+                };
+                match dep.on {
+                    DependOn::Success => target,
+                    DependOn::Completion => format!("{target} (on completion)"),
+                }
+            })
+            .collect();
+        parts.push(deps.join(", "));
+    }
+    if !step.on_failure.is_empty() {
+        parts.push(format!("on failure of: {}", step.on_failure.join(", ")));
+    }
+
+    if parts.is_empty() {
+        "(none)".to_string()
+    } else {
+        parts.join("; ")
+    }
+}
+
+/// Step gate for `--interactive`: shows the step and its resolved config,
+/// then asks on stdin whether to run it, skip it, or abort the whole run.
+///
+/// EOF on stdin is treated as abort, so a piped script can't run steps it
+/// didn't explicitly approve.
+fn interactive_gate() -> StepGate {
+    Arc::new(|step: &Step| {
+        println!("\n⏸️  Next step '{}' ({})", step.id, step.kind);
+        match serde_yaml::to_string(&step.config) {
+            Ok(config) if !step.config.is_null() => print!("{config}"),
+            Ok(_) => println!("(no config)"),
+            Err(err) => println!("(config could not be rendered: {err})"),
+        }
+
+        let stdin = io::stdin();
+        loop {
+            print!("[r]un / [s]kip / [a]bort > ");
+            io::stdout().flush().ok();
+
+            let mut answer = String::new();
+            match stdin.lock().read_line(&mut answer) {
+                Ok(0) | Err(_) => return StepDecision::Abort,
+                Ok(_) => {}
+            }
+
+            match answer.trim() {
+                "r" | "run" => return StepDecision::Run,
+                "s" | "skip" => return StepDecision::Skip,
+                "a" | "abort" => return StepDecision::Abort,
+                other => println!("Unknown choice '{other}', expected r, s or a"),
+            }
+        }
+    })
+}
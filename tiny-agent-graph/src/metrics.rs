@@ -0,0 +1,60 @@
+//! Run and step metrics for Prometheus (feature `metrics`)
+//!
+//! The engine records into whatever global `metrics` recorder is installed;
+//! `prometheus_handle` installs one that can render the Prometheus text format.
+
+#![allow(dead_code)] // The CLI records metrics but has nowhere to serve them yet
+
+use crate::engine::{RunHistory, RunStatus, StepStatus};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::warn;
+
+/// Counts a finished step and its duration, labeled by `kind`
+///
+/// Only steps that actually executed are counted (not skipped or cached ones).
+pub fn record_step(kind: &str, status: &StepStatus, duration: Duration) {
+    let kind = kind.to_string();
+    match status {
+        StepStatus::Success => ::metrics::counter!("steps_succeeded_total", "kind" => kind.clone()).increment(1),
+        StepStatus::Failed(..) => ::metrics::counter!("steps_failed_total", "kind" => kind.clone()).increment(1),
+        StepStatus::Skipped(_) | StepStatus::Cancelled => return,
+    }
+    ::metrics::histogram!("step_duration_seconds", "kind" => kind).record(duration.as_secs_f64());
+}
+
+/// Counts a finished run and its duration, labeled by `flow_id` and `status`
+pub fn record_run(history: &RunHistory) {
+    let status = match history.status {
+        RunStatus::Success => "success",
+        RunStatus::Failed(_) => "failed",
+    };
+    ::metrics::counter!("runs_total", "flow_id" => history.flow_id.clone(), "status" => status).increment(1);
+
+    let elapsed = (history.finished_at - history.started_at).to_std().unwrap_or_default();
+    ::metrics::histogram!("run_duration_seconds", "flow_id" => history.flow_id.clone())
+        .record(elapsed.as_secs_f64());
+}
+
+/// Handle to the Prometheus recorder, installing it globally on first use
+///
+/// If some other global recorder got there first, the handle still works but
+/// stays empty.
+pub fn prometheus_handle() -> &'static PrometheusHandle {
+    static HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+
+    HANDLE.get_or_init(|| {
+        let recorder = PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        if let Err(err) = ::metrics::set_global_recorder(recorder) {
+            warn!("⚠️ Could not install the Prometheus recorder: {err}");
+        }
+        handle
+    })
+}
+
+/// Everything recorded so far, in Prometheus text exposition format
+pub fn render_prometheus() -> String {
+    prometheus_handle().render()
+}
@@ -0,0 +1,84 @@
+#![allow(dead_code)] // Only the engine uses this so far
+
+use crate::engine::{RunHistory, RunStatus, StepStatus};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// How long a webhook gets to answer before we give up on it
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Flow-level `notify:` block — where to report finished runs, and which ones
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct NotifyConfig {
+    /// Receives a JSON POST per matching run (e.g. a Slack incoming webhook)
+    pub webhook_url: String,
+
+    /// Which run outcomes trigger a notification
+    #[serde(default)]
+    pub on: NotifyOn,
+}
+
+/// Which run outcomes to notify about
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotifyOn {
+    #[default]
+    Always,
+    OnFailure,
+    OnSuccess,
+}
+
+impl NotifyOn {
+    /// Whether a run that ended with `status` should be reported
+    pub fn matches(self, status: &RunStatus) -> bool {
+        match self {
+            NotifyOn::Always => true,
+            NotifyOn::OnFailure => matches!(status, RunStatus::Failed(_)),
+            NotifyOn::OnSuccess => matches!(status, RunStatus::Success),
+        }
+    }
+}
+
+/// The JSON body POSTed to the webhook (failed step IDs sorted)
+pub fn notification_payload(history: &RunHistory) -> serde_json::Value {
+    let mut failed_steps: Vec<&String> = history
+        .step_results
+        .iter()
+        .filter(|(_, result)| matches!(result.status, StepStatus::Failed(..)))
+        .map(|(step_id, _)| step_id)
+        .collect();
+    failed_steps.sort();
+
+    serde_json::json!({
+        "run_id": history.run_id,
+        "flow_id": history.flow_id,
+        "status": history.status,
+        "failed_steps": failed_steps,
+    })
+}
+
+/// Sends the run summary to the webhook if `config.on` matches the outcome
+///
+/// Posts with `client` (the registry's shared one, see
+/// `HandlerRegistry::http_client`). Notifying is best effort: errors are
+/// logged, never returned, so a flaky webhook can't change how a run is
+/// reported.
+pub async fn notify_run(client: &reqwest::Client, config: &NotifyConfig, history: &RunHistory) {
+    if !config.on.matches(&history.status) {
+        return;
+    }
+
+    let response = client
+        .post(&config.webhook_url)
+        .timeout(WEBHOOK_TIMEOUT)
+        .json(&notification_payload(history))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+
+    match response {
+        Ok(_) => info!("📣 Notified {} about run {}", config.webhook_url, history.run_id),
+        Err(err) => warn!("⚠️ Could not notify {}: {err}", config.webhook_url),
+    }
+}
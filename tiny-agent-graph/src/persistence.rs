@@ -0,0 +1,309 @@
+#![allow(dead_code)] // Some queries are only used by tests and embedders so far
+
+use crate::engine::{FailureKind, RunHistory, RunStatus, StepResult, StepStatus};
+use chrono::{DateTime, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions, SqliteRow};
+use sqlx::Row;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+const CREATE_RUNS: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    run_id TEXT PRIMARY KEY,
+    flow_id TEXT NOT NULL,
+    status TEXT NOT NULL,       -- success | failed
+    reason TEXT,
+    started_at TEXT NOT NULL,   -- RFC 3339
+    finished_at TEXT NOT NULL,
+    digest TEXT NOT NULL,
+    rollback TEXT,              -- JSON list of `Compensated`, if any ran
+    note TEXT,
+    dry_run INTEGER NOT NULL DEFAULT 0
+)";
+
+const CREATE_STEP_RESULTS: &str = "
+CREATE TABLE IF NOT EXISTS step_results (
+    run_id TEXT NOT NULL REFERENCES runs(run_id),
+    step_id TEXT NOT NULL,
+    status TEXT NOT NULL,       -- success | failed | skipped
+    reason TEXT,
+    failure_kind TEXT,          -- for failed/cancelled steps: timeout | cancelled | ...
+    output TEXT,
+    level INTEGER NOT NULL DEFAULT 0,
+    diagnostics TEXT,
+    explanation TEXT,           -- `StepResult::reason` (`reason` above is the status's)
+    attempts INTEGER NOT NULL DEFAULT 0,
+    change TEXT,                -- dry-run verdict: would-change | no-change | unknown
+    from_cache INTEGER NOT NULL DEFAULT 0,
+    output_bytes INTEGER,       -- full size of a truncated output
+    PRIMARY KEY (run_id, step_id)
+)";
+
+/// One row of the `runs` table — what `list_runs` returns
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunRecord {
+    pub run_id: String,
+    pub flow_id: String,
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// SQLite-backed store for `RunHistory` (tables are created on open)
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    pool: SqlitePool,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) a database file
+    pub async fn open(path: &Path) -> anyhow::Result<Self> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true);
+        Self::connect(options).await
+    }
+
+    /// Opens a throwaway in-memory database (handy for tests)
+    pub async fn in_memory() -> anyhow::Result<Self> {
+        Self::connect(SqliteConnectOptions::from_str("sqlite::memory:")?).await
+    }
+
+    async fn connect(options: SqliteConnectOptions) -> anyhow::Result<Self> {
+        // One connection: keeps `:memory:` databases alive and serializes writes
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+
+        sqlx::query(CREATE_RUNS).execute(&pool).await?;
+        sqlx::query(CREATE_STEP_RESULTS).execute(&pool).await?;
+
+        // Databases created by older versions get the newer columns added
+        add_column_if_missing(&pool, "step_results", "level", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "step_results", "diagnostics", "TEXT").await?;
+        add_column_if_missing(&pool, "step_results", "explanation", "TEXT").await?;
+        add_column_if_missing(&pool, "step_results", "attempts", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "step_results", "change", "TEXT").await?;
+        add_column_if_missing(&pool, "step_results", "failure_kind", "TEXT").await?;
+        add_column_if_missing(&pool, "step_results", "from_cache", "INTEGER NOT NULL DEFAULT 0").await?;
+        add_column_if_missing(&pool, "step_results", "output_bytes", "INTEGER").await?;
+        add_column_if_missing(&pool, "runs", "rollback", "TEXT").await?;
+        add_column_if_missing(&pool, "runs", "note", "TEXT").await?;
+        add_column_if_missing(&pool, "runs", "dry_run", "INTEGER NOT NULL DEFAULT 0").await?;
+
+        Ok(SqliteStore { pool })
+    }
+
+    /// Persists a run and all its step results in one transaction
+    pub async fn save_run(&self, run: &RunHistory) -> anyhow::Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let (status, reason) = encode_run_status(&run.status);
+        let rollback = match run.rollback.as_slice() {
+            [] => None,
+            rollback => Some(serde_json::to_string(rollback)?),
+        };
+        sqlx::query(
+            "INSERT INTO runs (run_id, flow_id, status, reason, started_at, finished_at, digest, rollback, note, dry_run)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(&run.run_id)
+        .bind(&run.flow_id)
+        .bind(status)
+        .bind(reason)
+        .bind(run.started_at.to_rfc3339())
+        .bind(run.finished_at.to_rfc3339())
+        .bind(&run.digest)
+        .bind(rollback)
+        .bind(&run.note)
+        .bind(run.dry_run)
+        .execute(&mut *tx)
+        .await?;
+
+        for (step_id, result) in &run.step_results {
+            sqlx::query(
+                "INSERT INTO step_results (run_id, step_id, status, reason, failure_kind, output, level, diagnostics, explanation, attempts, change, from_cache, output_bytes)
+                 VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(&run.run_id)
+            .bind(step_id)
+            .bind(result.status.state())
+            .bind(result.status.reason())
+            .bind(result.status.failure_kind().map(|kind| kind.to_string()))
+            .bind(&result.output)
+            .bind(result.level as i64)
+            .bind(&result.diagnostics)
+            .bind(&result.reason)
+            .bind(result.attempts as i64)
+            .bind(result.change.map(|change| change.to_string()))
+            .bind(result.from_cache)
+            .bind(result.output_bytes.map(|bytes| bytes as i64))
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Lists stored runs, newest first, optionally for a single flow and
+    /// capped at `limit` rows
+    pub async fn list_runs(
+        &self,
+        flow_id: Option<&str>,
+        limit: Option<usize>,
+    ) -> anyhow::Result<Vec<RunRecord>> {
+        // SQLite treats a negative LIMIT as "no limit"
+        let limit = limit.map_or(-1, |limit| limit as i64);
+
+        let rows = sqlx::query(
+            "SELECT run_id, flow_id, status, reason, started_at, finished_at
+             FROM runs
+             WHERE ?1 IS NULL OR flow_id = ?1
+             ORDER BY started_at DESC
+             LIMIT ?2",
+        )
+        .bind(flow_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter().map(run_record).collect()
+    }
+
+    /// Loads a full run (including step results), if it exists
+    pub async fn get_run(&self, run_id: &str) -> anyhow::Result<Option<RunHistory>> {
+        let row = sqlx::query(
+            "SELECT run_id, flow_id, status, reason, started_at, finished_at, digest, rollback, note, dry_run
+             FROM runs
+             WHERE run_id = ?",
+        )
+        .bind(run_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let run = run_record(&row)?;
+        let digest: String = row.try_get("digest")?;
+        let rollback = match row.try_get::<Option<String>, _>("rollback")? {
+            Some(json) => serde_json::from_str(&json)?,
+            None => Vec::new(),
+        };
+
+        let rows = sqlx::query(
+            "SELECT step_id, status, reason, failure_kind, output, level, diagnostics, explanation, attempts, change, from_cache, output_bytes
+             FROM step_results
+             WHERE run_id = ?",
+        )
+        .bind(run_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut step_results = HashMap::new();
+        for row in &rows {
+            step_results.insert(
+                row.try_get::<String, _>("step_id")?,
+                StepResult {
+                    status: decode_step_status(row.try_get("status")?, row.try_get("reason")?, row.try_get("failure_kind")?)?,
+                    output: row.try_get("output")?,
+                    diagnostics: row.try_get("diagnostics")?,
+                    reason: row.try_get("explanation")?,
+                    level: row.try_get::<i64, _>("level")? as usize,
+                    attempts: row.try_get::<i64, _>("attempts")? as usize,
+                    change: row
+                        .try_get::<Option<String>, _>("change")?
+                        .map(|change| serde_json::from_value(serde_json::Value::String(change)))
+                        .transpose()?,
+                    from_cache: row.try_get("from_cache")?,
+                    output_bytes: row.try_get::<Option<i64>, _>("output_bytes")?.map(|bytes| bytes as usize),
+                },
+            );
+        }
+
+        Ok(Some(RunHistory {
+            run_id: run.run_id,
+            flow_id: run.flow_id,
+            status: run.status,
+            step_results,
+            started_at: run.started_at,
+            finished_at: run.finished_at,
+            digest,
+            rollback,
+            note: row.try_get("note")?,
+            dry_run: row.try_get("dry_run")?,
+        }))
+    }
+}
+
+/// Adds `column` to `table` unless it's already there (SQLite has no
+/// `ADD COLUMN IF NOT EXISTS`)
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> anyhow::Result<()> {
+    let exists = sqlx::query("SELECT 1 FROM pragma_table_info(?) WHERE name = ?")
+        .bind(table)
+        .bind(column)
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+    if !exists {
+        sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"))
+            .execute(pool)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Maps a `runs` row to a `RunRecord`
+fn run_record(row: &SqliteRow) -> anyhow::Result<RunRecord> {
+    Ok(RunRecord {
+        run_id: row.try_get("run_id")?,
+        flow_id: row.try_get("flow_id")?,
+        status: decode_run_status(row.try_get("status")?, row.try_get("reason")?)?,
+        started_at: parse_timestamp(row.try_get("started_at")?)?,
+        finished_at: parse_timestamp(row.try_get("finished_at")?)?,
+    })
+}
+
+fn encode_run_status(status: &RunStatus) -> (&'static str, Option<&str>) {
+    match status {
+        RunStatus::Success => ("success", None),
+        RunStatus::Failed(reason) => ("failed", Some(reason.as_str())),
+    }
+}
+
+fn decode_run_status(state: &str, reason: Option<String>) -> anyhow::Result<RunStatus> {
+    match state {
+        "success" => Ok(RunStatus::Success),
+        "failed" => Ok(RunStatus::Failed(reason.unwrap_or_default())),
+        other => Err(anyhow::anyhow!("Unknown run status '{other}' in database")),
+    }
+}
+
+fn decode_step_status(state: &str, reason: Option<String>, failure_kind: Option<String>) -> anyhow::Result<StepStatus> {
+    match state {
+        "success" => Ok(StepStatus::Success),
+        "failed" => {
+            // Rows saved before failure kinds existed have none
+            let kind: FailureKind = match failure_kind {
+                Some(kind) => serde_json::from_value(serde_json::Value::String(kind))?,
+                None => FailureKind::default(),
+            };
+            Ok(StepStatus::Failed(kind, reason.unwrap_or_default()))
+        }
+        "skipped" => Ok(StepStatus::Skipped(reason.unwrap_or_default())),
+        "cancelled" => Ok(StepStatus::Cancelled),
+        other => Err(anyhow::anyhow!("Unknown step status '{other}' in database")),
+    }
+}
+
+fn parse_timestamp(value: &str) -> anyhow::Result<DateTime<Utc>> {
+    Ok(DateTime::parse_from_rfc3339(value)?.with_timezone(&Utc))
+}
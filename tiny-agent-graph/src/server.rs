@@ -0,0 +1,223 @@
+#![allow(dead_code)] // Only the `serve` subcommand uses this so far
+
+use crate::engine::{run_flow_with_options, RunEvent, RunHistory, RunOptions, DEFAULT_MAX_OUTPUT_BYTES};
+use crate::flow::{load_flow_from_str, validate_configs, Flow};
+use crate::handlers::HandlerRegistry;
+use crate::persistence::SqliteStore;
+use crate::template::EnvAccess;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::Stream;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Events buffered per streamed run before slow clients start missing some
+const EVENT_BUFFER: usize = 1024;
+
+/// Step kinds whose handlers reach into the server's host (run commands or
+/// scripts, read flow files); posted flows may only use them if the server
+/// allows it (`ServerState::allow_local_handlers`)
+pub const LOCAL_KINDS: [&str; 3] = ["script", "shell", "subflow"];
+
+/// Shared by every request the server handles
+#[derive(Debug, Clone)]
+pub struct ServerState {
+    /// Where runs are saved and looked up; without it `GET /flows/runs/:id` is 404
+    pub store: Option<SqliteStore>,
+
+    /// Let posted flows use the `LOCAL_KINDS` handlers — anyone who can
+    /// reach the server can then run commands on its host
+    pub allow_local_handlers: bool,
+
+    /// Environment variables posted flows may read with `{{ env.<VAR> }}`;
+    /// placeholders for any other variable are left as they are
+    pub allowed_env: Vec<String>,
+
+    /// Step outputs longer than this are truncated in the returned and
+    /// saved histories (see `RunOptions::max_output_bytes`)
+    pub max_output_bytes: usize,
+}
+
+impl Default for ServerState {
+    fn default() -> Self {
+        ServerState {
+            store: None,
+            allow_local_handlers: false,
+            allowed_env: Vec::new(),
+            max_output_bytes: DEFAULT_MAX_OUTPUT_BYTES,
+        }
+    }
+}
+
+impl ServerState {
+    /// Refuses flows with steps (or compensations) of a `LOCAL_KINDS` kind,
+    /// unless those are allowed
+    fn check_kinds(&self, flow: &Flow) -> Result<(), ApiError> {
+        if self.allow_local_handlers {
+            return Ok(());
+        }
+        let refused: Vec<String> = flow
+            .nodes
+            .iter()
+            .flat_map(|step| {
+                let compensation = step.compensation.as_ref().map(|compensation| compensation.kind.as_str());
+                [Some(step.kind.as_str()), compensation]
+                    .into_iter()
+                    .flatten()
+                    .filter(|kind| LOCAL_KINDS.contains(kind))
+                    .map(move |kind| format!("{} ({kind})", step.id))
+            })
+            .collect();
+        if refused.is_empty() {
+            Ok(())
+        } else {
+            Err(ApiError(
+                StatusCode::FORBIDDEN,
+                format!("Step kinds not allowed on this server: {}", refused.join(", ")),
+            ))
+        }
+    }
+
+    /// How posted flows run: the built-in handlers, minus `LOCAL_KINDS`
+    /// unless those are allowed, only `allowed_env` readable, and outputs
+    /// capped at `max_output_bytes`
+    fn run_options(&self) -> RunOptions {
+        let mut registry = HandlerRegistry::with_builtins();
+        if !self.allow_local_handlers {
+            for kind in LOCAL_KINDS {
+                registry.unregister(kind);
+            }
+        }
+        RunOptions {
+            registry: Arc::new(registry),
+            env_access: EnvAccess::Only(self.allowed_env.iter().cloned().collect()),
+            max_output_bytes: Some(self.max_output_bytes),
+            ..Default::default()
+        }
+    }
+}
+
+/// The HTTP API:
+/// - `POST /flows/run` — body is a flow in YAML or JSON; runs it and answers
+///   with its `RunHistory`
+/// - `POST /flows/run/events` — the same, but answers with a server-sent
+///   event per `RunEvent` as the run goes (`run_finished` carries the history)
+/// - `GET /flows/runs/:id` — a stored `RunHistory` (needs a store)
+pub fn router(state: ServerState) -> Router {
+    Router::new()
+        .route("/flows/run", post(run_flow_handler))
+        .route("/flows/run/events", post(run_flow_events_handler))
+        .route("/flows/runs/:id", get(get_run_handler))
+        .with_state(state)
+}
+
+/// Serves `router` on `addr` until the process is stopped
+pub async fn serve(addr: SocketAddr, state: ServerState) -> anyhow::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    info!("🌐 Listening on {}", listener.local_addr()?);
+    axum::serve(listener, router(state)).await?;
+    Ok(())
+}
+
+/// An error answer: status code plus a plain-text reason
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, self.1).into_response()
+    }
+}
+
+async fn run_flow_handler(State(state): State<ServerState>, body: String) -> Result<Json<RunHistory>, ApiError> {
+    let (flow, graph) = load_flow_from_str(&body).map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?;
+    info!("📥 Received flow '{}' with {} steps", flow.id, graph.node_count());
+    state.check_kinds(&flow)?;
+    let options = state.run_options();
+    check_configs(&flow, &options)?;
+
+    // Own task, so a long run doesn't hold up the connection handling
+    let history = tokio::spawn(async move { run_flow_with_options(&flow, graph, &options).await })
+        .await
+        .map_err(|err| ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?
+        .map_err(|err| ApiError(StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))?;
+
+    save_run(&state, &history).await;
+    Ok(Json(history))
+}
+
+async fn run_flow_events_handler(
+    State(state): State<ServerState>,
+    body: String,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (flow, graph) = load_flow_from_str(&body).map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))?;
+    info!("📥 Received flow '{}' with {} steps (streaming events)", flow.id, graph.node_count());
+    state.check_kinds(&flow)?;
+    let options = state.run_options();
+    check_configs(&flow, &options)?;
+
+    // The run owns the only sender, so the stream ends when the run does
+    let (sender, receiver) = mpsc::channel(EVENT_BUFFER);
+    tokio::spawn(async move {
+        let options = RunOptions {
+            event_sender: Some(sender),
+            ..options
+        };
+        if let Ok(history) = run_flow_with_options(&flow, graph, &options).await {
+            save_run(&state, &history).await;
+        }
+    });
+
+    let events = futures::stream::unfold(receiver, |mut receiver| async move {
+        let event = receiver.recv().await?;
+        Some((Ok(sse_event(&event)), receiver))
+    });
+    Ok(Sse::new(events))
+}
+
+/// Refuses flows whose step configs don't match their kind's schema, before
+/// anything runs
+fn check_configs(flow: &Flow, options: &RunOptions) -> Result<(), ApiError> {
+    validate_configs(flow, &options.registry).map_err(|err| ApiError(StatusCode::BAD_REQUEST, err.to_string()))
+}
+
+/// A `RunEvent` as a server-sent event: its kind (e.g. `step_finished`) as
+/// the event name, its fields as JSON data
+fn sse_event(event: &RunEvent) -> Event {
+    let json = serde_json::to_value(event).unwrap_or_default();
+    Event::default()
+        .event(json["event"].as_str().unwrap_or("event"))
+        .data(json["data"].to_string())
+}
+
+/// Saves a finished run if the server has a store
+async fn save_run(state: &ServerState, history: &RunHistory) {
+    if let Some(store) = &state.store {
+        // The run happened either way — don't turn a storage hiccup into a failure
+        if let Err(err) = store.save_run(history).await {
+            warn!("⚠️ Could not save run {}: {err}", history.run_id);
+        }
+    }
+}
+
+async fn get_run_handler(
+    State(state): State<ServerState>,
+    Path(run_id): Path<String>,
+) -> Result<Json<RunHistory>, ApiError> {
+    let Some(store) = &state.store else {
+        return Err(ApiError(StatusCode::NOT_FOUND, "Run history is not enabled (start with --db)".into()));
+    };
+
+    match store.get_run(&run_id).await {
+        Ok(Some(history)) => Ok(Json(history)),
+        Ok(None) => Err(ApiError(StatusCode::NOT_FOUND, format!("No run '{run_id}'"))),
+        Err(err) => Err(ApiError(StatusCode::INTERNAL_SERVER_ERROR, err.to_string())),
+    }
+}
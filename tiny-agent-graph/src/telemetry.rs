@@ -0,0 +1,60 @@
+//! OpenTelemetry export of the `run` and `step` tracing spans (feature `otel`)
+
+use crate::engine::StepStatus;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Span;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Name spans are reported under (`service.name`) and the tracer's name
+pub const SERVICE_NAME: &str = "tiny-agent-graph";
+
+/// Tracer provider batching spans to an OTLP/gRPC collector (e.g. Jaeger)
+///
+/// Call `shutdown` on it before exiting, or the last batch is lost.
+pub fn otlp_tracer_provider(endpoint: &str) -> anyhow::Result<TracerProvider> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", SERVICE_NAME)]))
+        .build())
+}
+
+/// `tracing` layer forwarding spans to `provider`
+pub fn layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: tracing::Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
+
+/// Tags a `run` span with the flow and run IDs
+pub fn annotate_run(span: &Span, flow_id: &str, run_id: &str) {
+    span.set_attribute("flow.id", flow_id.to_string());
+    span.set_attribute("run.id", run_id.to_string());
+}
+
+/// Tags a `step` span with the flow, run, and step kind
+pub fn annotate_step(span: &Span, flow_id: &str, run_id: &str, kind: &str) {
+    annotate_run(span, flow_id, run_id);
+    span.set_attribute("step.kind", kind.to_string());
+}
+
+/// Records how a step ended on its span (`step.status` = success, failed, ...)
+pub fn record_step_status(span: &Span, status: &StepStatus) {
+    span.set_attribute("step.status", status.state());
+    if let Some(reason) = status.reason() {
+        span.set_attribute("step.reason", reason.to_string());
+    }
+    if let Some(kind) = status.failure_kind() {
+        span.set_attribute("step.failure_kind", kind.to_string());
+    }
+}
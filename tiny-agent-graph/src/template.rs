@@ -0,0 +1,160 @@
+#![allow(dead_code)] // Only the engine uses this so far
+
+use serde_yaml::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// What `{{ ... }}` placeholders in a step's config can refer to
+pub struct TemplateContext<'a> {
+    /// Resolved flow inputs (`{{ inputs.<name> }}`)
+    pub inputs: &'a BTreeMap<String, Value>,
+    /// Outputs of earlier steps (`{{ steps.<id>.output }}`)
+    pub outputs: &'a HashMap<String, String>,
+    /// The current item of a `for_each` step (`{{ item }}`)
+    pub item: Option<&'a Value>,
+    /// Which environment variables `{{ env.<VAR> }}` may read
+    pub env: &'a EnvAccess,
+}
+
+/// Which environment variables `{{ env.<VAR> }}` placeholders may read;
+/// placeholders for any other variable are left untouched
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum EnvAccess {
+    /// Every variable of the process (the CLI's behavior)
+    #[default]
+    All,
+    /// Only the listed variables (e.g. flows posted to the server)
+    Only(HashSet<String>),
+}
+
+impl EnvAccess {
+    /// Whether `{{ env.<var> }}` may read `var`
+    pub fn allows(&self, var: &str) -> bool {
+        match self {
+            EnvAccess::All => true,
+            EnvAccess::Only(vars) => vars.contains(var),
+        }
+    }
+}
+
+/// Something a `{{ ... }}` placeholder points at (see `references`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reference {
+    /// `{{ inputs.<name> }}`
+    Input(String),
+    /// `{{ steps.<id>.output }}`
+    StepOutput(String),
+}
+
+/// Every input and step output referenced by placeholders anywhere in a
+/// config, in the order they appear (placeholders of other shapes are ignored)
+pub fn references(value: &Value) -> Vec<Reference> {
+    let mut found = Vec::new();
+    collect_references(value, &mut found);
+    found
+}
+
+fn collect_references(value: &Value, found: &mut Vec<Reference>) {
+    match value {
+        Value::String(text) => {
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(len) = rest[start..].find("}}") else {
+                    break;
+                };
+                let expr = rest[start + 2..start + len].trim();
+                if let Some(name) = expr.strip_prefix("inputs.") {
+                    found.push(Reference::Input(name.to_string()));
+                } else if let Some(step_id) = expr.strip_prefix("steps.").and_then(|rest| rest.strip_suffix(".output")) {
+                    found.push(Reference::StepOutput(step_id.to_string()));
+                }
+                rest = &rest[start + len + 2..];
+            }
+        }
+        Value::Sequence(items) => items.iter().for_each(|item| collect_references(item, found)),
+        Value::Mapping(mapping) => mapping.values().for_each(|value| collect_references(value, found)),
+        _ => {}
+    }
+}
+
+/// Renders placeholders in every string of a config, recursively
+///
+/// - A string that is exactly one placeholder takes the referenced value as-is,
+///   so `retries: "{{ inputs.retries }}"` stays a number if the input is one
+/// - Placeholders inside longer strings are replaced by the value's text
+/// - Placeholders that don't resolve are left untouched
+pub fn render(value: &Value, ctx: &TemplateContext) -> Value {
+    match value {
+        Value::String(text) => render_string(text, ctx),
+        Value::Sequence(items) => Value::Sequence(items.iter().map(|item| render(item, ctx)).collect()),
+        Value::Mapping(mapping) => Value::Mapping(
+            mapping
+                .iter()
+                .map(|(key, value)| (key.clone(), render(value, ctx)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+fn render_string(text: &str, ctx: &TemplateContext) -> Value {
+    // Whole-string placeholder: keep the value's type
+    if let Some(expr) = text.trim().strip_prefix("{{").and_then(|rest| rest.strip_suffix("}}")) {
+        if !expr.contains("{{") {
+            if let Some(value) = resolve(expr.trim(), ctx) {
+                return value;
+            }
+        }
+    }
+
+    let mut rendered = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        let placeholder = &rest[start..start + len + 2];
+        let expr = placeholder[2..placeholder.len() - 2].trim();
+
+        rendered.push_str(&rest[..start]);
+        match resolve(expr, ctx) {
+            Some(value) => rendered.push_str(&value_to_text(&value)),
+            None => rendered.push_str(placeholder),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    rendered.push_str(rest);
+
+    Value::String(rendered)
+}
+
+/// Looks up `inputs.<name>`, `steps.<id>.output`, `env.<VAR>`, or `item`
+fn resolve(expr: &str, ctx: &TemplateContext) -> Option<Value> {
+    if expr == "item" {
+        return ctx.item.cloned();
+    }
+    if let Some(var) = expr.strip_prefix("env.") {
+        if !ctx.env.allows(var) {
+            return None;
+        }
+        return std::env::var(var).ok().map(Value::String);
+    }
+    if let Some(name) = expr.strip_prefix("inputs.") {
+        return ctx.inputs.get(name).cloned();
+    }
+
+    let step_id = expr.strip_prefix("steps.")?.strip_suffix(".output")?;
+    ctx.outputs.get(step_id).cloned().map(Value::String)
+}
+
+/// Text form of a value for splicing into a longer string
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        Value::Bool(flag) => flag.to_string(),
+        Value::Number(number) => number.to_string(),
+        other => serde_yaml::to_string(other)
+            .map(|text| text.trim_end().to_string())
+            .unwrap_or_default(),
+    }
+}
@@ -1,5 +1,20 @@
-use tiny_agent_graph::engine::{run_flow, StepStatus, RunStatus};
-use tiny_agent_graph::flow::{Flow, Step, StepNode, StepGraph};
+use async_trait::async_trait;
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_agent_graph::engine::{
+    run_flow, run_flow_stream, run_flow_with_options, stage_tallies, OnConflict, RunEvent, RunOptions, RunStatus,
+    FailureKind, StageTally, StepDecision, StepResult, StepStatus, CANCELLED, DISABLED, EMPTY_FLOW_NOTE, FLOW_TIMEOUT,
+};
+use tiny_agent_graph::flow::{
+    load_flow_from_str, Compensation, DependOn, Dependency, DependencyTarget, Flow, RetryPolicy, Step, StepNode, StepGraph,
+    StepSelection,
+};
+use tiny_agent_graph::handlers::{Change, HandlerError, HandlerOutput, HandlerRegistry, StepContext, StepHandler};
+use tokio_util::sync::CancellationToken;
+use tracing_test::traced_test;
 
 /// Helper: build a simple flow + graph manually
 fn build_test_flow(steps: Vec<Step>, edges: Vec<(usize, usize)>) -> (Flow, StepGraph) {
@@ -7,6 +22,7 @@ fn build_test_flow(steps: Vec<Step>, edges: Vec<(usize, usize)>) -> (Flow, StepG
         id: "test-flow".to_string(),
         description: Some("Test flow".into()),
         nodes: steps.clone(),
+        ..Default::default()
     };
 
     let mut graph = StepGraph::new();
@@ -57,8 +73,8 @@ async fn test_successful_linear_flow() {
     }
 }
 
-#[tokio::test]
-async fn test_failure_propagation() {
+/// Helper: a (fails) -> b -> c
+fn failure_propagation_flow() -> (Flow, StepGraph) {
     let steps = vec![
         Step {
             id: "a".into(),
@@ -78,20 +94,81 @@ async fn test_failure_propagation() {
             ..Default::default()
         },
     ];
+    build_test_flow(steps, vec![(0, 1), (1, 2)])
+}
 
-    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (1, 2)]);
+#[tokio::test]
+async fn test_failure_propagation() {
+    let (flow, graph) = failure_propagation_flow();
 
     let result = run_flow(&flow, graph).await.unwrap();
     assert!(matches!(result.status, RunStatus::Failed(_)));
 
     let step_a = result.step_results.get("a").unwrap();
-    assert!(matches!(step_a.status, StepStatus::Failed(_)));
+    assert_eq!(step_a.status.failure_kind(), Some(FailureKind::HandlerError));
 
+    // Descendants of the failure are skipped, not failed themselves
     let step_b = result.step_results.get("b").unwrap();
-    assert!(matches!(step_b.status, StepStatus::Failed(_)));
+    assert_eq!(step_b.status, StepStatus::Skipped("Dependency 'a' failed".into()));
 
     let step_c = result.step_results.get("c").unwrap();
-    assert!(matches!(step_c.status, StepStatus::Failed(_)));
+    assert!(matches!(step_c.status, StepStatus::Skipped(_)));
+}
+
+#[tokio::test]
+async fn test_blocked_steps_explain_which_dependency_failed() {
+    let (flow, graph) = failure_propagation_flow();
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    let reason = |id: &str| result.step_results[id].reason.clone().unwrap_or_default();
+    assert_eq!(reason("a"), "failed (handler error)");
+    assert_eq!(reason("b"), "skipped (dep 'a' failed)");
+    assert_eq!(reason("c"), "skipped (dep 'b' skipped)");
+}
+
+#[tokio::test]
+async fn test_completion_edge_runs_even_if_dependency_failed() {
+    let steps = vec![
+        Step {
+            id: "deploy".into(),
+            kind: "fail_test".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "smoke_test".into(),
+            depends_on: vec!["deploy".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "cleanup".into(),
+            depends_on: vec![Dependency {
+                target: DependencyTarget::Step("deploy".into()),
+                on: DependOn::Completion,
+            }],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (0, 2)]);
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    // An on-success edge blocks, an on-completion edge doesn't
+    assert!(matches!(result.step_results["smoke_test"].status, StepStatus::Skipped(_)));
+    assert_eq!(result.step_results["cleanup"].status, StepStatus::Success);
+    // The failure itself still fails the run
+    assert!(matches!(result.status, RunStatus::Failed(_)));
+}
+
+#[tokio::test]
+async fn test_empty_flow_succeeds_with_a_note() {
+    let (flow, graph) = build_test_flow(vec![], vec![]);
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert!(result.step_results.is_empty());
+    assert_eq!(result.note.as_deref(), Some(EMPTY_FLOW_NOTE));
 }
 
 #[tokio::test]
@@ -127,3 +204,2137 @@ async fn test_parallel_branching_success() {
     let result = run_flow(&flow, graph).await.unwrap();
     assert!(matches!(result.status, RunStatus::Success));
 }
+
+#[tokio::test]
+async fn test_gate_skip_cascades_without_failing_run() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "noop".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+    ];
+
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+    let options = RunOptions {
+        step_gate: Some(Arc::new(|step: &Step| {
+            if step.id == "a" {
+                StepDecision::Skip
+            } else {
+                StepDecision::Run
+            }
+        })),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success));
+    assert!(matches!(result.step_results["a"].status, StepStatus::Skipped(_)));
+    assert!(matches!(result.step_results["b"].status, StepStatus::Skipped(_)));
+}
+
+/// Helper: a short two-step flow in the given concurrency group
+fn grouped_flow(group: &str) -> (Flow, StepGraph) {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+    ];
+    let (mut flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+    flow.concurrency_group = Some(group.into());
+    (flow, graph)
+}
+
+#[tokio::test]
+async fn test_concurrency_group_queues_runs() {
+    let (flow_1, graph_1) = grouped_flow("queue-group");
+    let (flow_2, graph_2) = grouped_flow("queue-group");
+    let options = RunOptions::default();
+
+    let (first, second) = tokio::join!(
+        run_flow_with_options(&flow_1, graph_1, &options),
+        run_flow_with_options(&flow_2, graph_2, &options),
+    );
+    let (first, second) = (first.unwrap(), second.unwrap());
+
+    // Whichever run went second must have started after the other finished
+    let no_overlap = first.finished_at <= second.started_at || second.finished_at <= first.started_at;
+    assert!(no_overlap, "runs in the same concurrency group overlapped");
+}
+
+#[tokio::test]
+async fn test_concurrency_group_rejects_second_run() {
+    let (flow_1, graph_1) = grouped_flow("reject-group");
+    let (flow_2, graph_2) = grouped_flow("reject-group");
+    let options = RunOptions {
+        on_conflict: OnConflict::Reject,
+        ..Default::default()
+    };
+
+    let (first, second) = tokio::join!(
+        run_flow_with_options(&flow_1, graph_1, &options),
+        run_flow_with_options(&flow_2, graph_2, &options),
+    );
+
+    assert!(first.is_ok());
+    let err = second.expect_err("second run should be rejected").to_string();
+    assert!(err.contains("reject-group"), "unexpected error: {err}");
+}
+
+/// Helper: start -> (a, b) -> end
+fn branching_flow() -> (Flow, StepGraph) {
+    let steps = vec![
+        Step {
+            id: "start".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "a".into(),
+            depends_on: vec!["start".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            depends_on: vec!["start".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "end".into(),
+            depends_on: vec!["a".into(), "b".into()],
+            ..Default::default()
+        },
+    ];
+    build_test_flow(steps, vec![(0, 1), (0, 2), (1, 3), (2, 3)])
+}
+
+#[tokio::test]
+async fn test_step_results_report_topological_level() {
+    let (flow, graph) = branching_flow();
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    let level = |id: &str| result.step_results[id].level;
+    assert_eq!(level("start"), 0);
+    assert_eq!(level("a"), 1);
+    assert_eq!(level("b"), 1);
+    assert_eq!(level("end"), 2);
+}
+
+/// Helper: run the branching flow with a selection and return the executed step ids
+async fn run_selection(selection: StepSelection) -> Vec<String> {
+    let (flow, graph) = branching_flow();
+    let options = RunOptions {
+        selection,
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success));
+
+    let mut ids: Vec<String> = result.step_results.keys().cloned().collect();
+    ids.sort();
+    ids
+}
+
+#[tokio::test]
+async fn test_select_single_step_with_ancestors() {
+    let ids = run_selection(StepSelection {
+        step: Some("a".into()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(ids, vec!["a", "start"]);
+}
+
+#[tokio::test]
+async fn test_select_from_step_downward() {
+    // `end` also depends on the pruned `b`, which must not block it
+    let ids = run_selection(StepSelection {
+        from: Some("a".into()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(ids, vec!["a", "end"]);
+}
+
+#[tokio::test]
+async fn test_select_up_to_step() {
+    let ids = run_selection(StepSelection {
+        to: Some("b".into()),
+        ..Default::default()
+    })
+    .await;
+    assert_eq!(ids, vec!["b", "start"]);
+}
+
+#[tokio::test]
+async fn test_select_unknown_step_errors() {
+    let (flow, graph) = branching_flow();
+    let options = RunOptions {
+        selection: StepSelection {
+            step: Some("nope".into()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    assert!(run_flow_with_options(&flow, graph, &options).await.is_err());
+}
+
+/// Helper: run a labeled flow with `--select-tag`s and return the executed step ids
+async fn run_tagged(tags: &[&str]) -> anyhow::Result<Vec<String>> {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: labeled
+nodes:
+  - id: login
+    kind: noop
+  - id: fetch
+    kind: noop
+    depends_on: [login]
+    labels: [critical]
+  - id: report
+    kind: noop
+    depends_on: [fetch]
+    labels: [nightly]
+  - id: cleanup
+    kind: noop
+    labels: [nightly]
+  - id: audit
+    kind: noop
+    depends_on: [login]
+"#,
+    )?;
+    let options = RunOptions {
+        selection: StepSelection {
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await?;
+    let mut ids: Vec<String> = result.step_results.keys().cloned().collect();
+    ids.sort();
+    Ok(ids)
+}
+
+#[tokio::test]
+async fn test_select_tag_runs_labeled_steps_with_ancestors() {
+    let ids = run_tagged(&["critical"]).await.unwrap();
+    assert_eq!(ids, vec!["fetch", "login"]);
+}
+
+#[tokio::test]
+async fn test_select_tags_union() {
+    let ids = run_tagged(&["critical", "nightly"]).await.unwrap();
+    assert_eq!(ids, vec!["cleanup", "fetch", "login", "report"]);
+}
+
+#[tokio::test]
+async fn test_select_unknown_tag_errors() {
+    let err = run_tagged(&["weekly"]).await.unwrap_err();
+    assert!(err.to_string().contains("No step is labeled 'weekly'"), "{err}");
+}
+
+#[tokio::test]
+async fn test_run_id_override_is_used_verbatim() {
+    let (flow, graph) = branching_flow();
+    let options = RunOptions {
+        run_id: Some("golden-run-1".into()),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.run_id, "golden-run-1");
+}
+
+#[tokio::test]
+async fn test_digest_is_stable_across_identical_runs() {
+    let (flow, graph) = branching_flow();
+    let first = run_flow(&flow, graph).await.unwrap();
+
+    let (flow, graph) = branching_flow();
+    let second = run_flow(&flow, graph).await.unwrap();
+
+    assert_ne!(first.run_id, second.run_id);
+    assert_eq!(first.digest, second.digest);
+    assert!(first.verify_digest());
+}
+
+#[tokio::test]
+async fn test_digest_changes_when_an_output_changes() {
+    let (flow, graph) = branching_flow();
+    let mut history = run_flow(&flow, graph).await.unwrap();
+    let original = history.digest.clone();
+
+    history.step_results.get_mut("a").unwrap().output = Some("tampered".into());
+
+    assert!(!history.verify_digest());
+    assert_ne!(history.compute_digest(), original);
+}
+
+/// Test handler: sleeps, then succeeds
+struct SlowHandler(Duration);
+
+#[async_trait]
+impl StepHandler for SlowHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        tokio::time::sleep(self.0).await;
+        Ok("done".into())
+    }
+}
+
+#[tokio::test]
+async fn test_failure_skips_descendants_without_waiting_on_other_branches() {
+    // a -> b1 (fails) -> c -> f, a -> b2 (slow) -> c, and an unrelated a -> d -> e
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: diamond-with-failure
+nodes:
+  - id: a
+    kind: noop
+  - id: b1
+    kind: fail_test
+    depends_on: [a]
+  - id: b2
+    kind: slow
+    depends_on: [a]
+  - id: c
+    kind: noop
+    depends_on: [b1, b2]
+  - id: d
+    kind: noop
+    depends_on: [a]
+  - id: e
+    kind: noop
+    depends_on: [d]
+  - id: f
+    kind: noop
+    depends_on: [c]
+"#,
+    )
+    .unwrap();
+
+    let mut registry = HandlerRegistry::with_builtins();
+    registry.register("slow", SlowHandler(Duration::from_millis(300)));
+    let finished = Arc::new(Mutex::new(Vec::new()));
+    let collected = finished.clone();
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        max_parallel: 4,
+        sim_latency_ms: Some((0, 0)),
+        on_event: Some(Arc::new(move |event| {
+            if let RunEvent::StepFinished { step_id, .. } = event {
+                collected.lock().unwrap().push(step_id);
+            }
+        })),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert!(matches!(result.status, RunStatus::Failed(_)));
+    assert!(matches!(result.step_results["b1"].status, StepStatus::Failed(..)));
+    assert_eq!(result.step_results["c"].status, StepStatus::Skipped("Dependency 'b1' failed".into()));
+    assert_eq!(result.step_results["f"].status, StepStatus::Skipped("Dependency 'c' was skipped".into()));
+    for step in ["a", "b2", "d", "e"] {
+        assert_eq!(result.step_results[step].status, StepStatus::Success, "{step}");
+    }
+
+    // `b1`'s whole subtree is settled as soon as it fails, not once `b2` is done
+    let finished = finished.lock().unwrap();
+    let position = |id: &str| finished.iter().position(|step| step == id).unwrap();
+    assert!(position("c") < position("b2"), "{finished:?}");
+    assert!(position("f") < position("b2"), "{finished:?}");
+}
+
+/// Test handler: counts how often it ran
+struct CountingHandler(Arc<AtomicUsize>);
+
+#[async_trait]
+impl StepHandler for CountingHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok("counted".into())
+    }
+}
+
+#[tokio::test]
+async fn test_timeout_triggers_compensation_immediately() {
+    let undo_calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("slow", SlowHandler(Duration::from_secs(5)));
+    registry.register("undo", CountingHandler(undo_calls.clone()));
+
+    let step = Step {
+        id: "write".into(),
+        kind: "slow".into(),
+        timeout_seconds: Some(1),
+        compensate_on_timeout: true,
+        compensation: Some(Compensation {
+            kind: "undo".into(),
+            config: serde_yaml::Value::Null,
+            retry: None,
+        }),
+        ..Default::default()
+    };
+    let (flow, graph) = build_test_flow(vec![step], vec![]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(undo_calls.load(Ordering::SeqCst), 1);
+    match &result.step_results["write"].status {
+        StepStatus::Failed(FailureKind::Timeout, reason) => {
+            assert!(reason.contains("Timed out"), "{reason}");
+            assert!(reason.contains("compensation 'undo' succeeded"), "{reason}");
+        }
+        other => panic!("expected timeout failure, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_timeout_without_flag_does_not_compensate() {
+    let undo_calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("slow", SlowHandler(Duration::from_secs(5)));
+    registry.register("undo", CountingHandler(undo_calls.clone()));
+
+    let step = Step {
+        id: "write".into(),
+        kind: "slow".into(),
+        timeout_seconds: Some(1),
+        compensation: Some(Compensation {
+            kind: "undo".into(),
+            config: serde_yaml::Value::Null,
+            retry: None,
+        }),
+        ..Default::default()
+    };
+    let (flow, graph) = build_test_flow(vec![step], vec![]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert_eq!(undo_calls.load(Ordering::SeqCst), 0);
+    assert_eq!(result.step_results["write"].status.failure_kind(), Some(FailureKind::Timeout));
+}
+
+/// Test handler: records which step it ran for; fails if its config says `fail: true`
+struct RecordingHandler(Arc<Mutex<Vec<String>>>);
+
+#[async_trait]
+impl StepHandler for RecordingHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        self.0.lock().unwrap().push(ctx.step.id.clone());
+        if ctx.step.config["fail"].as_bool() == Some(true) {
+            return Err("undo went wrong".into());
+        }
+        Ok("undone".into())
+    }
+}
+
+#[tokio::test]
+async fn test_rollback_compensates_in_reverse_topological_order() {
+    // Diamond a -> (b, c) -> d, then `boom` fails after d
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: diamond
+rollback_on_failure: true
+nodes:
+  - id: a
+    kind: noop
+    compensation: { kind: undo }
+  - id: b
+    kind: noop
+    depends_on: [a]
+    compensation: { kind: undo, config: { fail: true } }
+  - id: c
+    kind: noop
+    depends_on: [a]
+    compensation: { kind: undo }
+  - id: d
+    kind: noop
+    depends_on: [b, c]
+    compensation: { kind: undo }
+  - id: boom
+    kind: fail_test
+    depends_on: [d]
+    compensation: { kind: undo }
+"#,
+    )
+    .unwrap();
+    let undone = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = HandlerRegistry::new();
+    registry.register("undo", RecordingHandler(undone.clone()));
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Failed(_)));
+
+    // Only succeeded steps are undone, and each before whatever it depends on
+    let order = undone.lock().unwrap().clone();
+    let position = |id: &str| order.iter().position(|step| step == id).unwrap();
+    assert_eq!(order.len(), 4, "{order:?}");
+    assert!(!order.contains(&"boom".to_string()));
+    assert!(position("d") < position("b") && position("d") < position("c"), "{order:?}");
+    assert!(position("b") < position("a") && position("c") < position("a"), "{order:?}");
+
+    // b's compensation failed, but a's still ran afterwards
+    let recorded: Vec<&str> = result.rollback.iter().map(|entry| entry.step_id.as_str()).collect();
+    assert_eq!(recorded, order);
+    let b = result.rollback.iter().find(|entry| entry.step_id == "b").unwrap();
+    assert!(matches!(&b.status, StepStatus::Failed(_, reason) if reason.contains("undo went wrong")));
+    let a = result.rollback.iter().find(|entry| entry.step_id == "a").unwrap();
+    assert_eq!(a.status, StepStatus::Success);
+}
+
+/// Test handler: fails its first `failures` calls, then succeeds
+struct FlakyHandler {
+    failures: usize,
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl StepHandler for FlakyHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if call <= self.failures {
+            return Err(format!("delete timed out (call {call})").into());
+        }
+        Ok("deleted".into())
+    }
+}
+
+/// Test handler: fails every call with an error retrying can't fix
+struct NotFoundHandler {
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl StepHandler for NotFoundHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(HandlerError::permanent("record 42 does not exist"))
+    }
+}
+
+#[tokio::test]
+async fn test_non_retriable_error_is_not_retried() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: permanent-failure
+nodes:
+  - id: lookup
+    kind: lookup
+    retry: { max_attempts: 3, backoff_seconds: 0 }
+"#,
+    )
+    .unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("lookup", NotFoundHandler { calls: calls.clone() });
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    let lookup = &result.step_results["lookup"];
+    assert_eq!(lookup.attempts, 1);
+    assert_eq!(lookup.status, StepStatus::Failed(FailureKind::HandlerError, "record 42 does not exist".into()));
+}
+
+/// Helper: rolls back one step whose flaky compensation fails twice, allowing
+/// `max_attempts`; returns the compensation's outcome and how often it ran
+async fn roll_back_flaky_compensation(max_attempts: usize) -> (StepStatus, usize) {
+    let (flow, graph) = load_flow_from_str(&format!(
+        r#"
+id: flaky-rollback
+rollback_on_failure: true
+nodes:
+  - id: create
+    kind: noop
+    compensation:
+      kind: flaky_delete
+      retry: {{ max_attempts: {max_attempts}, backoff_seconds: 0 }}
+  - id: boom
+    kind: fail_test
+    depends_on: [create]
+"#
+    ))
+    .unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("flaky_delete", FlakyHandler { failures: 2, calls: calls.clone() });
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert_eq!(result.rollback.len(), 1);
+    (result.rollback[0].status.clone(), calls.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn test_compensation_retries_until_it_succeeds() {
+    let (status, calls) = roll_back_flaky_compensation(3).await;
+    assert_eq!(status, StepStatus::Success);
+    assert_eq!(calls, 3);
+}
+
+#[tokio::test]
+async fn test_compensation_out_of_retries_records_last_error() {
+    let (status, calls) = roll_back_flaky_compensation(2).await;
+    assert_eq!(status, StepStatus::Failed(FailureKind::HandlerError, "delete timed out (call 2)".into()));
+    assert_eq!(calls, 2);
+}
+
+#[tokio::test]
+async fn test_step_result_counts_attempts() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: flaky-step
+nodes:
+  - id: fetch
+    kind: flaky
+    retry: { max_attempts: 5, backoff_seconds: 0 }
+  - id: parse
+    kind: noop
+    depends_on: [fetch]
+  - id: skipped
+    kind: noop
+    enabled: false
+"#,
+    )
+    .unwrap();
+    let mut registry = HandlerRegistry::new();
+    registry.register("flaky", FlakyHandler { failures: 2, calls: Arc::new(AtomicUsize::new(0)) });
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.step_results["fetch"].status, StepStatus::Success);
+    assert_eq!(result.step_results["fetch"].attempts, 3);
+    assert_eq!(result.step_results["parse"].attempts, 1);
+    assert_eq!(result.step_results["skipped"].attempts, 0);
+}
+
+/// Test handler: already in the state its config asks for (`present`), so a
+/// dry run reports no change; executing it would be a bug
+struct AlreadyAppliedHandler(Arc<AtomicUsize>);
+
+#[async_trait]
+impl StepHandler for AlreadyAppliedHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok("applied".into())
+    }
+
+    async fn would_change(&self, ctx: &StepContext) -> Result<Change, String> {
+        Ok(if ctx.step.config["present"].as_bool() == Some(true) {
+            Change::Unchanged
+        } else {
+            Change::Changed
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_dry_run_reports_changes_without_executing() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: idempotency-check
+nodes:
+  - id: present
+    kind: ensure
+    config: { present: true }
+  - id: missing
+    kind: ensure
+    depends_on: [present]
+  - id: simulated
+    kind: db_upsert
+"#,
+    )
+    .unwrap();
+    let executions = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("ensure", AlreadyAppliedHandler(executions.clone()));
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        dry_run: true,
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph.clone(), &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert!(result.dry_run);
+    assert_eq!(executions.load(Ordering::SeqCst), 0, "a dry run must not execute handlers");
+    let change = |id: &str| result.step_results[id].change;
+    assert_eq!(change("present"), Some(Change::Unchanged));
+    assert_eq!(change("missing"), Some(Change::Changed));
+    assert_eq!(change("simulated"), Some(Change::Unknown));
+
+    // Its "successes" never happened, so there's nothing to pick up from
+    for only_failed in [false, true] {
+        let options = RunOptions {
+            resume_from: Some(result.clone()),
+            only_failed,
+            ..Default::default()
+        };
+        let err = run_flow_with_options(&flow, graph.clone(), &options).await.unwrap_err();
+        assert!(err.to_string().contains("it was a dry run"), "{err}");
+    }
+}
+
+#[tokio::test]
+async fn test_cache_dir_reuses_outputs_across_runs() {
+    let cache_dir = tempfile::tempdir().unwrap();
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: memoized
+nodes:
+  - id: crunch
+    kind: expensive
+    config: { dataset: big }
+    cache: true
+  - id: report
+    kind: expensive
+    depends_on: [crunch]
+"#,
+    )
+    .unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("expensive", CountingHandler(calls.clone()));
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        cache_dir: Some(cache_dir.path().to_path_buf()),
+        ..Default::default()
+    };
+
+    let first = run_flow_with_options(&flow, graph.clone(), &options).await.unwrap();
+    let second = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    // `report` didn't opt in, so it ran both times
+    assert_eq!(calls.load(Ordering::SeqCst), 3, "the second run should use the cache for crunch");
+    assert!(!second.step_results["report"].from_cache);
+    assert!(!first.step_results["crunch"].from_cache);
+    let cached = &second.step_results["crunch"];
+    assert_eq!(second.status, RunStatus::Success);
+    assert!(cached.from_cache);
+    assert_eq!(cached.output.as_deref(), Some("counted"));
+    assert_eq!(cached.attempts, 0);
+}
+
+#[tokio::test]
+async fn test_rollback_leaves_cached_steps_alone_and_evicts_undone_ones() {
+    let load = |nodes: &str| {
+        let yaml = format!(
+            "id: cached\nrollback_on_failure: true\nnodes:\n  - id: crunch\n    kind: expensive\n    cache: true\n    compensation: {{ kind: undo }}\n{nodes}"
+        );
+        load_flow_from_str(&yaml).unwrap()
+    };
+    let (clean, clean_graph) = load("");
+    let (failing, failing_graph) = load("  - id: boom\n    kind: fail_test\n    depends_on: [crunch]\n");
+    let calls = Arc::new(AtomicUsize::new(0));
+    let undone = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = HandlerRegistry::new();
+    registry.register("expensive", CountingHandler(calls.clone()));
+    registry.register("undo", RecordingHandler(undone.clone()));
+    let registry = Arc::new(registry);
+    let options_for = |dir: &tempfile::TempDir| RunOptions {
+        registry: registry.clone(),
+        cache_dir: Some(dir.path().to_path_buf()),
+        ..Default::default()
+    };
+
+    // Reused from the cache, crunch did nothing this run: no compensation
+    let warm = tempfile::tempdir().unwrap();
+    run_flow_with_options(&clean, clean_graph.clone(), &options_for(&warm)).await.unwrap();
+    let result = run_flow_with_options(&failing, failing_graph.clone(), &options_for(&warm)).await.unwrap();
+    assert!(result.step_results["crunch"].from_cache);
+    assert!(result.rollback.is_empty(), "{:?}", result.rollback);
+    assert!(undone.lock().unwrap().is_empty());
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Executed and then undone, crunch's output must not be reused
+    let cold = tempfile::tempdir().unwrap();
+    let result = run_flow_with_options(&failing, failing_graph, &options_for(&cold)).await.unwrap();
+    assert_eq!(result.rollback.len(), 1);
+    let rerun = run_flow_with_options(&clean, clean_graph, &options_for(&cold)).await.unwrap();
+    assert!(!rerun.step_results["crunch"].from_cache);
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_failed_run_without_rollback_flag_compensates_nothing() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: no-rollback
+nodes:
+  - id: a
+    kind: noop
+    compensation: { kind: undo }
+  - id: boom
+    kind: fail_test
+    depends_on: [a]
+"#,
+    )
+    .unwrap();
+    let undone = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = HandlerRegistry::new();
+    registry.register("undo", RecordingHandler(undone.clone()));
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Failed(_)));
+    assert!(undone.lock().unwrap().is_empty());
+    assert!(result.rollback.is_empty());
+}
+
+/// Test handler: answers with the config it was given, as JSON
+struct ConfigEchoHandler;
+
+#[async_trait]
+impl StepHandler for ConfigEchoHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        serde_json::to_string(&ctx.step.config)
+            .map(HandlerOutput::from)
+            .map_err(|err| err.to_string().into())
+    }
+}
+
+#[tokio::test]
+async fn test_kind_defaults_merge_under_step_config() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: defaults
+defaults:
+  echo:
+    timeout_seconds: 30
+    headers: { accept: json, user-agent: tiny }
+nodes:
+  - id: fetch
+    kind: echo
+    config:
+      url: https://example.com
+      headers: { accept: yaml }
+"#,
+    )
+    .unwrap();
+    let mut registry = HandlerRegistry::new();
+    registry.register("echo", ConfigEchoHandler);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    let config: serde_json::Value =
+        serde_json::from_str(result.step_results["fetch"].output.as_deref().unwrap()).unwrap();
+    assert_eq!(
+        config,
+        serde_json::json!({
+            "timeout_seconds": 30,
+            "url": "https://example.com",
+            "headers": { "accept": "yaml", "user-agent": "tiny" },
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_selected_profile_overrides_defaults_and_inputs() {
+    let (mut flow, graph) = load_flow_from_str(
+        r#"
+id: profiles
+inputs:
+  bucket: dev-bucket
+defaults:
+  echo:
+    timeout_seconds: 30
+    host: dev.example.com
+profiles:
+  prod:
+    defaults:
+      echo: { host: example.com }
+    inputs:
+      bucket: prod-bucket
+  typo:
+    inputs:
+      buckte: oops
+nodes:
+  - id: upload
+    kind: echo
+    config:
+      target: "{{ inputs.bucket }}"
+"#,
+    )
+    .unwrap();
+    flow.apply_profile("prod").unwrap();
+    let mut registry = HandlerRegistry::new();
+    registry.register("echo", ConfigEchoHandler);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    let config: serde_json::Value =
+        serde_json::from_str(result.step_results["upload"].output.as_deref().unwrap()).unwrap();
+    assert_eq!(
+        config,
+        serde_json::json!({ "timeout_seconds": 30, "host": "example.com", "target": "prod-bucket" })
+    );
+
+    let err = flow.apply_profile("qa").unwrap_err();
+    assert_eq!(err.to_string(), "Flow 'profiles' has no profile 'qa' (defined: prod, typo)");
+    let err = flow.apply_profile("typo").unwrap_err();
+    assert_eq!(err.to_string(), "Profile 'typo' sets input 'buckte', which the flow does not declare");
+    assert!(!flow.inputs.contains_key("buckte"));
+}
+
+/// Test handler: answers with 100 KB of text
+struct BigBodyHandler;
+
+#[async_trait]
+impl StepHandler for BigBodyHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        Ok("x".repeat(100_000).into())
+    }
+}
+
+#[tokio::test]
+async fn test_long_outputs_are_truncated_in_the_history() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: big-output
+nodes:
+  - id: download
+    kind: big_body
+  - id: measure
+    kind: script
+    depends_on: [download]
+    config:
+      script: "steps.download.len()"
+"#,
+    )
+    .unwrap();
+    let mut registry = HandlerRegistry::with_builtins();
+    registry.register("big_body", BigBodyHandler);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        max_output_bytes: Some(1024),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    let download = &result.step_results["download"];
+    assert_eq!(download.output_bytes, Some(100_000));
+    assert_eq!(
+        download.output.as_deref(),
+        Some(format!("{}...(truncated, 100000 bytes total)", "x".repeat(1024)).as_str())
+    );
+    // The dependent step still got the whole output
+    let measure = &result.step_results["measure"];
+    assert_eq!(measure.output.as_deref(), Some("100000"));
+    assert_eq!(measure.output_bytes, None);
+}
+
+#[tokio::test]
+async fn test_resume_reruns_steps_whose_output_was_truncated() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: big-output
+nodes:
+  - id: download
+    kind: big_body
+  - id: measure
+    kind: script
+    depends_on: [download]
+    config:
+      script: "steps.download.len()"
+"#,
+    )
+    .unwrap();
+    let mut registry = HandlerRegistry::with_builtins();
+    registry.register("big_body", BigBodyHandler);
+    let registry = Arc::new(registry);
+    let first = RunOptions {
+        registry: registry.clone(),
+        max_output_bytes: Some(1024),
+        ..Default::default()
+    };
+    let mut previous = run_flow_with_options(&flow, graph.clone(), &first).await.unwrap();
+    previous.step_results.insert("measure".into(), StepResult::failed(FailureKind::HandlerError, "boom"));
+
+    for only_failed in [false, true] {
+        let options = RunOptions {
+            registry: registry.clone(),
+            resume_from: Some(previous.clone()),
+            only_failed,
+            ..Default::default()
+        };
+        let result = run_flow_with_options(&flow, graph.clone(), &options).await.unwrap();
+
+        // `measure` saw the whole output again, not the truncated one
+        let download = &result.step_results["download"];
+        assert_eq!(download.output.as_ref().map(String::len), Some(100_000), "only_failed: {only_failed}");
+        assert_eq!(download.output_bytes, None);
+        assert_eq!(result.step_results["measure"].output.as_deref(), Some("100000"));
+    }
+}
+
+/// Test handler: records the `file` it was given; fails for `bad`
+struct FileHandler(Arc<Mutex<Vec<String>>>);
+
+#[async_trait]
+impl StepHandler for FileHandler {
+    async fn execute(&self, ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let file = ctx.step.config["file"].as_str().unwrap_or_default().to_string();
+        self.0.lock().unwrap().push(file.clone());
+        if file.ends_with("bad") {
+            return Err(format!("cannot read {file}").into());
+        }
+        Ok(format!("processed {file}").into())
+    }
+}
+
+/// Helper: runs a `for_each` step over the `files` input, 2 items at a time
+async fn run_for_each_over(files: &[&str]) -> (Vec<String>, StepResult) {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: fan-out
+inputs:
+  files: []
+nodes:
+  - id: process
+    kind: file
+    for_each: "{{ inputs.files }}"
+    config:
+      file: "data/{{ item }}"
+"#,
+    )
+    .unwrap();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = HandlerRegistry::new();
+    registry.register("file", FileHandler(seen.clone()));
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        inputs: [("files".to_string(), serde_yaml::to_value(files).unwrap())].into(),
+        max_parallel: 2,
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    let mut seen = seen.lock().unwrap().clone();
+    seen.sort();
+    (seen, result.step_results["process"].clone())
+}
+
+#[tokio::test]
+async fn test_for_each_runs_once_per_item_and_aggregates() {
+    let (seen, result) = run_for_each_over(&["a.csv", "b.csv", "c.csv"]).await;
+
+    assert_eq!(seen, ["data/a.csv", "data/b.csv", "data/c.csv"]);
+    assert_eq!(result.status, StepStatus::Success);
+    let outputs: Vec<String> = serde_json::from_str(result.output.as_deref().unwrap()).unwrap();
+    assert_eq!(outputs, ["processed data/a.csv", "processed data/b.csv", "processed data/c.csv"]);
+}
+
+#[tokio::test]
+async fn test_for_each_fails_if_any_item_fails() {
+    let (seen, result) = run_for_each_over(&["a.csv", "bad", "c.csv"]).await;
+
+    assert_eq!(seen.len(), 3);
+    assert_eq!(
+        result.status,
+        StepStatus::Failed(FailureKind::HandlerError, "1 of 3 items failed: item 1: cannot read data/bad".into())
+    );
+}
+
+#[tokio::test]
+async fn test_zero_sim_latency_runs_large_flow_quickly() {
+    // A 200-step chain: at the default 100-300ms per step this takes a minute
+    let steps: Vec<Step> = (0..200)
+        .map(|i| Step {
+            id: format!("s{i}"),
+            depends_on: if i == 0 { vec![] } else { vec![format!("s{}", i - 1).into()] },
+            ..Default::default()
+        })
+        .collect();
+    let edges = (1..200).map(|i| (i - 1, i)).collect();
+    let (flow, graph) = build_test_flow(steps, edges);
+    let options = RunOptions {
+        sim_latency_ms: Some((0, 0)),
+        ..Default::default()
+    };
+
+    let started = std::time::Instant::now();
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(result.step_results.len(), 200);
+    assert!(started.elapsed() < Duration::from_secs(2), "took {:?}", started.elapsed());
+}
+
+#[tokio::test]
+async fn test_flow_timeout_not_hit_by_fast_flow() {
+    let (flow, graph) = branching_flow();
+    let options = RunOptions {
+        flow_timeout_seconds: Some(10),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success));
+}
+
+#[tokio::test]
+async fn test_flow_timeout_fails_unfinished_steps() {
+    let mut registry = HandlerRegistry::new();
+    registry.register("slow", SlowHandler(Duration::from_secs(2)));
+
+    let steps = vec![
+        Step {
+            id: "first".into(),
+            kind: "slow".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "second".into(),
+            kind: "slow".into(),
+            depends_on: vec!["first".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        flow_timeout_seconds: Some(1),
+        ..Default::default()
+    };
+
+    let started = std::time::Instant::now();
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(2));
+    assert_eq!(result.status, RunStatus::Failed(FLOW_TIMEOUT.into()));
+    for step_id in ["first", "second"] {
+        assert_eq!(
+            result.step_results[step_id].status,
+            StepStatus::Failed(FailureKind::Cancelled, FLOW_TIMEOUT.into())
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_flow_timeout_compensates_succeeded_steps() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: timed-out
+timeout_seconds: 1
+nodes:
+  - id: create
+    kind: noop
+    compensation:
+      kind: undo
+  - id: wait
+    kind: slow
+    depends_on: [create]
+    compensation:
+      kind: undo
+"#,
+    )
+    .unwrap();
+    let undone = Arc::new(Mutex::new(Vec::new()));
+    let mut registry = HandlerRegistry::new();
+    registry.register("slow", SlowHandler(Duration::from_secs(2)));
+    registry.register("undo", RecordingHandler(undone.clone()));
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        sim_latency_ms: Some((0, 0)),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    // No `rollback_on_failure` needed: the timeout alone rolls back
+    assert_eq!(result.status, RunStatus::Failed(FLOW_TIMEOUT.into()));
+    assert_eq!(*undone.lock().unwrap(), vec!["create"]);
+    assert_eq!(result.rollback.len(), 1);
+    assert_eq!(result.rollback[0].step_id, "create");
+    assert_eq!(result.rollback[0].status, StepStatus::Success);
+}
+
+#[tokio::test]
+async fn test_stream_reports_steps_and_ends_with_history() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "noop".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+
+    let events: Vec<RunEvent> = run_flow_stream(flow, graph, RunOptions::default()).collect().await;
+
+    assert!(matches!(events.first(), Some(RunEvent::RunStarted { .. })));
+
+    let finished: Vec<&str> = events
+        .iter()
+        .filter_map(|event| match event {
+            RunEvent::StepFinished { step_id, .. } => Some(step_id.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(finished, vec!["a", "b"]);
+
+    match events.last() {
+        Some(RunEvent::RunFinished(history)) => {
+            assert_eq!(history.status, RunStatus::Success);
+            assert_eq!(history.step_results.len(), 2);
+        }
+        other => panic!("expected RunFinished last, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_on_event_callback_follows_topological_order() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "noop".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "c".into(),
+            kind: "noop".into(),
+            depends_on: vec!["b".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (1, 2)]);
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let collected = events.clone();
+    let options = RunOptions {
+        on_event: Some(Arc::new(move |event| collected.lock().unwrap().push(event))),
+        ..Default::default()
+    };
+
+    run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    let events = events.lock().unwrap();
+    let progress: Vec<String> = events
+        .iter()
+        .filter_map(|event| match event {
+            RunEvent::StepStarted { step_id, .. } => Some(format!("start {step_id}")),
+            RunEvent::StepFinished { step_id, .. } => Some(format!("finish {step_id}")),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        progress,
+        ["start a", "finish a", "start b", "finish b", "start c", "finish c"]
+    );
+    assert!(matches!(events.last(), Some(RunEvent::RunFinished(history)) if history.status == RunStatus::Success));
+}
+
+#[tokio::test]
+async fn test_independent_steps_start_in_declaration_order() {
+    let ids = ["zeta", "alpha", "mid", "omega", "beta"];
+    let steps: Vec<Step> = ids
+        .iter()
+        .map(|id| Step {
+            id: id.to_string(),
+            kind: "noop".into(),
+            ..Default::default()
+        })
+        .collect();
+    let (flow, _) = build_test_flow(steps.clone(), vec![]);
+
+    // Insert the nodes backwards, so graph order disagrees with the flow
+    let mut graph = StepGraph::new();
+    for step in steps.into_iter().rev() {
+        graph.add_node(StepNode { step });
+    }
+
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let collected = started.clone();
+    let options = RunOptions {
+        on_event: Some(Arc::new(move |event| {
+            if let RunEvent::StepStarted { step_id, .. } = event {
+                collected.lock().unwrap().push(step_id);
+            }
+        })),
+        ..Default::default()
+    };
+
+    run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(*started.lock().unwrap(), ids);
+}
+
+#[tokio::test]
+async fn test_resume_skips_steps_that_already_succeeded() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "noop".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "c".into(),
+            kind: "noop".into(),
+            depends_on: vec!["b".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (1, 2)]);
+
+    let mut previous = run_flow(&flow, graph.clone()).await.unwrap();
+    previous.step_results.insert("a".into(), StepResult::success("from last time".into()));
+    previous.step_results.insert("b".into(), StepResult::failed(FailureKind::HandlerError, "boom"));
+    previous.step_results.insert("c".into(), StepResult::skipped("Dependency 'b' failed"));
+
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let collected = started.clone();
+    let options = RunOptions {
+        resume_from: Some(previous),
+        on_event: Some(Arc::new(move |event| {
+            if let RunEvent::StepStarted { step_id, .. } = event {
+                collected.lock().unwrap().push(step_id);
+            }
+        })),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(*started.lock().unwrap(), ["b", "c"]);
+    assert_eq!(result.step_results["a"].output.as_deref(), Some("from last time"));
+}
+
+#[tokio::test]
+async fn test_only_failed_reruns_just_the_failed_steps() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: nightly
+nodes:
+  - id: fetch
+    kind: noop
+  - id: transform
+    kind: noop
+    depends_on: [fetch]
+  - id: publish
+    kind: noop
+    depends_on: [transform]
+  - id: cleanup
+    kind: noop
+"#,
+    )
+    .unwrap();
+
+    let mut previous = run_flow(&flow, graph.clone()).await.unwrap();
+    previous.step_results.insert("transform".into(), StepResult::failed(FailureKind::HandlerError, "boom"));
+    previous.step_results.insert("publish".into(), StepResult::skipped("Dependency 'transform' failed"));
+
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let collected = started.clone();
+    let options = RunOptions {
+        resume_from: Some(previous.clone()),
+        only_failed: true,
+        on_event: Some(Arc::new(move |event| {
+            if let RunEvent::StepStarted { step_id, .. } = event {
+                collected.lock().unwrap().push(step_id);
+            }
+        })),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph.clone(), &options).await.unwrap();
+
+    assert_eq!(*started.lock().unwrap(), ["transform"]);
+    assert_eq!(result.step_results["transform"].status, StepStatus::Success);
+    // `publish` could run now, but only failed steps are rerun — and it says so
+    let reason = format!("Not rerun (skipped in run {}: Dependency 'transform' failed)", previous.run_id);
+    assert_eq!(result.step_results["publish"].status, StepStatus::Skipped(reason));
+    assert_eq!(result.step_results["cleanup"].output, previous.step_results["cleanup"].output);
+
+    // A failed step whose dependency failed too can't be rerun by itself
+    previous.step_results.insert("fetch".into(), StepResult::failed(FailureKind::HandlerError, "boom"));
+    let options = RunOptions {
+        resume_from: Some(previous),
+        only_failed: true,
+        ..Default::default()
+    };
+    let err = run_flow_with_options(&flow, graph, &options).await.unwrap_err();
+    assert!(err.to_string().contains("Step 'transform' can't be rerun on its own"), "{err}");
+}
+
+#[tokio::test]
+async fn test_higher_priority_ready_step_starts_first() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: prioritized
+nodes:
+  - id: setup
+    kind: noop
+  - id: report
+    kind: noop
+    depends_on: [setup]
+  - id: hotfix
+    kind: noop
+    depends_on: [setup]
+    priority: 10
+  - id: cleanup
+    kind: noop
+    depends_on: [setup]
+    priority: -1
+"#,
+    )
+    .unwrap();
+
+    let started = Arc::new(Mutex::new(Vec::new()));
+    let collected = started.clone();
+    let options = RunOptions {
+        max_parallel: 1,
+        on_event: Some(Arc::new(move |event| {
+            if let RunEvent::StepStarted { step_id, .. } = event {
+                collected.lock().unwrap().push(step_id);
+            }
+        })),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(*started.lock().unwrap(), ["setup", "hotfix", "report", "cleanup"]);
+}
+
+#[tokio::test]
+async fn test_stage_tallies_count_outcomes_per_stage() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: staged
+nodes:
+  - id: compile
+    kind: noop
+    stage: build
+  - id: lint
+    kind: fail_test
+    stage: build
+  - id: upload
+    kind: noop
+    stage: deploy
+    depends_on: [compile]
+  - id: release
+    kind: noop
+    stage: deploy
+    depends_on: [lint]
+  - id: notify
+    kind: noop
+"#,
+    )
+    .unwrap();
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    let tally = |stage: Option<&str>, success, failed, skipped| StageTally {
+        stage: stage.map(String::from),
+        success,
+        failed,
+        skipped,
+        cancelled: 0,
+    };
+    assert_eq!(
+        stage_tallies(&flow, &result),
+        [tally(Some("build"), 1, 1, 0), tally(Some("deploy"), 1, 0, 1), tally(None, 1, 0, 0)]
+    );
+}
+
+#[tokio::test]
+async fn test_event_sender_receives_events_in_order() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "noop".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+    let (sender, mut receiver) = tokio::sync::mpsc::channel(16);
+    let options = RunOptions {
+        event_sender: Some(sender),
+        ..Default::default()
+    };
+
+    run_flow_with_options(&flow, graph, &options).await.unwrap();
+    drop(options);
+
+    let mut events = Vec::new();
+    while let Some(event) = receiver.recv().await {
+        events.push(match event {
+            RunEvent::RunStarted { .. } => "run started".to_string(),
+            RunEvent::StepStarted { step_id, .. } => format!("start {step_id}"),
+            RunEvent::StepFinished { step_id, .. } => format!("finish {step_id}"),
+            RunEvent::RunFinished(_) => "run finished".to_string(),
+            RunEvent::RunFailed(err) => format!("run failed: {err}"),
+        });
+    }
+    assert_eq!(events, ["run started", "start a", "finish a", "start b", "finish b", "run finished"]);
+}
+
+#[tokio::test]
+async fn test_event_sender_without_receiver_does_not_stop_the_run() {
+    let (flow, graph) = build_test_flow(vec![Step { id: "a".into(), ..Default::default() }], vec![]);
+    let (sender, receiver) = tokio::sync::mpsc::channel(1);
+    drop(receiver);
+    let options = RunOptions {
+        event_sender: Some(sender),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert_eq!(result.status, RunStatus::Success);
+}
+
+/// Helper: `check` echoes `check_output`, `deploy` runs only `when` holds
+fn conditional_flow(check_output: &str, when: &str) -> (Flow, StepGraph) {
+    let steps = vec![
+        Step {
+            id: "check".into(),
+            kind: "shell".into(),
+            config: serde_yaml::from_str(&format!("{{ command: echo, args: [{check_output}] }}")).unwrap(),
+            ..Default::default()
+        },
+        Step {
+            id: "deploy".into(),
+            kind: "noop".into(),
+            depends_on: vec!["check".into()],
+            when: Some(when.into()),
+            ..Default::default()
+        },
+    ];
+    build_test_flow(steps, vec![(0, 1)])
+}
+
+#[tokio::test]
+async fn test_when_condition_satisfied_runs_step() {
+    let (flow, graph) = conditional_flow("ready", r#"steps.check.output == "ready""#);
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(result.step_results["deploy"].status, StepStatus::Success);
+}
+
+#[tokio::test]
+async fn test_when_condition_false_skips_step() {
+    let (flow, graph) = conditional_flow("pending", r#"steps.check.output == "ready""#);
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(
+        result.step_results["deploy"].status,
+        StepStatus::Skipped("condition false".into())
+    );
+    assert_eq!(result.step_results["deploy"].output, None);
+}
+
+#[tokio::test]
+async fn test_idempotency_key_reuses_result_within_run() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("charge", CountingHandler(calls.clone()));
+
+    let steps = vec![
+        Step {
+            id: "charge".into(),
+            kind: "charge".into(),
+            idempotency_key: Some("order-42".into()),
+            ..Default::default()
+        },
+        Step {
+            id: "charge_again".into(),
+            kind: "charge".into(),
+            depends_on: vec!["charge".into()],
+            idempotency_key: Some("order-42".into()),
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(calls.load(Ordering::SeqCst), 1, "handler should run once per key");
+    assert_eq!(result.step_results["charge_again"].output.as_deref(), Some("counted"));
+
+    // The cache doesn't outlive the run
+    let (flow, graph) = build_test_flow(flow.nodes.clone(), vec![(0, 1)]);
+    run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+/// Fails with the given message on every call, counting attempts
+struct FailingHandler {
+    message: &'static str,
+    calls: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl StepHandler for FailingHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Err(self.message.into())
+    }
+}
+
+/// Helper: runs one step failing with `message` under a 3-attempt policy
+async fn run_with_retry_on(message: &'static str, retry_on: Option<Vec<String>>) -> usize {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("flaky", FailingHandler { message, calls: calls.clone() });
+
+    let step = Step {
+        id: "call".into(),
+        kind: "flaky".into(),
+        retry: Some(RetryPolicy {
+            max_attempts: 3,
+            backoff_seconds: 0,
+            retry_on,
+        }),
+        ..Default::default()
+    };
+    let (flow, graph) = build_test_flow(vec![step], vec![]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert_eq!(result.step_results["call"].status, StepStatus::Failed(FailureKind::HandlerError, message.into()));
+
+    calls.load(Ordering::SeqCst)
+}
+
+#[tokio::test]
+async fn test_retry_on_matching_transient_error_retries() {
+    let attempts = run_with_retry_on("503 Service Unavailable", Some(vec!["503".into()])).await;
+    assert_eq!(attempts, 3);
+}
+
+#[tokio::test]
+async fn test_retry_on_non_matching_error_fails_immediately() {
+    let attempts = run_with_retry_on("invalid payload: missing sku", Some(vec!["503".into()])).await;
+    assert_eq!(attempts, 1);
+}
+
+#[tokio::test]
+async fn test_retry_without_retry_on_retries_any_failure() {
+    let attempts = run_with_retry_on("invalid payload: missing sku", None).await;
+    assert_eq!(attempts, 3);
+}
+
+#[tokio::test]
+async fn test_retry_budget_stops_retries_once_used_up() {
+    let first_calls = Arc::new(AtomicUsize::new(0));
+    let second_calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("flaky_first", FailingHandler { message: "boom", calls: first_calls.clone() });
+    registry.register("flaky_second", FailingHandler { message: "boom", calls: second_calls.clone() });
+
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        backoff_seconds: 0,
+        retry_on: None,
+    };
+    let steps = vec![
+        Step {
+            id: "first".into(),
+            kind: "flaky_first".into(),
+            retry: Some(policy.clone()),
+            ..Default::default()
+        },
+        Step {
+            id: "second".into(),
+            kind: "flaky_second".into(),
+            depends_on: vec![Dependency {
+                target: DependencyTarget::Step("first".into()),
+                on: DependOn::Completion,
+            }],
+            retry: Some(policy),
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1)]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        retry_budget: Some(2),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    // `first` spends both retries; `second` gets none left
+    assert_eq!(first_calls.load(Ordering::SeqCst), 3);
+    assert_eq!(second_calls.load(Ordering::SeqCst), 1);
+    assert_eq!(result.step_results["second"].status, StepStatus::Failed(FailureKind::HandlerError, "boom".into()));
+}
+
+#[tokio::test]
+async fn test_default_retry_applies_to_steps_without_a_policy() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let mut registry = HandlerRegistry::new();
+    registry.register("flaky", FailingHandler { message: "boom", calls: calls.clone() });
+
+    let steps = vec![
+        Step {
+            id: "inherits".into(),
+            kind: "flaky".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "own_policy".into(),
+            kind: "flaky".into(),
+            retry: Some(RetryPolicy {
+                max_attempts: 1,
+                backoff_seconds: 0,
+                retry_on: None,
+            }),
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![]);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        default_retry: Some(RetryPolicy {
+            max_attempts: 3,
+            backoff_seconds: 0,
+            retry_on: None,
+        }),
+        ..Default::default()
+    };
+
+    run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    // 3 attempts under the default, 1 under the step's own policy
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+}
+
+/// Reports that it started, then waits for a message that never comes
+struct WaitingHandler {
+    started: tokio::sync::mpsc::UnboundedSender<()>,
+}
+
+#[async_trait]
+impl StepHandler for WaitingHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let _ = self.started.send(());
+        std::future::pending().await
+    }
+}
+
+#[tokio::test]
+async fn test_cancel_marks_in_flight_and_remaining_steps_cancelled() {
+    let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut registry = HandlerRegistry::new();
+    registry.register("wait", WaitingHandler { started: started_tx });
+
+    let steps = vec![
+        Step {
+            id: "prepare".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "wait".into(),
+            kind: "wait".into(),
+            depends_on: vec!["prepare".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "publish".into(),
+            kind: "noop".into(),
+            depends_on: vec!["wait".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (1, 2)]);
+    let cancel = CancellationToken::new();
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        cancel: Some(cancel.clone()),
+        ..Default::default()
+    };
+
+    let canceller = async {
+        started_rx.recv().await.expect("wait step never started");
+        cancel.cancel();
+    };
+    let (result, _) = tokio::join!(run_flow_with_options(&flow, graph, &options), canceller);
+    let result = result.unwrap();
+
+    assert_eq!(result.status, RunStatus::Failed(CANCELLED.into()));
+    assert_eq!(result.step_results["prepare"].status, StepStatus::Success);
+    assert_eq!(result.step_results["wait"].status, StepStatus::Cancelled);
+    assert_eq!(result.step_results["publish"].status, StepStatus::Cancelled);
+    assert_eq!(result.step_results["wait"].status.failure_kind(), Some(FailureKind::Cancelled));
+}
+
+#[tokio::test]
+async fn test_cancel_token_after_first_step_cancels_the_rest() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "noop".into(),
+            depends_on: vec!["a".into()],
+            ..Default::default()
+        },
+        Step {
+            id: "c".into(),
+            kind: "noop".into(),
+            depends_on: vec!["b".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (1, 2)]);
+
+    let cancel = CancellationToken::new();
+    let token = cancel.clone();
+    let options = RunOptions {
+        cancel: Some(cancel),
+        on_event: Some(Arc::new(move |event| {
+            if matches!(event, RunEvent::StepFinished { ref step_id, .. } if step_id == "a") {
+                token.cancel();
+            }
+        })),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Failed(CANCELLED.into()));
+    assert_eq!(result.step_results["a"].status, StepStatus::Success);
+    assert_eq!(result.step_results["b"].status, StepStatus::Cancelled);
+    assert_eq!(result.step_results["c"].status, StepStatus::Cancelled);
+}
+
+#[tokio::test]
+async fn test_disabled_step_lets_dependents_run() {
+    let steps = vec![
+        Step {
+            id: "a".into(),
+            kind: "noop".into(),
+            ..Default::default()
+        },
+        Step {
+            id: "b".into(),
+            kind: "fail_test".into(),
+            depends_on: vec!["a".into()],
+            enabled: false,
+            ..Default::default()
+        },
+        Step {
+            id: "c".into(),
+            kind: "noop".into(),
+            depends_on: vec!["b".into()],
+            ..Default::default()
+        },
+    ];
+    let (flow, graph) = build_test_flow(steps, vec![(0, 1), (1, 2)]);
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(result.step_results["b"].status, StepStatus::Skipped(DISABLED.into()));
+    assert_eq!(result.step_results["c"].status, StepStatus::Success);
+}
+
+/// Helper: `deploy` (of kind `deploy_kind`) with a `page_oncall` branch on
+/// its failure, and a `report` step after the branch
+async fn run_with_failure_branch(deploy_kind: &str) -> HashMap<String, StepStatus> {
+    let (flow, graph) = load_flow_from_str(&format!(
+        r#"
+id: failure-branch
+nodes:
+  - id: deploy
+    kind: {deploy_kind}
+  - id: page_oncall
+    kind: noop
+    on_failure: [deploy]
+  - id: report
+    kind: noop
+    depends_on: [page_oncall]
+"#
+    ))
+    .unwrap();
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    result
+        .step_results
+        .into_iter()
+        .map(|(id, step)| (id, step.status))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_on_failure_step_runs_when_upstream_fails() {
+    let statuses = run_with_failure_branch("fail_test").await;
+
+    assert!(matches!(statuses["deploy"], StepStatus::Failed(..)));
+    assert_eq!(statuses["page_oncall"], StepStatus::Success);
+    assert_eq!(statuses["report"], StepStatus::Success);
+}
+
+#[tokio::test]
+async fn test_on_failure_step_is_skipped_when_upstream_succeeds() {
+    let statuses = run_with_failure_branch("noop").await;
+
+    assert_eq!(statuses["deploy"], StepStatus::Success);
+    assert_eq!(statuses["page_oncall"], StepStatus::Skipped("No on_failure step failed".into()));
+    assert!(matches!(statuses["report"], StepStatus::Skipped(_)));
+}
+
+#[tokio::test]
+async fn test_inputs_are_substituted_into_step_config() {
+    let step = Step {
+        id: "greet".into(),
+        kind: "shell".into(),
+        config: serde_yaml::from_str(
+            r#"{ command: echo, args: ["customer {{ inputs.customer_id }} in {{ inputs.region }}"] }"#,
+        )
+        .unwrap(),
+        ..Default::default()
+    };
+    let (mut flow, graph) = build_test_flow(vec![step], vec![]);
+    flow.inputs = serde_yaml::from_str("{ customer_id: null, region: eu }").unwrap();
+
+    let options = RunOptions {
+        inputs: [("customer_id".to_string(), serde_yaml::Value::from("c-42"))].into(),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(result.step_results["greet"].output.as_deref(), Some("customer c-42 in eu"));
+}
+
+#[tokio::test]
+async fn test_missing_required_input_fails_the_run() {
+    let step = Step {
+        id: "a".into(),
+        kind: "noop".into(),
+        ..Default::default()
+    };
+    let (mut flow, graph) = build_test_flow(vec![step], vec![]);
+    flow.inputs = serde_yaml::from_str("{ customer_id: null }").unwrap();
+
+    let err = run_flow(&flow, graph).await.expect_err("customer_id is required");
+    assert!(err.to_string().contains("customer_id"), "{err}");
+}
+
+/// Sleeps briefly while tracking how many of its steps run at the same time
+#[derive(Clone, Default)]
+struct OverlapHandler {
+    current: Arc<AtomicUsize>,
+    peak: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl StepHandler for OverlapHandler {
+    async fn execute(&self, _ctx: &StepContext) -> Result<HandlerOutput, HandlerError> {
+        let now = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        self.current.fetch_sub(1, Ordering::SeqCst);
+        Ok("done".into())
+    }
+}
+
+#[tokio::test]
+async fn test_kind_limit_serializes_capped_kind_only() {
+    let capped = OverlapHandler::default();
+    let free = OverlapHandler::default();
+    let mut registry = HandlerRegistry::new();
+    registry.register("db_upsert", capped.clone());
+    registry.register("fetch", free.clone());
+
+    let steps: Vec<Step> = ["upsert_1", "upsert_2", "fetch_1", "fetch_2"]
+        .into_iter()
+        .map(|id| Step {
+            id: id.into(),
+            kind: if id.starts_with("upsert") { "db_upsert" } else { "fetch" }.into(),
+            ..Default::default()
+        })
+        .collect();
+    let (mut flow, graph) = build_test_flow(steps, vec![]);
+    flow.concurrency = [("db_upsert".to_string(), 1)].into();
+
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        max_parallel: 4,
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(capped.peak.load(Ordering::SeqCst), 1, "db_upsert steps overlapped");
+    assert_eq!(free.peak.load(Ordering::SeqCst), 2, "fetch steps should run together");
+}
+
+#[tokio::test]
+async fn test_for_each_items_share_the_run_wide_parallelism_limit() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: fan-out
+nodes:
+  - id: left
+    kind: fetch
+    for_each: "[1, 2, 3, 4]"
+  - id: right
+    kind: fetch
+    for_each: "[1, 2, 3, 4]"
+  - id: single
+    kind: fetch
+"#,
+    )
+    .unwrap();
+    let overlap = OverlapHandler::default();
+    let mut registry = HandlerRegistry::new();
+    registry.register("fetch", overlap.clone());
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        max_parallel: 3,
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert_eq!(overlap.peak.load(Ordering::SeqCst), 3, "more than max_parallel handlers ran at once");
+}
+
+#[tokio::test]
+async fn test_rate_limit_spaces_out_steps_of_a_kind() {
+    let (flow, graph) = load_flow_from_str(
+        r#"
+id: rate-limited
+rate_limits:
+  fetch: { per_second: 10 }
+nodes:
+  - { id: fetch_1, kind: fetch }
+  - { id: fetch_2, kind: fetch }
+  - { id: fetch_3, kind: fetch }
+  - { id: fetch_4, kind: fetch }
+  - { id: fetch_5, kind: fetch }
+  - { id: fetch_6, kind: fetch }
+  - { id: other, kind: noop }
+"#,
+    )
+    .unwrap();
+    let options = RunOptions {
+        max_parallel: 7,
+        sim_latency_ms: Some((0, 0)),
+        ..Default::default()
+    };
+
+    let started = std::time::Instant::now();
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    // Six calls at 10/s, spaced evenly: at least five 100ms gaps
+    assert!(started.elapsed() >= Duration::from_millis(480), "{:?}", started.elapsed());
+}
+
+#[tokio::test]
+#[traced_test]
+async fn test_step_logs_are_inside_run_and_step_spans() {
+    let steps = vec![Step {
+        id: "fetch".into(),
+        kind: "noop".into(),
+        ..Default::default()
+    }];
+    let (flow, graph) = build_test_flow(steps, vec![]);
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert_eq!(result.status, RunStatus::Success);
+    assert!(logs_contain(&format!("run{{run_id={} flow_id=test-flow}}", result.run_id)));
+    assert!(logs_contain("step{id=fetch kind=noop}"));
+}
@@ -1,9 +1,18 @@
 #![allow(dead_code)]
 
-use tiny_agent_graph::flow::load_flow;
+use tiny_agent_graph::flow::{
+    build_step_graph, critical_path, diff_graphs, execution_levels, flow_stats, graph_hash,
+    load_flow, load_flow_strict, load_flows, normalize_flow, select_flow, to_ascii_tree,
+    unreachable_steps, validate_configs, validate_kinds, validate_templates, DependOn, Dependency,
+    DependencyTarget, Flow, FlowError, LabelMode, Step, FLOW_FORMAT_VERSION,
+};
+use tiny_agent_graph::engine::{ready_steps, FailureKind, StepStatus};
+use tiny_agent_graph::handlers::HandlerRegistry;
 use petgraph::algo::is_cyclic_directed;
+use std::collections::HashMap;
 use tempfile::NamedTempFile;
 use std::io::Write;
+use tracing_test::traced_test;
 
 fn write_yaml(contents: &str) -> NamedTempFile {
     let mut tmp = NamedTempFile::new().expect("Failed to create temp file");
@@ -60,6 +69,84 @@ nodes:
     assert!(!is_cyclic_directed(&graph));
 }
 
+#[test]
+fn test_ready_steps_frontier_advances_along_linear_flow() {
+    let yaml = r#"
+id: test-flow
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+  - id: c
+    kind: noop
+    depends_on: [b]
+"#;
+    let file = write_yaml(yaml);
+    let (_flow, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let mut completed = HashMap::new();
+    assert_eq!(ready_steps(&graph, &completed), ["a"]);
+
+    completed.insert("a".to_string(), StepStatus::Success);
+    assert_eq!(ready_steps(&graph, &completed), ["b"]);
+
+    completed.insert("b".to_string(), StepStatus::Success);
+    assert_eq!(ready_steps(&graph, &completed), ["c"]);
+
+    completed.insert("c".to_string(), StepStatus::Success);
+    assert!(ready_steps(&graph, &completed).is_empty());
+}
+
+#[test]
+fn test_ready_steps_stalls_behind_failed_dependency() {
+    let yaml = r#"
+id: test-flow
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+"#;
+    let file = write_yaml(yaml);
+    let (_flow, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let completed = HashMap::from([("a".to_string(), StepStatus::Failed(FailureKind::HandlerError, "boom".into()))]);
+    assert!(ready_steps(&graph, &completed).is_empty());
+}
+
+#[test]
+fn test_flow_stats_of_branching_flow() {
+    let yaml = r#"
+id: branch-flow
+nodes:
+  - id: start
+    kind: noop
+  - id: a
+    kind: noop
+    depends_on: [start]
+  - id: b
+    kind: noop
+    depends_on: [start]
+  - id: end
+    kind: noop
+    depends_on: [a, b]
+"#;
+
+    let file = write_yaml(yaml);
+    let (_flow, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let stats = flow_stats(&graph);
+    assert_eq!(stats.nodes, 4);
+    assert_eq!(stats.edges, 4);
+    assert_eq!(stats.roots, 1);
+    assert_eq!(stats.leaves, 1);
+    assert_eq!(stats.max_depth, 3); // start → a → end
+    assert_eq!(stats.max_fan_out, 2); // start → a, b
+}
+
 #[test]
 fn test_detects_cycle() {
     let yaml = r#"
@@ -80,12 +167,45 @@ nodes:
     let result = load_flow(file.path());
 
     assert!(result.is_err());
-    let err = result.err().unwrap().to_string();
+    let err = result.err().unwrap();
+    assert!(matches!(err, FlowError::Cycle { .. }), "unexpected error: {err:?}");
+    let err = err.to_string();
     assert!(err.contains("cycle"), "Error did not contain 'cycle': {}", err);
+    assert!(err.contains("a -> b -> c -> a"), "Error did not list the cycle in order: {}", err);
+}
+
+#[test]
+fn test_cycle_error_suggests_dependency_to_remove() {
+    let yaml = r#"
+id: bad-flow
+nodes:
+  - id: a
+    kind: noop
+    depends_on: [c]
+  - id: b
+    kind: noop
+    depends_on: [a]
+  - id: c
+    kind: noop
+    depends_on: [b]
+"#;
+
+    let file = write_yaml(yaml);
+    let err = load_flow(file.path()).expect_err("cyclic flow should be rejected");
+
+    // `a` is declared first but depends on `c`, declared last
+    match &err {
+        FlowError::Cycle { remove, .. } => assert_eq!(remove, &("c".to_string(), "a".to_string())),
+        other => panic!("expected a cycle error, got {other:?}"),
+    }
+    assert!(
+        err.to_string().contains("consider removing dependency 'c' -> 'a'"),
+        "{err}"
+    );
 }
 
 #[test]
-fn test_missing_dependency_warns_but_does_not_crash() {
+fn test_unknown_dependency_is_rejected() {
     let yaml = r#"
 id: warn-flow
 nodes:
@@ -95,11 +215,299 @@ nodes:
 "#;
 
     let file = write_yaml(yaml);
-    let result = load_flow(file.path());
+    let err = load_flow(file.path()).expect_err("Missing dep should be rejected");
 
-    assert!(result.is_ok(), "Missing dep should warn, not error");
-    let (_flow, graph) = result.unwrap();
-    assert_eq!(graph.node_count(), 1);
+    match err {
+        FlowError::UnknownDependency { step, dep } => {
+            assert_eq!(step, "x");
+            assert_eq!(dep, "nonexistent_step");
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+fn test_depends_on_accepts_plain_ids_and_edge_conditions() {
+    let yaml = r#"
+id: with-cleanup
+nodes:
+  - id: deploy
+    kind: noop
+  - id: smoke_test
+    kind: noop
+    depends_on: [deploy]
+  - id: cleanup
+    kind: noop
+    depends_on:
+      - { step: deploy, on: completion }
+      - smoke_test
+"#;
+    let file = write_yaml(yaml);
+    let (flow, graph) = load_flow(file.path()).expect("flow should load");
+    assert_eq!(graph.edge_count(), 3);
+
+    assert_eq!(flow.nodes[1].depends_on, vec![Dependency::from("deploy")]);
+    assert_eq!(
+        flow.nodes[2].depends_on,
+        vec![
+            Dependency {
+                target: DependencyTarget::Step("deploy".into()),
+                on: DependOn::Completion,
+            },
+            Dependency::from("smoke_test"),
+        ]
+    );
+
+    // The default keeps its short form when written back out
+    let normalized = normalize_flow(yaml).expect("normalize failed");
+    assert!(normalized.contains("on: completion"), "{normalized}");
+    assert!(normalized.contains("- smoke_test"), "{normalized}");
+}
+
+/// Helper: an in-memory flow from (id, depends_on) pairs
+fn flow_of(steps: &[(&str, &[&str])]) -> Flow {
+    Flow {
+        id: "in-memory".into(),
+        nodes: steps
+            .iter()
+            .map(|(id, deps)| Step {
+                id: id.to_string(),
+                depends_on: deps.iter().map(|d| (*d).into()).collect(),
+                ..Default::default()
+            })
+            .collect(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_build_step_graph_reports_cycle_variant() {
+    let flow = flow_of(&[("a", &["b"]), ("b", &["a"])]);
+    let err = build_step_graph(&flow).expect_err("cycle should be rejected");
+    assert!(matches!(err, FlowError::Cycle { .. }), "unexpected error: {err:?}");
+}
+
+#[test]
+fn test_build_step_graph_rejects_duplicate_ids() {
+    let flow = flow_of(&[("a", &[]), ("a", &[])]);
+    let err = build_step_graph(&flow).expect_err("duplicate should be rejected");
+    assert!(matches!(err, FlowError::DuplicateId { ref id } if id == "a"), "unexpected error: {err:?}");
+}
+
+#[test]
+fn test_build_step_graph_rejects_self_dependency() {
+    let flow = flow_of(&[("a", &["a"])]);
+    let err = build_step_graph(&flow).expect_err("self-dependency should be rejected");
+    assert!(matches!(err, FlowError::SelfDependency { ref step } if step == "a"), "unexpected error: {err:?}");
+}
+
+#[test]
+fn test_validate_checks_in_memory_flows() {
+    assert!(flow_of(&[("a", &[]), ("b", &["a"])]).validate().is_ok());
+
+    let err = flow_of(&[("a", &[]), ("a", &[])]).validate().unwrap_err();
+    assert!(matches!(err, FlowError::DuplicateId { ref id } if id == "a"), "unexpected error: {err:?}");
+
+    let err = flow_of(&[("a", &[]), ("b", &["b"])]).validate().unwrap_err();
+    assert!(matches!(err, FlowError::SelfDependency { ref step } if step == "b"), "unexpected error: {err:?}");
+
+    let err = flow_of(&[("a", &["ghost"])]).validate().unwrap_err();
+    assert!(
+        matches!(err, FlowError::UnknownDependency { ref step, ref dep } if step == "a" && dep == "ghost"),
+        "unexpected error: {err:?}"
+    );
+
+    let err = flow_of(&[("a", &["c"]), ("b", &["a"]), ("c", &["b"])]).validate().unwrap_err();
+    assert!(matches!(err, FlowError::Cycle { .. }), "unexpected error: {err:?}");
+}
+
+#[test]
+fn test_ascii_tree_draws_shared_steps_once() {
+    let flow = flow_of(&[
+        ("extract", &[]),
+        ("clean", &["extract"]),
+        ("enrich", &["extract"]),
+        ("load", &["clean", "enrich"]),
+        ("audit", &[]),
+    ]);
+    let graph = build_step_graph(&flow).unwrap();
+
+    let tree = to_ascii_tree(&graph, LabelMode::Id);
+
+    for step in &flow.nodes {
+        assert!(tree.contains(&step.id), "{tree}");
+    }
+    assert_eq!(
+        tree,
+        "audit\n\
+         extract\n\
+         ├── clean\n\
+         │   └── load\n\
+         └── enrich\n\
+         \x20   └── load (*)\n\
+         (*) already shown above\n"
+    );
+}
+
+#[test]
+fn test_ascii_tree_and_levels_ignore_declaration_order() {
+    let flow = flow_of(&[
+        ("publish", &["test", "lint"]),
+        ("lint", &["checkout"]),
+        ("test", &["checkout", "build"]),
+        ("build", &["checkout"]),
+        ("checkout", &[]),
+        ("announce", &[]),
+    ]);
+    let mut reversed = flow.clone();
+    reversed.nodes.reverse();
+    let graph = build_step_graph(&flow).unwrap();
+    let reversed_graph = build_step_graph(&reversed).unwrap();
+
+    let tree = to_ascii_tree(&graph, LabelMode::Id);
+    assert_eq!(tree, to_ascii_tree(&graph, LabelMode::Id));
+    assert_eq!(tree, to_ascii_tree(&reversed_graph, LabelMode::Id));
+    assert_eq!(
+        tree,
+        "announce\n\
+         checkout\n\
+         ├── build\n\
+         │   └── test\n\
+         │       └── publish\n\
+         ├── lint\n\
+         │   └── publish (*)\n\
+         └── test (*)\n\
+         (*) already shown above\n"
+    );
+
+    let levels = execution_levels(&graph);
+    assert_eq!(levels, execution_levels(&reversed_graph));
+    assert_eq!(levels, [vec!["announce", "checkout"], vec!["build", "lint"], vec!["test"], vec!["publish"]]);
+}
+
+#[test]
+fn test_parses_step_description() {
+    let yaml = r#"
+id: described
+nodes:
+  - id: fetch
+    kind: http_get
+    description: Pull the latest catalog
+  - id: store
+    kind: noop
+"#;
+    let file = write_yaml(yaml);
+    let (flow, _) = load_flow(file.path()).unwrap();
+
+    assert_eq!(flow.nodes[0].description.as_deref(), Some("Pull the latest catalog"));
+    assert_eq!(flow.nodes[1].description, None);
+}
+
+#[test]
+fn test_diff_graphs_reports_kind_changes_and_removed_steps() {
+    let graph_of = |yaml: &str| {
+        let file = write_yaml(yaml);
+        load_flow(file.path()).unwrap().1
+    };
+    let old = graph_of("id: f\nnodes:\n  - { id: fetch, kind: http_get }\n  - { id: audit, kind: noop, depends_on: [fetch] }\n");
+    let new = graph_of("id: f\nnodes:\n  - { id: fetch, kind: http_post }\n");
+
+    let changes: Vec<String> = diff_graphs(&old, &new).iter().map(ToString::to_string).collect();
+    assert_eq!(changes, ["- step 'audit'", "~ step 'fetch' kind http_get -> http_post", "- edge fetch->audit"]);
+    assert!(diff_graphs(&new, &new).is_empty());
+}
+
+#[test]
+fn test_graph_hash_ignores_ordering_but_not_structure() {
+    let hash_of = |yaml: &str| {
+        let file = write_yaml(yaml);
+        let (_, graph) = load_flow(file.path()).unwrap();
+        graph_hash(&graph)
+    };
+
+    let original = hash_of(
+        r#"
+id: pipeline
+nodes:
+  - id: fetch
+    kind: http_get
+    config: { url: "https://example.com" }
+  - id: parse
+    kind: noop
+    depends_on: [fetch]
+  - id: store
+    kind: db_upsert
+    depends_on: [fetch, parse]
+"#,
+    );
+    let reordered = hash_of(
+        r#"
+nodes:
+  - depends_on: [parse, fetch]
+    kind: db_upsert
+    id: store
+  - kind: http_get
+    id: fetch
+    config: { url: "https://example.com" }
+  - id: parse
+    depends_on: [fetch]
+    kind: noop
+id: pipeline
+"#,
+    );
+    let rewired = hash_of(
+        r#"
+id: pipeline
+nodes:
+  - id: fetch
+    kind: http_get
+  - id: parse
+    kind: noop
+    depends_on: [fetch]
+  - id: store
+    kind: db_upsert
+    depends_on: [parse]
+"#,
+    );
+
+    assert_eq!(original, reordered);
+    assert_ne!(original, rewired);
+}
+
+#[test]
+fn test_match_label_dependency_expands_to_every_labeled_step() {
+    let yaml = r#"
+id: nightly-report
+nodes:
+  - id: report
+    kind: noop
+    labels: [nightly]
+    depends_on:
+      - { match_label: nightly }
+  - id: backup
+    kind: noop
+    labels: [nightly]
+  - id: vacuum
+    kind: noop
+    labels: [nightly, db]
+  - id: reindex
+    kind: noop
+    labels: [nightly]
+  - id: deploy
+    kind: noop
+"#;
+    let file = write_yaml(yaml);
+    let (flow, graph) = load_flow(file.path()).expect("flow should load");
+
+    let report = graph.node_indices().find(|idx| graph[*idx].step.id == "report").unwrap();
+    assert_eq!(graph.neighbors_directed(report, petgraph::Direction::Incoming).count(), 3);
+    let deps: Vec<&str> = graph[report].step.upstream_ids().map(String::as_str).collect();
+    assert_eq!(deps, ["backup", "vacuum", "reindex"]);
+    assert_eq!(ready_steps(&graph, &HashMap::new()), ["backup", "vacuum", "reindex", "deploy"]);
+
+    // The flow itself keeps the label form, e.g. when written back out
+    assert_eq!(flow.nodes[0].depends_on[0].target, DependencyTarget::Label("nightly".into()));
+    assert!(normalize_flow(yaml).unwrap().contains("match_label: nightly"));
 }
 
 #[test]
@@ -131,3 +539,488 @@ nodes:
     assert_eq!(comp.kind, "http_delete");
     assert!(comp.config["url"].as_str().unwrap().contains("example.com"));
 }
+
+#[test]
+fn test_validate_kinds_reports_unknown_kinds() {
+    let yaml = r#"
+id: typo-flow
+nodes:
+  - id: a
+    kind: shell
+  - id: b
+    kind: shel
+  - id: c
+    kind: htpp_get
+"#;
+
+    let file = write_yaml(yaml);
+    let (flow, _graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let err = validate_kinds(&flow, &HandlerRegistry::with_builtins())
+        .expect_err("unknown kinds should be rejected");
+    assert!(matches!(err, FlowError::UnknownKinds(ref steps) if steps.len() == 2));
+    let err = err.to_string();
+    assert!(err.contains("'b' (kind 'shel')"), "{err}");
+    assert!(err.contains("'c' (kind 'htpp_get')"), "{err}");
+    assert!(!err.contains("'a'"), "{err}");
+}
+
+/// Helper: a registry whose `http_get` steps need a `url` string
+fn registry_with_url_schema() -> HandlerRegistry {
+    let mut registry = HandlerRegistry::new();
+    registry
+        .register_schema(
+            "http_get",
+            serde_json::json!({
+                "type": "object",
+                "required": ["url"],
+                "properties": { "url": { "type": "string" } }
+            }),
+        )
+        .expect("schema should compile");
+    registry
+}
+
+#[test]
+fn test_validate_templates_rejects_forward_references() {
+    let yaml = r#"
+id: template-flow
+inputs:
+  region: eu
+nodes:
+  - id: report
+    kind: shell
+    config:
+      command: "echo {{ steps.fetch.output }} for {{ inputs.region }}"
+  - id: fetch
+    kind: noop
+    depends_on: [report]
+    config:
+      target: "{{ inputs.bucket }}"
+"#;
+    let file = write_yaml(yaml);
+    let (flow, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    match validate_templates(&flow, &graph) {
+        Err(FlowError::InvalidTemplates(problems)) => assert_eq!(
+            problems,
+            [
+                "step 'report': step 'fetch' is not one of its dependencies, so its output isn't available yet",
+                "step 'fetch': input 'bucket' is not declared",
+            ]
+        ),
+        other => panic!("expected InvalidTemplates, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_validate_templates_accepts_backward_references() {
+    let yaml = r#"
+id: template-flow
+inputs:
+  region: eu
+nodes:
+  - id: fetch
+    kind: noop
+  - id: parse
+    kind: noop
+    depends_on: [fetch]
+  - id: report
+    kind: shell
+    depends_on: [parse]
+    config:
+      command: "echo {{ steps.fetch.output }} for {{ inputs.region }}"
+"#;
+    let file = write_yaml(yaml);
+    let (flow, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    assert!(validate_templates(&flow, &graph).is_ok());
+}
+
+#[test]
+fn test_validate_configs_reports_schema_violations() {
+    let yaml = r#"
+id: schema-flow
+nodes:
+  - id: ok
+    kind: http_get
+    config:
+      url: https://example.com
+  - id: missing_url
+    kind: http_get
+    config:
+      uri: https://example.com
+  - id: wrong_type
+    kind: http_get
+    config:
+      url: 42
+  - id: no_schema
+    kind: noop
+    config:
+      anything: goes
+"#;
+    let file = write_yaml(yaml);
+    let (flow, _graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let err = validate_configs(&flow, &registry_with_url_schema()).expect_err("bad configs should be rejected");
+    assert!(matches!(err, FlowError::InvalidConfig(ref problems) if problems.len() == 2), "{err:?}");
+    let err = err.to_string();
+    assert!(err.contains("step 'missing_url': \"url\" is a required property"), "{err}");
+    assert!(err.contains("step 'wrong_type': `url`:"), "{err}");
+    assert!(!err.contains("'ok'") && !err.contains("'no_schema'"), "{err}");
+}
+
+#[test]
+fn test_validate_configs_checks_builtin_shell_config() {
+    let yaml = r#"
+id: shell-flow
+nodes:
+  - id: greet
+    kind: shell
+    config:
+      args: [hello]
+"#;
+    let file = write_yaml(yaml);
+    let (flow, _graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let err = validate_configs(&flow, &HandlerRegistry::with_builtins()).expect_err("missing command");
+    assert!(err.to_string().contains("step 'greet': \"command\" is a required property"), "{err}");
+}
+
+#[test]
+fn test_validate_kinds_accepts_registered_kinds() {
+    let yaml = r#"
+id: known-flow
+nodes:
+  - id: a
+    kind: shell
+  - id: b
+    kind: shell
+    depends_on: [a]
+"#;
+
+    let file = write_yaml(yaml);
+    let (flow, _graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    assert!(validate_kinds(&flow, &HandlerRegistry::with_builtins()).is_ok());
+}
+
+#[test]
+fn test_malformed_yaml_is_a_parse_error() {
+    let file = write_yaml("id: broken\nnodes: [this is: not, a: step list");
+    let err = load_flow(file.path()).expect_err("malformed YAML should be rejected");
+    assert!(matches!(err, FlowError::Parse(_)), "unexpected error: {err:?}");
+}
+
+#[test]
+fn test_missing_file_is_an_io_error() {
+    let err = load_flow(std::path::Path::new("config/does_not_exist.yml"))
+        .expect_err("missing file should be rejected");
+    assert!(matches!(err, FlowError::Io(_)), "unexpected error: {err:?}");
+}
+
+#[test]
+fn test_flow_error_converts_into_anyhow() {
+    let flow = flow_of(&[("a", &["ghost"])]);
+    let err: anyhow::Error = build_step_graph(&flow).unwrap_err().into();
+    assert!(matches!(
+        err.downcast_ref::<FlowError>(),
+        Some(FlowError::UnknownDependency { .. })
+    ));
+}
+
+#[test]
+fn test_config_file_is_merged_under_inline_config() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        dir.path().join("fetch.yml"),
+        "url: https://example.com/catalog\nretries: 3\nheaders:\n  accept: json\n",
+    )
+    .unwrap();
+
+    let flow_path = dir.path().join("flow.yml");
+    std::fs::write(
+        &flow_path,
+        r#"
+id: external-config
+nodes:
+  - id: fetch
+    kind: http_get
+    config_file: fetch.yml
+    config:
+      retries: 5
+      headers:
+        user-agent: tiny
+"#,
+    )
+    .unwrap();
+
+    let (flow, _graph) = load_flow(&flow_path).expect("Failed to load flow");
+    let config = &flow.nodes[0].config;
+
+    assert_eq!(config["url"].as_str(), Some("https://example.com/catalog"));
+    assert_eq!(config["retries"].as_u64(), Some(5), "inline value should win");
+    assert_eq!(config["headers"]["accept"].as_str(), Some("json"));
+    assert_eq!(config["headers"]["user-agent"].as_str(), Some("tiny"));
+}
+
+#[test]
+fn test_missing_config_file_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let flow_path = dir.path().join("flow.yml");
+    std::fs::write(
+        &flow_path,
+        "id: broken\nnodes:\n  - id: a\n    kind: noop\n    config_file: nope.yml\n",
+    )
+    .unwrap();
+
+    let err = load_flow(&flow_path).expect_err("missing config file should fail");
+    assert!(matches!(err, FlowError::ConfigFile { ref step, .. } if step == "a"), "{err:?}");
+}
+
+#[test]
+fn test_normalize_is_idempotent() {
+    let yaml = r#"
+id: shop
+nodes:
+  - id: report
+    kind: noop
+    depends_on: [prices, stock]
+  - id: stock
+    kind: http_get
+    config: { url: "https://example.com/stock", headers: { b: 2, a: 1 } }
+  - id: prices
+    kind: http_get
+    timeout_seconds: 10
+"#;
+
+    let once = normalize_flow(yaml).expect("normalize failed");
+    let twice = normalize_flow(&once).expect("normalize of normalized output failed");
+    assert_eq!(once, twice);
+
+    // Defaults are dropped, dependencies come first
+    assert!(!once.contains("compensate_on_timeout"), "{once}");
+    let position = |id: &str| once.find(&format!("id: {id}")).unwrap();
+    assert!(position("prices") < position("stock"));
+    assert!(position("stock") < position("report"));
+}
+
+#[test]
+fn test_normalize_equivalent_flows_match() {
+    let a = r#"
+id: shop
+nodes:
+  - id: stock
+    kind: http_get
+    config:
+      url: https://example.com/stock
+      retries: 3
+  - id: report
+    kind: noop
+    depends_on: [stock, prices]
+  - id: prices
+    kind: http_get
+"#;
+    let b = r#"
+id: shop
+nodes:
+  - id: prices
+    kind: http_get
+    depends_on: []
+  - id: report
+    kind: noop
+    depends_on: [prices, stock]
+    compensate_on_timeout: false
+  - id: stock
+    kind: http_get
+    config: { retries: 3, url: "https://example.com/stock" }
+"#;
+
+    assert_eq!(normalize_flow(a).unwrap(), normalize_flow(b).unwrap());
+}
+
+const MULTI_FLOW_YAML: &str = r#"
+flows:
+  - id: ingest
+    nodes:
+      - id: fetch
+        kind: noop
+  - id: report
+    nodes:
+      - id: collect
+        kind: noop
+      - id: send
+        kind: noop
+        depends_on: [collect]
+"#;
+
+#[test]
+fn test_single_flow_file_loads_as_one_flow() {
+    let file = write_yaml("id: solo\nnodes:\n  - id: a\n    kind: noop\n");
+
+    let flows = load_flows(file.path()).expect("Failed to load flows");
+    assert_eq!(flows.len(), 1);
+
+    let (flow, graph) = select_flow(flows, None).expect("single flow needs no --flow");
+    assert_eq!(flow.id, "solo");
+    assert_eq!(graph.node_count(), 1);
+}
+
+#[test]
+fn test_multi_flow_file_selects_flow_by_id() {
+    let file = write_yaml(MULTI_FLOW_YAML);
+
+    let flows = load_flows(file.path()).expect("Failed to load flows");
+    let ids: Vec<&str> = flows.iter().map(|(flow, _)| flow.id.as_str()).collect();
+    assert_eq!(ids, vec!["ingest", "report"]);
+
+    let (flow, graph) = select_flow(flows, Some("report")).expect("report should be selectable");
+    assert_eq!(flow.id, "report");
+    assert_eq!(graph.node_count(), 2);
+}
+
+#[test]
+fn test_multi_flow_file_without_selection_is_ambiguous() {
+    let file = write_yaml(MULTI_FLOW_YAML);
+
+    let err = load_flow(file.path()).expect_err("two flows and no --flow");
+    assert!(matches!(err, FlowError::AmbiguousFlow { ref ids } if ids == &["ingest", "report"]));
+
+    let flows = load_flows(file.path()).unwrap();
+    let err = select_flow(flows, Some("missing")).expect_err("no such flow");
+    assert!(matches!(err, FlowError::UnknownFlow { ref id } if id == "missing"));
+}
+
+#[test]
+fn test_multi_flow_file_rejects_duplicate_flow_ids() {
+    let file = write_yaml(
+        "flows:\n  - id: twin\n    nodes: []\n  - id: twin\n    nodes: []\n",
+    );
+
+    let err = load_flows(file.path()).expect_err("duplicate flow IDs");
+    assert!(matches!(err, FlowError::DuplicateFlowId { ref id } if id == "twin"));
+}
+
+#[test]
+fn test_critical_path_picks_longest_branch() {
+    let yaml = r#"
+id: branch-flow
+nodes:
+  - id: start
+    kind: noop
+    estimated_seconds: 5
+  - id: a
+    kind: noop
+    depends_on: [start]
+    estimated_seconds: 10
+  - id: b
+    kind: noop
+    depends_on: [start]
+    estimated_seconds: 30
+  - id: end
+    kind: noop
+    depends_on: [a, b]
+"#;
+
+    let file = write_yaml(yaml);
+    let (flow, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    let (path, seconds) = critical_path(&flow, &graph);
+    assert_eq!(path, vec!["start", "b", "end"]);
+    assert_eq!(seconds, 35);
+}
+
+#[test]
+fn test_unreachable_steps_empty_for_connected_flow() {
+    let yaml = r#"
+id: connected
+nodes:
+  - id: extract_a
+    kind: noop
+  - id: extract_b
+    kind: noop
+  - id: merge
+    kind: noop
+    depends_on: [extract_a, extract_b]
+"#;
+
+    let file = write_yaml(yaml);
+    let (_, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    assert!(unreachable_steps(&graph).is_empty(), "parallel roots are not islands");
+}
+
+#[test]
+fn test_unreachable_steps_finds_orphan() {
+    let yaml = r#"
+id: orphaned
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+  - id: orphan
+    kind: noop
+"#;
+
+    let file = write_yaml(yaml);
+    let (_, graph) = load_flow(file.path()).expect("Failed to load flow");
+
+    assert_eq!(unreachable_steps(&graph), vec!["orphan"]);
+}
+
+#[test]
+#[traced_test]
+fn test_supported_version_loads_quietly() {
+    let yaml = format!("id: versioned\nversion: {FLOW_FORMAT_VERSION}\nnodes:\n  - id: a\n    kind: noop\n");
+    let file = write_yaml(&yaml);
+
+    let (flow, _) = load_flow(file.path()).expect("current version should load");
+    assert_eq!(flow.version, Some(FLOW_FORMAT_VERSION));
+    assert!(!logs_contain("WARN"));
+}
+
+#[test]
+#[traced_test]
+fn test_empty_flow_loads_with_a_warning() {
+    let file = write_yaml("id: empty\nversion: 1\nnodes: []\n");
+
+    let (flow, graph) = load_flow(file.path()).expect("lenient loading accepts an empty flow");
+    assert!(flow.nodes.is_empty());
+    assert_eq!(graph.node_count(), 0);
+    assert!(logs_contain("Flow 'empty' has no steps"));
+}
+
+#[test]
+fn test_empty_flow_is_rejected_by_strict_loading() {
+    let file = write_yaml("id: empty\nversion: 1\nnodes: []\n");
+
+    let err = load_flow_strict(file.path()).expect_err("strict loading rejects an empty flow");
+    assert!(matches!(err, FlowError::EmptyFlow { ref id } if id == "empty"), "{err:?}");
+}
+
+#[test]
+fn test_newer_version_is_rejected() {
+    let yaml = format!("id: future\nversion: {}\nnodes:\n  - id: a\n    kind: noop\n", FLOW_FORMAT_VERSION + 1);
+    let file = write_yaml(&yaml);
+
+    let err = load_flow(file.path()).expect_err("newer version should be rejected");
+    match err {
+        FlowError::UnsupportedVersion { flow, version } => {
+            assert_eq!(flow, "future");
+            assert_eq!(version, FLOW_FORMAT_VERSION + 1);
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
+
+#[test]
+#[traced_test]
+fn test_missing_version_loads_as_version_one_with_a_warning() {
+    let file = write_yaml("id: legacy\nnodes:\n  - id: a\n    kind: noop\n");
+
+    let (flow, _) = load_flow(file.path()).expect("unversioned flow should load");
+    assert_eq!(flow.version, None);
+    assert!(logs_contain("Flow 'legacy' has no `version`; assuming version 1"));
+}
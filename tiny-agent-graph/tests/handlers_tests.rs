@@ -0,0 +1,662 @@
+use std::io::Write;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::NamedTempFile;
+use tiny_agent_graph::engine::{run_flow, run_flow_with_options, FailureKind, RunHistory, RunOptions, RunStatus, StepStatus};
+#[cfg(feature = "publish")]
+use tiny_agent_graph::flow::validate_configs;
+use tiny_agent_graph::flow::load_flow;
+#[cfg(feature = "publish")]
+use tiny_agent_graph::handlers::publish::{InMemoryBroker, PublishHandler};
+use tiny_agent_graph::handlers::{HandlerRegistry, HttpClientConfig, ShellHandler};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Helper: write a flow YAML to a temp file and load it
+fn load(yaml: &str) -> (tiny_agent_graph::flow::Flow, tiny_agent_graph::flow::StepGraph) {
+    let mut tmp = NamedTempFile::new().expect("Failed to create temp file");
+    write!(tmp, "{}", yaml).expect("Failed to write YAML");
+    load_flow(tmp.path()).expect("Failed to load flow")
+}
+
+#[tokio::test]
+async fn test_shell_step_captures_stdout() {
+    let (flow, graph) = load(
+        r#"
+id: shell-ok
+nodes:
+  - id: greet
+    kind: shell
+    config:
+      command: echo
+      args: [hello]
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success));
+
+    let greet = &result.step_results["greet"];
+    assert!(matches!(greet.status, StepStatus::Success));
+    assert_eq!(greet.output.as_deref(), Some("hello"));
+}
+
+#[tokio::test]
+async fn test_shell_step_runs_in_process_cwd_unless_told_otherwise() {
+    let mut tmp = NamedTempFile::new().expect("Failed to create temp file");
+    write!(
+        tmp,
+        "id: shell-cwd\nnodes:\n  - id: here\n    kind: shell\n    config: {{ command: pwd }}\n  - id: there\n    kind: shell\n    config: {{ command: pwd, cwd: . }}\n"
+    )
+    .expect("Failed to write YAML");
+    let (flow, graph) = load_flow(tmp.path()).expect("Failed to load flow");
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    let dir_of = |id: &str| std::fs::canonicalize(result.step_results[id].output.as_deref().unwrap()).unwrap();
+    assert_eq!(dir_of("here"), std::env::current_dir().unwrap().canonicalize().unwrap());
+    // An explicit `cwd` is relative to the flow file
+    assert_eq!(dir_of("there"), tmp.path().parent().unwrap().canonicalize().unwrap());
+}
+
+#[tokio::test]
+async fn test_shell_step_keeps_stderr_out_of_output() {
+    let (flow, graph) = load(
+        r#"
+id: shell-diagnostics
+nodes:
+  - id: chatty
+    kind: shell
+    config:
+      command: sh
+      args: ["-c", "echo result; echo 'progress: 100%' >&2"]
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    let chatty = &result.step_results["chatty"];
+    assert!(matches!(chatty.status, StepStatus::Success));
+    assert_eq!(chatty.output.as_deref(), Some("result"));
+    assert_eq!(chatty.diagnostics.as_deref(), Some("progress: 100%"));
+}
+
+#[tokio::test]
+async fn test_shell_step_fails_on_non_zero_exit() {
+    let (flow, graph) = load(
+        r#"
+id: shell-fail
+nodes:
+  - id: boom
+    kind: shell
+    config:
+      command: sh
+      args: ["-c", "echo broken >&2; exit 1"]
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Failed(_)));
+
+    let boom = &result.step_results["boom"];
+    assert!(boom.output.is_none());
+    match &boom.status {
+        StepStatus::Failed(_, reason) => {
+            assert!(reason.contains("code 1"), "unexpected reason: {reason}");
+            assert!(reason.contains("broken"), "stderr missing from reason: {reason}");
+        }
+        other => panic!("expected failure, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_shell_step_false_fails() {
+    let (flow, graph) = load(
+        r#"
+id: shell-false
+nodes:
+  - id: nope
+    kind: shell
+    config:
+      command: "false"
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.step_results["nope"].status, StepStatus::Failed(..)));
+}
+
+#[tokio::test]
+async fn test_shell_step_respects_timeout() {
+    let (flow, graph) = load(
+        r#"
+id: shell-slow
+nodes:
+  - id: slow
+    kind: shell
+    timeout_seconds: 1
+    config:
+      command: sleep
+      args: [5]
+"#,
+    );
+
+    let started = std::time::Instant::now();
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert!(started.elapsed() < std::time::Duration::from_secs(4));
+    match &result.step_results["slow"].status {
+        StepStatus::Failed(_, reason) => assert!(reason.contains("Timed out"), "{reason}"),
+        other => panic!("expected timeout, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_script_step_combines_upstream_outputs() {
+    let (flow, graph) = load(
+        r#"
+id: script-ok
+nodes:
+  - id: left
+    kind: shell
+    config:
+      command: echo
+      args: [tiny]
+  - id: right
+    kind: shell
+    config:
+      command: echo
+      args: [graph]
+  - id: joined
+    kind: script
+    depends_on: [left, right]
+    config:
+      script: 'steps.left + "-" + steps.right'
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success));
+    assert_eq!(result.step_results["joined"].output.as_deref(), Some("tiny-graph"));
+}
+
+#[tokio::test]
+async fn test_script_step_reads_flow_inputs() {
+    let (flow, graph) = load(
+        r#"
+id: script-inputs
+inputs:
+  greeting: hello
+  count: null
+  tags: [a, b, c]
+nodes:
+  - id: summary
+    kind: script
+    config:
+      script: 'inputs.greeting + " x" + (inputs.count * 2) + " / " + inputs.tags.len()'
+"#,
+    );
+    let options = RunOptions {
+        inputs: [("count".to_string(), serde_yaml::Value::from(21))].into(),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success), "{:?}", result.step_results);
+    assert_eq!(result.step_results["summary"].output.as_deref(), Some("hello x42 / 3"));
+}
+
+#[tokio::test]
+async fn test_script_step_is_terminated_on_timeout() {
+    let (flow, graph) = load(
+        r#"
+id: script-loop
+nodes:
+  - id: spin
+    kind: script
+    timeout_seconds: 1
+    config:
+      script: "loop { }"
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    match &result.step_results["spin"].status {
+        StepStatus::Failed(_, reason) => assert!(reason.to_lowercase().contains("timed out"), "{reason}"),
+        other => panic!("expected timeout, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_sleep_step_waits_then_succeeds() {
+    let (flow, graph) = load(
+        r#"
+id: sleep-ok
+nodes:
+  - id: nap
+    kind: sleep
+    config:
+      seconds: 0.2
+"#,
+    );
+
+    let started = std::time::Instant::now();
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(matches!(result.status, RunStatus::Success));
+    assert_eq!(result.step_results["nap"].output.as_deref(), Some("slept 0.2s"));
+}
+
+#[tokio::test]
+async fn test_sleep_step_longer_than_its_timeout_times_out() {
+    let (flow, graph) = load(
+        r#"
+id: sleep-slow
+nodes:
+  - id: nap
+    kind: sleep
+    timeout_seconds: 1
+    config:
+      seconds: 5
+"#,
+    );
+
+    let started = std::time::Instant::now();
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(4));
+    match &result.step_results["nap"].status {
+        StepStatus::Failed(FailureKind::Timeout, reason) => assert!(reason.contains("Timed out"), "{reason}"),
+        other => panic!("expected timeout, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_assert_step_passes_on_templated_upstream_output() {
+    let (flow, graph) = load(
+        r#"
+id: assert-ok
+nodes:
+  - id: greet
+    kind: shell
+    config:
+      command: echo
+      args: [hello world]
+  - id: check
+    kind: assert
+    depends_on: [greet]
+    config:
+      left: "{{ steps.greet.output }}"
+      op: contains
+      right: world
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success));
+    assert!(matches!(result.step_results["check"].status, StepStatus::Success));
+}
+
+#[tokio::test]
+async fn test_failing_assert_step_says_what_was_expected() {
+    let (flow, graph) = load(
+        r#"
+id: assert-fails
+nodes:
+  - id: count
+    kind: shell
+    config:
+      command: echo
+      args: [3]
+  - id: check
+    kind: assert
+    depends_on: [count]
+    config:
+      left: "{{ steps.count.output }}"
+      op: eq
+      right: 4
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Failed(_)));
+    match &result.step_results["check"].status {
+        StepStatus::Failed(FailureKind::HandlerError, reason) => {
+            assert_eq!(reason, r#"Assertion failed: expected "3" to equal "4""#)
+        }
+        other => panic!("expected a failed assertion, got {other:?}"),
+    }
+}
+
+#[cfg(feature = "publish")]
+#[tokio::test]
+async fn test_publish_step_delivers_templated_payload_to_the_broker() {
+    let (flow, graph) = load(
+        r#"
+id: publish-report
+nodes:
+  - id: report
+    kind: shell
+    config:
+      command: echo
+      args: [42 rows]
+  - id: announce
+    kind: publish
+    depends_on: [report]
+    config:
+      topic: reports
+      payload: "nightly: {{ steps.report.output }}"
+"#,
+    );
+    let broker = Arc::new(InMemoryBroker::new());
+    let mut registry = HandlerRegistry::with_builtins();
+    registry.register("publish", PublishHandler::new(broker.clone()));
+    registry.register_schema("publish", PublishHandler::config_schema()).unwrap();
+    validate_configs(&flow, &registry).unwrap();
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+
+    assert!(matches!(result.status, RunStatus::Success));
+    assert_eq!(broker.messages(), [("reports".to_string(), "nightly: 42 rows".to_string())]);
+    assert_eq!(result.step_results["announce"].output.as_deref(), Some("ack-1"));
+}
+
+#[tokio::test]
+async fn test_subflow_step_runs_child_flow() {
+    let dir = tempfile::tempdir().unwrap();
+    let child = dir.path().join("child.yml");
+    std::fs::write(
+        &child,
+        "id: child\nnodes:\n  - id: inner\n    kind: shell\n    config: { command: echo, args: [nested] }\n",
+    )
+    .unwrap();
+
+    let (flow, graph) = load(&format!(
+        "id: parent\nnodes:\n  - id: run_child\n    kind: subflow\n    config:\n      path: {:?}\n",
+        child.display().to_string()
+    ));
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert_eq!(result.status, RunStatus::Success);
+
+    let output = result.step_results["run_child"].output.as_deref().unwrap();
+    let nested: RunHistory = serde_json::from_str(output).unwrap();
+    assert_eq!(nested.flow_id, "child");
+    assert_eq!(nested.status, RunStatus::Success);
+    assert_eq!(nested.step_results["inner"].output.as_deref(), Some("nested"));
+}
+
+#[tokio::test]
+async fn test_subflow_uses_the_parent_runs_registry() {
+    let (flow, graph) = load(
+        r#"
+id: parent
+nodes:
+  - id: run_child
+    kind: subflow
+    config:
+      flow:
+        id: child
+        nodes:
+          - id: announce
+            kind: say
+            config: { command: echo, args: [from the sub-flow] }
+"#,
+    );
+    // `say` is only known to the parent run's registry
+    let mut registry = HandlerRegistry::with_builtins();
+    registry.register("say", ShellHandler);
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        sim_latency_ms: Some((0, 0)),
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success), "{:?}", result.step_results);
+    let output = result.step_results["run_child"].output.as_deref().unwrap();
+    let nested: RunHistory = serde_json::from_str(output).unwrap();
+    assert_eq!(nested.step_results["announce"].output.as_deref(), Some("from the sub-flow"));
+}
+
+#[tokio::test]
+async fn test_failing_inline_subflow_fails_parent_step() {
+    let (flow, graph) = load(
+        r#"
+id: parent
+nodes:
+  - id: run_child
+    kind: subflow
+    config:
+      flow:
+        id: child
+        nodes:
+          - id: boom
+            kind: fail_test
+"#,
+    );
+
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    match &result.step_results["run_child"].status {
+        StepStatus::Failed(_, reason) => assert!(reason.contains("failed steps: boom"), "{reason}"),
+        other => panic!("expected the sub-flow step to fail, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_subflow_include_cycle_is_an_error() {
+    let dir = tempfile::tempdir().unwrap();
+    let a = dir.path().join("a.yml");
+    let b = dir.path().join("b.yml");
+    let include = |id: &str, path: &std::path::Path| {
+        format!(
+            "id: {id}\nnodes:\n  - id: include\n    kind: subflow\n    config:\n      path: {:?}\n",
+            path.display().to_string()
+        )
+    };
+    std::fs::write(&a, include("a", &b)).unwrap();
+    std::fs::write(&b, include("b", &a)).unwrap();
+
+    let (flow, graph) = load_flow(&a).unwrap();
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    match &result.step_results["include"].status {
+        StepStatus::Failed(_, reason) => assert!(reason.contains("Sub-flow cycle"), "{reason}"),
+        other => panic!("expected a cycle error, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn test_flow_including_itself_fails_before_running_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let flow_file = dir.path().join("loop.yml");
+    std::fs::write(
+        &flow_file,
+        r#"
+id: loop
+nodes:
+  - id: mark
+    kind: shell
+    config:
+      command: sh
+      args: ["-c", "echo ran >> marks.txt"]
+      cwd: .
+  - id: include
+    kind: subflow
+    depends_on: [mark]
+    config:
+      path: loop.yml
+"#,
+    )
+    .unwrap();
+
+    let (flow, graph) = load_flow(&flow_file).unwrap();
+    let result = run_flow(&flow, graph).await.unwrap();
+
+    match &result.step_results["include"].status {
+        StepStatus::Failed(_, reason) => assert!(reason.contains("Sub-flow cycle"), "{reason}"),
+        other => panic!("expected a cycle error, got {other:?}"),
+    }
+    // The included copy never got to run its steps
+    assert_eq!(std::fs::read_to_string(dir.path().join("marks.txt")).unwrap(), "ran\n");
+}
+
+#[tokio::test]
+async fn test_relative_paths_resolve_against_the_flow_file() {
+    // Deliberately not the current directory
+    let dir = tempfile::tempdir().unwrap();
+    let flows = dir.path().join("flows");
+    std::fs::create_dir(&flows).unwrap();
+    std::fs::write(flows.join("greeting.txt"), "hello from a sibling").unwrap();
+    std::fs::write(flows.join("child.yml"), "id: child\nnodes:\n  - id: inner\n    kind: noop\n").unwrap();
+    std::fs::write(
+        flows.join("parent.yml"),
+        r#"
+id: parent
+nodes:
+  - id: read
+    kind: shell
+    config:
+      command: cat
+      args: [greeting.txt]
+      cwd: .
+  - id: include
+    kind: subflow
+    config:
+      path: child.yml
+"#,
+    )
+    .unwrap();
+
+    let (flow, graph) = load_flow(&flows.join("parent.yml")).unwrap();
+    assert_eq!(flow.base_dir.as_deref(), Some(flows.as_path()));
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success), "{:?}", result.step_results);
+    assert_eq!(result.step_results["read"].output.as_deref(), Some("hello from a sibling"));
+    assert_eq!(result.step_results["include"].status, StepStatus::Success);
+}
+
+#[tokio::test]
+async fn test_http_steps_share_one_small_pool_under_concurrency() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/item"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("ok").set_delay(Duration::from_millis(20)))
+        .expect(20)
+        .mount(&server)
+        .await;
+
+    let nodes: String = (0..20)
+        .map(|i| format!("  - id: fetch{i}\n    kind: http_get\n    config:\n      url: {}/item\n", server.uri()))
+        .collect();
+    let (flow, graph) = load(&format!("id: http-fan-out\nnodes:\n{nodes}"));
+
+    let registry = HandlerRegistry::with_builtins()
+        .with_http_client(&HttpClientConfig {
+            pool_max_idle_per_host: 1,
+            timeout_seconds: Some(5),
+        })
+        .unwrap();
+    let options = RunOptions {
+        registry: Arc::new(registry),
+        max_parallel: 10,
+        ..Default::default()
+    };
+
+    let result = run_flow_with_options(&flow, graph, &options).await.unwrap();
+    assert!(matches!(result.status, RunStatus::Success), "{:?}", result.step_results);
+    for step in result.step_results.values() {
+        assert_eq!(step.output.as_deref(), Some("ok"));
+        assert_eq!(step.diagnostics.as_deref(), Some("HTTP 200"));
+    }
+}
+
+#[tokio::test]
+async fn test_http_step_fails_on_error_status_and_slow_responses() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("no such item"))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/slow"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(3)))
+        .mount(&server)
+        .await;
+
+    let (flow, graph) = load(&format!(
+        r#"
+id: http-failures
+nodes:
+  - id: missing
+    kind: http_get
+    config:
+      url: {uri}/missing
+  - id: slow
+    kind: http_get
+    timeout_seconds: 1
+    config:
+      url: {uri}/slow
+"#,
+        uri = server.uri()
+    ));
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    match &result.step_results["missing"].status {
+        StepStatus::Failed(_, reason) => assert!(reason.contains("HTTP 404: no such item"), "{reason}"),
+        other => panic!("expected failure, got {other:?}"),
+    }
+    assert!(
+        matches!(result.step_results["slow"].status, StepStatus::Failed(..)),
+        "{:?}",
+        result.step_results["slow"]
+    );
+}
+
+#[tokio::test]
+async fn test_http_step_retries_server_errors_but_not_client_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/missing"))
+        .respond_with(ResponseTemplate::new(404))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/down"))
+        .respond_with(ResponseTemplate::new(503))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    let (flow, graph) = load(&format!(
+        r#"
+id: http-retries
+nodes:
+  - id: missing
+    kind: http_get
+    retry: {{ max_attempts: 3, backoff_seconds: 0 }}
+    config:
+      url: {uri}/missing
+  - id: down
+    kind: http_get
+    retry: {{ max_attempts: 3, backoff_seconds: 0 }}
+    config:
+      url: {uri}/down
+"#,
+        uri = server.uri()
+    ));
+
+    let result = run_flow(&flow, graph).await.unwrap();
+    assert_eq!(result.step_results["missing"].attempts, 1);
+    assert_eq!(result.step_results["down"].attempts, 3);
+    server.verify().await;
+}
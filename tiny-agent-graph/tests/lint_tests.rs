@@ -0,0 +1,83 @@
+use tiny_agent_graph::flow::{build_step_graph, Flow, Step, StepGraph};
+use tiny_agent_graph::lint::{
+    fail_test_steps, has_errors, lint_flow, long_dependency_chains, unreachable_steps, Severity,
+    MAX_CHAIN_LENGTH,
+};
+
+/// Helper: parse flow YAML and build its graph
+fn flow_from_yaml(yaml: &str) -> (Flow, StepGraph) {
+    let flow: Flow = serde_yaml::from_str(yaml).expect("valid flow YAML");
+    let graph = build_step_graph(&flow).expect("valid DAG");
+    (flow, graph)
+}
+
+#[test]
+fn test_clean_flow_has_no_findings() {
+    let (flow, graph) = flow_from_yaml(
+        r#"
+id: clean
+nodes:
+  - id: fetch
+    kind: http_get
+    retry: { max_attempts: 3 }
+  - id: store
+    kind: db_upsert
+    depends_on: [fetch]
+"#,
+    );
+
+    assert_eq!(lint_flow(&flow, &graph), vec![]);
+}
+
+#[test]
+fn test_orphan_step_is_reported_as_unreachable() {
+    let (flow, graph) = flow_from_yaml(
+        r#"
+id: orphaned
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+  - id: orphan
+    kind: noop
+"#,
+    );
+
+    let findings = unreachable_steps(&flow, &graph);
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].step.as_deref(), Some("orphan"));
+    assert_eq!(findings[0].severity, Severity::Warning);
+    assert!(!has_errors(&findings), "warnings must not fail the lint");
+}
+
+#[test]
+fn test_fail_test_step_is_an_error() {
+    let (flow, graph) = flow_from_yaml("id: f\nnodes:\n  - id: boom\n    kind: fail_test\n");
+
+    let findings = fail_test_steps(&flow, &graph);
+    assert_eq!(findings[0].step.as_deref(), Some("boom"));
+    assert!(has_errors(&findings));
+}
+
+#[test]
+fn test_long_chain_is_reported_once_at_its_end() {
+    let nodes: Vec<Step> = (0..=MAX_CHAIN_LENGTH)
+        .map(|i| Step {
+            id: format!("s{i}"),
+            depends_on: if i == 0 { vec![] } else { vec![format!("s{}", i - 1).into()] },
+            ..Default::default()
+        })
+        .collect();
+    let flow = Flow {
+        id: "long".into(),
+        nodes,
+        ..Default::default()
+    };
+    let graph = build_step_graph(&flow).unwrap();
+
+    let findings = long_dependency_chains(&flow, &graph);
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].step, Some(format!("s{MAX_CHAIN_LENGTH}")));
+}
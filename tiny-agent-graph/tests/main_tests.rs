@@ -1,7 +1,12 @@
 use assert_cmd::Command;
+use predicates::prelude::*;
 use predicates::str::contains;
 use tempfile::NamedTempFile;
 use std::io::Write;
+use tiny_agent_graph::engine::{FailureKind, RunHistory, RunStatus, StepStatus};
+use tiny_agent_graph::persistence::SqliteStore;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 /// Helper: write a temporary flow YAML file
 fn write_flow(contents: &str) -> NamedTempFile {
@@ -31,6 +36,265 @@ nodes:
         .stdout(contains("🎯 Final status: Success"));
 }
 
+#[tokio::test]
+async fn test_main_no_color_output_has_no_ansi_escapes() {
+    let yaml = r#"
+id: mixed-flow
+nodes:
+  - id: ok
+    kind: noop
+  - id: broken
+    kind: fail_test
+  - id: after
+    kind: noop
+    depends_on: [broken]
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("--no-color")
+        .arg("run-flow")
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(contains("✅ ok →"))
+        .stdout(contains("❌ broken → Failed"))
+        .stdout(contains("\u{1b}[").not())
+        .stderr(contains("\u{1b}[").not());
+}
+
+#[tokio::test]
+async fn test_main_quiet_prints_only_the_final_status() {
+    let file = write_flow("id: quiet-flow\nnodes:\n  - id: a\n    kind: noop\n  - id: b\n    kind: fail_test\n");
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("-q")
+        .arg("--fail-on")
+        .arg("any")
+        .assert()
+        .failure()
+        .stdout(contains("🎯 Final status: Failed"))
+        .stdout(contains("→").not())
+        .stdout(contains("Loaded flow").not());
+}
+
+#[tokio::test]
+async fn test_main_verbose_prints_step_dependencies() {
+    let yaml = r#"
+id: verbose-flow
+inputs:
+  table: products
+nodes:
+  - id: login
+    kind: noop
+  - id: fetch
+    kind: noop
+  - id: store
+    kind: noop
+    depends_on: [login, { step: fetch, on: completion }]
+    retry:
+      max_attempts: 3
+      backoff_seconds: 0
+    config:
+      table: "{{ inputs.table }}"
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(contains("🔍 store (noop)"))
+        .stdout(contains("depends on:   login, fetch (on completion)"))
+        .stdout(contains("retry:        3 attempts, 0s apart"))
+        .stdout(contains("🔍 store: success after 1 attempt\n"))
+        .stdout(contains("table: products").not());
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("-vv")
+        .assert()
+        .success()
+        .stdout(contains("     table: products"));
+}
+
+#[tokio::test]
+async fn test_main_env_file_fills_env_placeholders() {
+    let yaml = r#"
+id: env-flow
+nodes:
+  - id: deploy
+    kind: noop
+    config:
+      region: "{{ env.TAG_TEST_REGION }}"
+      tier: "{{ env.TAG_TEST_TIER }}"
+"#;
+    let file = write_flow(yaml);
+    let env_file = write_flow("# deploy settings\nTAG_TEST_REGION=eu-west-1\nTAG_TEST_TIER=\"gold\"\n");
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--env-file")
+        .arg(env_file.path())
+        .arg("-vv")
+        .env("TAG_TEST_TIER", "silver")
+        .assert()
+        .success()
+        .stdout(contains("     region: eu-west-1"))
+        .stdout(contains("     tier: silver"));
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--env-file")
+        .arg(env_file.path())
+        .arg("--env-file-override")
+        .arg("-vv")
+        .env("TAG_TEST_TIER", "silver")
+        .assert()
+        .success()
+        .stdout(contains("     tier: gold"));
+}
+
+#[tokio::test]
+async fn test_main_shows_step_descriptions() {
+    let yaml = r#"
+id: described-flow
+nodes:
+  - id: upsert
+    kind: db_upsert
+    description: Store the cleaned rows
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("-v")
+        .assert()
+        .success()
+        .stdout(contains("🔍 upsert (db_upsert)\n   description:  Store the cleaned rows\n"));
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(contains("upsert → unknown (level 0) — Store the cleaned rows"));
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(contains(r#""description": "Store the cleaned rows""#));
+}
+
+#[tokio::test]
+async fn test_main_by_stage_prints_tallies() {
+    let yaml = r#"
+id: staged-flow
+nodes:
+  - id: compile
+    kind: noop
+    stage: build
+  - id: test
+    kind: fail_test
+    stage: build
+  - id: upload
+    kind: noop
+    stage: deploy
+    depends_on: [compile, test]
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--by-stage")
+        .assert()
+        .success()
+        .stdout(contains("📦 build\n✅ compile"))
+        .stdout(contains("   build: 1 succeeded, 1 failed, 0 skipped\n   deploy: 0 succeeded, 0 failed, 1 skipped\n"));
+}
+
+#[tokio::test]
+async fn test_main_diff_lists_structural_changes() {
+    let old = write_flow(
+        r#"
+id: etl
+nodes:
+  - id: extract
+    kind: http_get
+  - id: transform
+    kind: noop
+    depends_on: [extract]
+  - id: load
+    kind: noop
+    depends_on: [extract]
+"#,
+    );
+    let new = write_flow(
+        r#"
+id: etl
+nodes:
+  - id: extract
+    kind: http_get
+  - id: transform
+    kind: noop
+    depends_on: [extract]
+  - id: load
+    kind: noop
+    depends_on: [transform]
+  - id: notify
+    kind: noop
+    depends_on: [load]
+"#,
+    );
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("diff")
+        .arg(old.path())
+        .arg(new.path())
+        .assert()
+        .success()
+        .stdout("+ step 'notify'\n- edge extract->load\n+ edge load->notify\n+ edge transform->load\n");
+}
+
+#[tokio::test]
+async fn test_main_dry_run_reports_unknown_for_simulated_steps() {
+    let file = write_flow("id: dry-flow\nnodes:\n  - id: upsert\n    kind: db_upsert\n");
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--dry-run")
+        .arg("--json")
+        .assert()
+        .success()
+        .stdout(contains(r#""change": "unknown""#));
+}
+
 #[tokio::test]
 async fn test_main_handles_cycle_error() {
     let yaml = r#"
@@ -54,6 +318,54 @@ nodes:
         .stderr(contains("❌ Failed to load flow"));
 }
 
+#[tokio::test]
+async fn test_main_max_steps_rejects_larger_flows() {
+    let file = write_flow("id: big-flow\nnodes:\n  - id: a\n    kind: noop\n  - id: b\n    kind: noop\n  - id: c\n    kind: noop\n");
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--max-steps")
+        .arg("2")
+        .assert()
+        .failure()
+        .stderr(contains("Flow 'big-flow' has 3 steps, more than the limit of 2"))
+        .stdout(contains("Loaded flow").not());
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--max-steps")
+        .arg("3")
+        .assert()
+        .success();
+}
+
+#[tokio::test]
+async fn test_main_strict_rejects_empty_flows() {
+    let file = write_flow("id: empty-flow\nnodes: []\n");
+
+    for command in ["validate", "run-flow"] {
+        Command::cargo_bin("tiny-agent-graph")
+            .unwrap()
+            .arg(command)
+            .arg(file.path())
+            .arg("--strict")
+            .assert()
+            .failure()
+            .stderr(contains("Flow 'empty-flow' has no steps"));
+
+        Command::cargo_bin("tiny-agent-graph")
+            .unwrap()
+            .arg(command)
+            .arg(file.path())
+            .assert()
+            .success();
+    }
+}
+
 #[tokio::test]
 async fn test_main_handles_missing_file() {
     Command::cargo_bin("tiny-agent-graph")
@@ -64,3 +376,652 @@ async fn test_main_handles_missing_file() {
         .failure()
         .stderr(contains("❌ Failed to load flow"));
 }
+
+/// Helper: run a flow with one passing and one failing step under `--fail-on`
+fn run_with_fail_on(policy: &str) -> assert_cmd::assert::Assert {
+    let yaml = r#"
+id: partial-flow
+nodes:
+  - id: ok
+    kind: noop
+  - id: broken
+    kind: fail_test
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--fail-on")
+        .arg(policy)
+        .assert()
+}
+
+#[tokio::test]
+async fn test_main_fail_on_none_exits_zero_after_step_failure() {
+    run_with_fail_on("none").success().stdout(contains("🎯 Final status: Failed"));
+}
+
+#[tokio::test]
+async fn test_main_fail_on_partial_exits_zero_when_some_steps_succeed() {
+    run_with_fail_on("partial").success().stdout(contains("🎯 Final status: Failed"));
+}
+
+#[tokio::test]
+async fn test_main_fail_on_any_exits_non_zero_after_step_failure() {
+    run_with_fail_on("any")
+        .failure()
+        .stdout(contains("🎯 Final status: Failed"))
+        .stderr(contains("failed under the --fail-on policy"));
+}
+
+#[tokio::test]
+async fn test_main_fail_on_partial_exits_non_zero_when_everything_fails() {
+    let yaml = r#"
+id: failing-flow
+nodes:
+  - id: broken
+    kind: fail_test
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--fail-on")
+        .arg("partial")
+        .assert()
+        .failure();
+}
+
+#[tokio::test]
+async fn test_main_prints_step_results_in_topological_order() {
+    // Declared (and alphabetically) in the opposite order of the dependencies
+    let yaml = r#"
+id: ordered-flow
+nodes:
+  - id: alpha
+    kind: noop
+    depends_on: [mid]
+  - id: mid
+    kind: noop
+    depends_on: [zeta, yank]
+  - id: yank
+    kind: noop
+  - id: zeta
+    kind: noop
+"#;
+    let file = write_flow(yaml);
+
+    let step_lines = || {
+        let output = Command::cargo_bin("tiny-agent-graph")
+            .unwrap()
+            .arg("run-flow")
+            .arg(file.path())
+            .output()
+            .unwrap();
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("✅ ") && line.contains(" → "))
+            .map(|line| line.split(' ').nth(1).unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let first = step_lines();
+    assert_eq!(first, ["yank", "zeta", "mid", "alpha"]);
+    assert_eq!(step_lines(), first);
+}
+
+#[tokio::test]
+async fn test_main_runs_flow_from_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path("/flow.yml"))
+        .respond_with(ResponseTemplate::new(200).set_body_string(
+            r#"
+id: remote-flow
+nodes:
+  - id: a
+    kind: noop
+"#,
+        ))
+        .mount(&server)
+        .await;
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(format!("{}/flow.yml", server.uri()))
+        .assert()
+        .success()
+        .stdout(contains("✅ Loaded flow 'remote-flow'"))
+        .stdout(contains("✅ a →"))
+        .stdout(contains("🎯 Final status: Success"));
+}
+
+#[tokio::test]
+async fn test_main_fails_on_flow_url_error_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(404))
+        .mount(&server)
+        .await;
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(format!("{}/missing.yml", server.uri()))
+        .assert()
+        .failure()
+        .stderr(contains("❌ Failed to load flow: Could not fetch flow from"))
+        .stderr(contains("404"));
+}
+
+#[tokio::test]
+async fn test_main_rejects_oversized_flow_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .respond_with(ResponseTemplate::new(200).set_body_string("#".repeat(2 * 1024 * 1024)))
+        .mount(&server)
+        .await;
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(format!("{}/huge.yml", server.uri()))
+        .assert()
+        .failure()
+        .stderr(contains("flow is larger than"));
+}
+
+#[tokio::test]
+async fn test_main_interactive_runs_each_step() {
+    let yaml = r#"
+id: interactive-flow
+inputs:
+  name: world
+defaults:
+  noop:
+    retries: 2
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+    config:
+      greeting: "hello {{ inputs.name }}"
+  - id: c
+    kind: noop
+    depends_on: [b]
+"#;
+    let file = write_flow(yaml);
+
+    // The config shown is the one the step runs with: defaults merged in,
+    // placeholders rendered
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--interactive")
+        .write_stdin("r\nr\nr\n")
+        .assert()
+        .success()
+        .stdout(contains("greeting: hello world"))
+        .stdout(contains("retries: 2"))
+        .stdout(contains("⏸️  Next step 'c'"))
+        .stdout(contains("✅ c →"))
+        .stdout(contains("🎯 Final status: Success"));
+}
+
+#[tokio::test]
+async fn test_main_interactive_abort_stops_early() {
+    let yaml = r#"
+id: interactive-flow
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+  - id: c
+    kind: noop
+    depends_on: [b]
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--interactive")
+        .write_stdin("a\n")
+        .assert()
+        .success()
+        .stdout(contains("⏭️ a → Skipped: Run aborted"))
+        .stdout(contains("⏭️ c → Skipped: Run aborted"))
+        .stdout(contains("🎯 Final status: Failed"))
+        .stdout(contains("⏸️  Next step 'b'").not());
+}
+
+/// Helper: a minimal stored run with no steps
+fn stored_run(run_id: &str, flow_id: &str) -> RunHistory {
+    let now = chrono::Utc::now();
+    let mut run = RunHistory {
+        run_id: run_id.into(),
+        flow_id: flow_id.into(),
+        status: RunStatus::Success,
+        step_results: Default::default(),
+        started_at: now,
+        finished_at: now,
+        digest: String::new(),
+        rollback: Vec::new(),
+        note: None,
+        dry_run: false,
+    };
+    run.digest = run.compute_digest();
+    run
+}
+
+#[tokio::test]
+async fn test_main_history_lists_stored_runs() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("runs.db");
+
+    let store = SqliteStore::open(&db).await.unwrap();
+    store.save_run(&stored_run("run-one", "flow-a")).await.unwrap();
+    store.save_run(&stored_run("run-two", "flow-b")).await.unwrap();
+    drop(store);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("history")
+        .arg("--db")
+        .arg(&db)
+        .assert()
+        .success()
+        .stdout(contains("RUN ID"))
+        .stdout(contains("run-one"))
+        .stdout(contains("run-two"));
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("history")
+        .arg("--db")
+        .arg(&db)
+        .arg("--flow-id")
+        .arg("flow-b")
+        .assert()
+        .success()
+        .stdout(contains("run-two"))
+        .stdout(contains("run-one").not());
+}
+
+#[tokio::test]
+async fn test_main_applies_default_step_timeout() {
+    let yaml = r#"
+id: sleepy-flow
+nodes:
+  - id: nap
+    kind: shell
+    config:
+      command: sleep
+      args: [5]
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--step-timeout-default")
+        .arg("1")
+        .timeout(std::time::Duration::from_secs(4))
+        .assert()
+        .success()
+        .stdout(contains("❌ nap → Failed (timeout): Timed out after 1s"))
+        .stdout(contains("🎯 Final status: Failed"));
+}
+
+#[tokio::test]
+async fn test_main_json_summary() {
+    let yaml = r#"
+id: json-flow
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: fail_test
+    depends_on: [a]
+"#;
+    let file = write_flow(yaml);
+
+    let output = Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--json")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let summary: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("stdout should be a single JSON object");
+
+    for key in ["run_id", "flow_id", "status", "steps"] {
+        assert!(summary.get(key).is_some(), "missing key '{key}' in {summary}");
+    }
+    assert_eq!(summary["flow_id"], "json-flow");
+    assert_eq!(summary["status"]["state"], "failed");
+
+    let steps = summary["steps"].as_array().unwrap();
+    assert_eq!(steps.len(), 2);
+    assert_eq!(steps[0]["id"], "a");
+    assert_eq!(steps[0]["status"]["state"], "success");
+    assert_eq!(steps[0]["attempts"], 1);
+    assert_eq!(steps[1]["id"], "b");
+    assert_eq!(steps[1]["status"]["reason"], "Simulated failure");
+}
+
+#[tokio::test]
+async fn test_main_rejects_undeclared_input() {
+    let yaml = r#"
+id: input-flow
+inputs:
+  customer_id: null
+nodes:
+  - id: a
+    kind: noop
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .args(["--input", "customer_id=c-42", "--input", "regoin=eu"])
+        .assert()
+        .failure()
+        .stderr(contains("regoin"));
+}
+
+#[tokio::test]
+async fn test_main_json_log_format() {
+    let yaml = r#"
+id: test-flow
+nodes:
+  - id: a
+    kind: noop
+"#;
+    let file = write_flow(yaml);
+
+    let output = Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .args(["--log-format", "json", "--log-level", "info", "run-flow"])
+        .arg(file.path())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert!(!lines.is_empty(), "expected some log lines");
+    for line in lines {
+        let entry: serde_json::Value = serde_json::from_str(line).expect("log line is JSON");
+        assert!(entry.is_object(), "{line}");
+        assert_ne!(entry["level"], "DEBUG", "--log-level info should drop debug logs");
+    }
+}
+
+#[tokio::test]
+async fn test_main_plan_shows_levels_and_parallelism() {
+    let yaml = r#"
+id: branching
+nodes:
+  - id: login
+    kind: noop
+    estimated_seconds: 2
+  - id: fetch_a
+    kind: noop
+    depends_on: [login]
+    estimated_seconds: 5
+  - id: fetch_b
+    kind: noop
+    depends_on: [login]
+    estimated_seconds: 3
+  - id: fetch_c
+    kind: noop
+    depends_on: [login]
+    estimated_seconds: 1
+  - id: merge
+    kind: fail_test
+    depends_on: [fetch_a, fetch_b, fetch_c]
+    estimated_seconds: 4
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("plan")
+        .arg(file.path())
+        .assert()
+        .success()
+        .stdout(contains("level 1 (3): fetch_a, fetch_b, fetch_c"))
+        .stdout(contains("Levels: 3, max parallelism: 3"))
+        .stdout(contains("Critical path (11s): login → fetch_a → merge"))
+        .stdout(contains("Estimated duration: 11s fully parallel, 15s serial"))
+        .stdout(contains("Final status").not())
+        .stdout(contains("🌳 Tree").not());
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("plan")
+        .arg(file.path())
+        .arg("--tree")
+        .assert()
+        .success()
+        .stdout(contains("🌳 Tree:\n   login\n   ├── fetch_a\n   │   └── merge\n"))
+        .stdout(contains("   └── fetch_c\n       └── merge (*)\n"));
+}
+
+#[tokio::test]
+async fn test_main_tree_label_modes() {
+    let yaml = r#"
+id: labeled
+nodes:
+  - id: fetch
+    kind: http_get
+    description: Pull the catalog
+    config: { url: "https://example.com/catalog" }
+  - id: store
+    kind: db_upsert
+    depends_on: [fetch]
+"#;
+    let file = write_flow(yaml);
+    let tree = |label: &str| {
+        Command::cargo_bin("tiny-agent-graph")
+            .unwrap()
+            .args(["validate", "--tree", "--label", label])
+            .arg(file.path())
+            .assert()
+            .success()
+    };
+
+    tree("id").stdout(contains("🌳 Tree:\n   fetch\n   └── store\n"));
+    tree("id_kind").stdout(contains("🌳 Tree:\n   fetch (http_get)\n   └── store (db_upsert)\n"));
+    tree("description").stdout(contains("🌳 Tree:\n   Pull the catalog\n   └── store\n"));
+}
+
+#[tokio::test]
+async fn test_main_lint_fails_only_on_errors() {
+    let warnings_only = write_flow("id: lint-flow\nnodes:\n  - id: fetch\n    kind: http_get\n");
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("lint")
+        .arg(warnings_only.path())
+        .assert()
+        .success()
+        .stdout(contains("warning[network-without-retry] step 'fetch'"));
+
+    let with_error = write_flow("id: lint-flow\nnodes:\n  - id: boom\n    kind: fail_test\n");
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("lint")
+        .arg(with_error.path())
+        .assert()
+        .failure()
+        .stdout(contains("error[fail-test-step] step 'boom'"));
+}
+
+#[tokio::test]
+async fn test_main_resume_from_saved_history() {
+    let yaml = r#"
+id: resume-flow
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+"#;
+    let file = write_flow(yaml);
+    let dir = tempfile::tempdir().unwrap();
+    let saved = dir.path().join("run.json");
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--output")
+        .arg(&saved)
+        .assert()
+        .success();
+
+    // Pretend `b` failed last time, so only it has to run again
+    let mut history: RunHistory = serde_json::from_str(&std::fs::read_to_string(&saved).unwrap()).unwrap();
+    history.step_results.get_mut("b").unwrap().status = StepStatus::Failed(FailureKind::HandlerError, "boom".into());
+    std::fs::write(&saved, serde_json::to_string(&history).unwrap()).unwrap();
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("resume")
+        .arg(file.path())
+        .arg(&saved)
+        .assert()
+        .success()
+        .stdout(contains("🎯 Final status: Success"))
+        .stderr(contains("Step 'a' already succeeded"));
+}
+
+#[tokio::test]
+async fn test_main_output_writes_yaml_history_by_extension() {
+    let yaml = r#"
+id: yaml-output
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+"#;
+    let file = write_flow(yaml);
+    let dir = tempfile::tempdir().unwrap();
+    let saved = dir.path().join("run.yaml");
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--output")
+        .arg(&saved)
+        .assert()
+        .success();
+
+    let written = std::fs::read_to_string(&saved).unwrap();
+    assert!(written.starts_with("run_id:"), "expected YAML, got:\n{written}");
+
+    let history: RunHistory = serde_yaml::from_str(&written).unwrap();
+    assert_eq!(history.flow_id, "yaml-output");
+    assert_eq!(history.status, RunStatus::Success);
+    assert_eq!(history.step_results["b"].status, StepStatus::Success);
+    assert_eq!(history.step_results["b"].level, 1);
+    assert!(history.verify_digest());
+    // Nothing lost on the way back (compared as values: step order may differ)
+    let reparsed: serde_yaml::Value = serde_yaml::from_str(&written).unwrap();
+    assert_eq!(serde_yaml::to_value(&history).unwrap(), reparsed);
+}
+
+#[tokio::test]
+async fn test_main_explain_prints_step_reasons() {
+    let yaml = r#"
+id: explained
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+    enabled: false
+"#;
+    let file = write_flow(yaml);
+
+    Command::cargo_bin("tiny-agent-graph")
+        .unwrap()
+        .arg("run-flow")
+        .arg(file.path())
+        .arg("--explain")
+        .assert()
+        .success()
+        .stdout(contains("a → ran (all deps ok)"))
+        .stdout(contains("b → skipped (disabled)"));
+}
+
+#[test]
+fn test_main_watch_reruns_after_file_change() {
+    use std::io::{BufRead, BufReader};
+    use std::process::Stdio;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("flow.yml");
+    std::fs::write(&config, "id: watched\nnodes:\n  - id: first\n    kind: noop\n").unwrap();
+
+    let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("tiny-agent-graph"))
+        .args(["--log-level", "off", "run-flow", "--watch"])
+        .arg(&config)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let stdout = child.stdout.take().unwrap();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            let _ = tx.send(line);
+        }
+    });
+    // Collects output until a line contains `needle`, or gives up after a while
+    let wait_for = |needle: &str| -> Vec<String> {
+        let mut seen = Vec::new();
+        while let Ok(line) = rx.recv_timeout(Duration::from_secs(10)) {
+            let found = line.contains(needle);
+            seen.push(line);
+            if found {
+                return seen;
+            }
+        }
+        panic!("never saw {needle:?}; output so far: {seen:#?}");
+    };
+
+    wait_for("👀 Watching");
+    std::fs::write(&config, "id: watched\nnodes:\n  - id: second\n    kind: noop\n").unwrap();
+    let rerun = wait_for("✅ second →");
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(rerun.iter().any(|line| line.contains("changed, running again")), "{rerun:#?}");
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "metrics")]
+
+use tiny_agent_graph::engine::{run_flow, RunStatus};
+use tiny_agent_graph::flow::{build_step_graph, Flow};
+use tiny_agent_graph::metrics::{prometheus_handle, render_prometheus};
+
+/// Helper: value of one sample line (e.g. `steps_succeeded_total{kind="noop"}`), 0 if absent
+fn sample(rendered: &str, series: &str) -> f64 {
+    rendered
+        .lines()
+        .find_map(|line| line.strip_prefix(series)?.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+#[tokio::test]
+async fn test_successful_steps_are_counted_by_kind() {
+    prometheus_handle();
+    let before = render_prometheus();
+
+    let flow: Flow = serde_yaml::from_str(
+        r#"
+id: metered
+nodes:
+  - id: a
+    kind: noop
+  - id: b
+    kind: noop
+    depends_on: [a]
+  - id: c
+    kind: noop
+    depends_on: [b]
+"#,
+    )
+    .unwrap();
+    let graph = build_step_graph(&flow).unwrap();
+    let history = run_flow(&flow, graph).await.unwrap();
+    assert_eq!(history.status, RunStatus::Success);
+
+    let after = render_prometheus();
+    let series = r#"steps_succeeded_total{kind="noop"}"#;
+    assert_eq!(sample(&after, series) - sample(&before, series), 3.0, "{after}");
+    assert!(after.contains("step_duration_seconds"), "{after}");
+    assert_eq!(sample(&after, r#"runs_total{flow_id="metered",status="success"}"#), 1.0, "{after}");
+}
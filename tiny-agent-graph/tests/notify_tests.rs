@@ -0,0 +1,73 @@
+use tiny_agent_graph::engine::{run_flow, RunStatus};
+use tiny_agent_graph::flow::{build_step_graph, Flow};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// Helper: a flow with one passing and one failing step, notifying `webhook_url`
+fn failing_flow(webhook_url: &str, on: &str) -> Flow {
+    serde_yaml::from_str(&format!(
+        r#"
+id: notify-flow
+notify:
+  webhook_url: "{webhook_url}"
+  on: {on}
+nodes:
+  - id: ok
+    kind: noop
+  - id: broken
+    kind: fail_test
+"#
+    ))
+    .expect("valid flow YAML")
+}
+
+#[tokio::test]
+async fn test_webhook_receives_run_summary() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/hook"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let flow = failing_flow(&format!("{}/hook", server.uri()), "on_failure");
+    let graph = build_step_graph(&flow).unwrap();
+    let history = run_flow(&flow, graph).await.unwrap();
+
+    let requests = server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["run_id"], history.run_id.as_str());
+    assert_eq!(body["status"]["state"], "failed");
+    assert_eq!(body["failed_steps"], serde_json::json!(["broken"]));
+}
+
+#[tokio::test]
+async fn test_webhook_skipped_when_status_does_not_match() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let flow = failing_flow(&server.uri(), "on_success");
+    let graph = build_step_graph(&flow).unwrap();
+    run_flow(&flow, graph).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_webhook_errors_do_not_change_run_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let mut flow = failing_flow(&server.uri(), "always");
+    flow.nodes.retain(|step| step.id == "ok");
+    let graph = build_step_graph(&flow).unwrap();
+
+    let history = run_flow(&flow, graph).await.unwrap();
+    assert_eq!(history.status, RunStatus::Success);
+}
@@ -0,0 +1,122 @@
+use chrono::Utc;
+use std::collections::HashMap;
+use tempfile::tempdir;
+use tiny_agent_graph::engine::{Compensated, FailureKind, RunHistory, RunStatus, StepResult, StepStatus};
+use tiny_agent_graph::handlers::Change;
+use tiny_agent_graph::persistence::SqliteStore;
+
+/// Helper: a finished run with one step of each status
+fn sample_run(run_id: &str, flow_id: &str) -> RunHistory {
+    let mut step_results = HashMap::new();
+    step_results.insert(
+        "a".to_string(),
+        StepResult {
+            status: StepStatus::Success,
+            output: Some("Simulated output of 'a'".into()),
+            diagnostics: Some("warning: cache miss".into()),
+            reason: Some("ran (all deps ok)".into()),
+            level: 0,
+            attempts: 1,
+            change: Some(Change::Changed),
+            from_cache: true,
+            output_bytes: Some(70_000),
+        },
+    );
+    step_results.insert(
+        "b".to_string(),
+        StepResult {
+            status: StepStatus::Failed(FailureKind::Timeout, "Simulated failure".into()),
+            output: None,
+            diagnostics: None,
+            reason: None,
+            level: 1,
+            attempts: 3,
+            change: None,
+            from_cache: false,
+            output_bytes: None,
+        },
+    );
+    step_results.insert(
+        "c".to_string(),
+        StepResult {
+            status: StepStatus::Skipped("Dependency 'b' was skipped".into()),
+            output: None,
+            diagnostics: None,
+            reason: None,
+            level: 2,
+            attempts: 0,
+            change: None,
+            from_cache: false,
+            output_bytes: None,
+        },
+    );
+
+    let started_at = Utc::now();
+    let mut run = RunHistory {
+        run_id: run_id.into(),
+        flow_id: flow_id.into(),
+        status: RunStatus::Failed("At least one step failed".into()),
+        step_results,
+        started_at,
+        finished_at: started_at + chrono::Duration::milliseconds(250),
+        digest: String::new(),
+        rollback: vec![Compensated {
+            step_id: "a".into(),
+            kind: "undo_a".into(),
+            status: StepStatus::Success,
+        }],
+        note: None,
+        dry_run: false,
+    };
+    run.digest = run.compute_digest();
+    run
+}
+
+#[tokio::test]
+async fn test_save_and_get_run_round_trips() {
+    let dir = tempdir().unwrap();
+    let store = SqliteStore::open(&dir.path().join("runs.db")).await.unwrap();
+
+    let run = sample_run("run-1", "flow-a");
+    store.save_run(&run).await.unwrap();
+
+    let loaded = store.get_run("run-1").await.unwrap().expect("run not found");
+    assert_eq!(loaded, run);
+    assert!(loaded.verify_digest());
+}
+
+#[tokio::test]
+async fn test_dry_run_flag_round_trips() {
+    let store = SqliteStore::in_memory().await.unwrap();
+    let run = RunHistory {
+        dry_run: true,
+        ..sample_run("run-dry", "flow-a")
+    };
+    store.save_run(&run).await.unwrap();
+
+    let loaded = store.get_run("run-dry").await.unwrap().expect("run not found");
+    assert!(loaded.dry_run);
+}
+
+#[tokio::test]
+async fn test_get_unknown_run_returns_none() {
+    let store = SqliteStore::in_memory().await.unwrap();
+    assert!(store.get_run("missing").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_list_runs_filters_by_flow() {
+    let store = SqliteStore::in_memory().await.unwrap();
+    store.save_run(&sample_run("run-1", "flow-a")).await.unwrap();
+    store.save_run(&sample_run("run-2", "flow-b")).await.unwrap();
+
+    let all = store.list_runs(None, None).await.unwrap();
+    assert_eq!(all.len(), 2);
+
+    let limited = store.list_runs(None, Some(1)).await.unwrap();
+    assert_eq!(limited.len(), 1);
+
+    let only_a = store.list_runs(Some("flow-a"), None).await.unwrap();
+    assert_eq!(only_a.len(), 1);
+    assert_eq!(only_a[0].run_id, "run-1");
+}
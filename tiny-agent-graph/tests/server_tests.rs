@@ -0,0 +1,199 @@
+use tiny_agent_graph::engine::{RunHistory, RunStatus, StepStatus};
+use tiny_agent_graph::persistence::SqliteStore;
+use tiny_agent_graph::server::{router, ServerState};
+use tokio::net::TcpListener;
+
+/// Helper: serve the API on a random local port, returning its base URL
+async fn start_server(state: ServerState) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router(state)).await.unwrap() });
+    format!("http://{addr}")
+}
+
+#[tokio::test]
+async fn test_post_flow_runs_it_and_returns_history() {
+    let base = start_server(ServerState::default()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{base}/flows/run"))
+        .body("id: posted\nnodes:\n  - id: a\n    kind: noop\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 200);
+    let history: RunHistory = response.json().await.unwrap();
+    assert_eq!(history.flow_id, "posted");
+    assert_eq!(history.status, RunStatus::Success);
+}
+
+#[tokio::test]
+async fn test_post_flow_events_streams_them_as_the_run_goes() {
+    let base = start_server(ServerState::default()).await;
+
+    let body = reqwest::Client::new()
+        .post(format!("{base}/flows/run/events"))
+        .body("id: streamed\nnodes:\n  - id: a\n    kind: noop\n")
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+
+    let names: Vec<&str> = body.lines().filter_map(|line| line.strip_prefix("event: ")).collect();
+    assert_eq!(names, ["run_started", "step_started", "step_finished", "run_finished"]);
+    assert!(body.contains(r#""step_id":"a""#), "{body}");
+}
+
+#[tokio::test]
+async fn test_invalid_flow_is_a_bad_request() {
+    let base = start_server(ServerState::default()).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{base}/flows/run"))
+        .body("id: broken\nnodes:\n  - id: a\n    kind: noop\n    depends_on: [missing]\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), 400);
+    assert!(response.text().await.unwrap().contains("missing"));
+}
+
+#[tokio::test]
+async fn test_local_handlers_are_refused_unless_allowed() {
+    let flow = "id: sneaky\nnodes:\n  - id: a\n    kind: shell\n    config: { command: echo, args: [pwned] }\n";
+    let client = reqwest::Client::new();
+
+    let base = start_server(ServerState::default()).await;
+    for route in ["flows/run", "flows/run/events"] {
+        let response = client.post(format!("{base}/{route}")).body(flow).send().await.unwrap();
+        assert_eq!(response.status(), 403, "{route}");
+        assert!(response.text().await.unwrap().contains("a (shell)"));
+    }
+
+    let base = start_server(ServerState {
+        allow_local_handlers: true,
+        ..Default::default()
+    })
+    .await;
+    let history: RunHistory = client
+        .post(format!("{base}/flows/run"))
+        .body(flow)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history.step_results["a"].output.as_deref(), Some("pwned"));
+}
+
+#[tokio::test]
+async fn test_long_outputs_are_truncated_in_returned_runs() {
+    let flow = "id: chatty\nnodes:\n  - id: a\n    kind: shell\n    config: { command: echo, args: [0123456789] }\n";
+    let base = start_server(ServerState {
+        allow_local_handlers: true,
+        max_output_bytes: 4,
+        ..Default::default()
+    })
+    .await;
+
+    let history: RunHistory = reqwest::Client::new()
+        .post(format!("{base}/flows/run"))
+        .body(flow)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let result = &history.step_results["a"];
+    assert_eq!(result.output.as_deref(), Some("0123...(truncated, 10 bytes total)"));
+    assert_eq!(result.output_bytes, Some(10));
+}
+
+#[tokio::test]
+async fn test_invalid_step_config_is_rejected_before_running() {
+    let base = start_server(ServerState::default()).await;
+    let flow = "id: bad\nnodes:\n  - id: a\n    kind: sleep\n    config: { seconds: soon }\n";
+    let client = reqwest::Client::new();
+
+    for route in ["flows/run", "flows/run/events"] {
+        let response = client.post(format!("{base}/{route}")).body(flow).send().await.unwrap();
+        assert_eq!(response.status(), 400, "{route}");
+        assert!(response.text().await.unwrap().contains("step 'a'"));
+    }
+}
+
+#[tokio::test]
+async fn test_posted_flows_only_read_allowed_env_vars() {
+    std::env::set_var("SERVER_TEST_SECRET", "hunter2");
+    let flow = "id: peek\nnodes:\n  - id: a\n    kind: assert\n    config: { left: \"{{ env.SERVER_TEST_SECRET }}\", op: eq, right: hunter2 }\n";
+    let client = reqwest::Client::new();
+
+    let base = start_server(ServerState::default()).await;
+    let history: RunHistory = client
+        .post(format!("{base}/flows/run"))
+        .body(flow)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(matches!(history.status, RunStatus::Failed(_)));
+    assert!(matches!(
+        &history.step_results["a"].status,
+        StepStatus::Failed(_, reason) if reason.contains("{{ env.SERVER_TEST_SECRET }}")
+    ));
+
+    let base = start_server(ServerState {
+        allowed_env: vec!["SERVER_TEST_SECRET".into()],
+        ..Default::default()
+    })
+    .await;
+    let history: RunHistory = client
+        .post(format!("{base}/flows/run"))
+        .body(flow)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history.status, RunStatus::Success);
+}
+
+#[tokio::test]
+async fn test_stored_run_can_be_fetched_by_id() {
+    let store = SqliteStore::in_memory().await.unwrap();
+    let base = start_server(ServerState {
+        store: Some(store),
+        ..Default::default()
+    }).await;
+    let client = reqwest::Client::new();
+
+    let posted: RunHistory = client
+        .post(format!("{base}/flows/run"))
+        .body(r#"{"id": "json-flow", "nodes": [{"id": "a", "kind": "noop"}]}"#)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    let fetched = client
+        .get(format!("{base}/flows/runs/{}", posted.run_id))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(fetched.status(), 200);
+    assert_eq!(fetched.json::<RunHistory>().await.unwrap().run_id, posted.run_id);
+
+    let missing = client.get(format!("{base}/flows/runs/nope")).send().await.unwrap();
+    assert_eq!(missing.status(), 404);
+}
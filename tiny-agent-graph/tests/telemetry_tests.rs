@@ -0,0 +1,43 @@
+#![cfg(feature = "otel")]
+
+use opentelemetry::KeyValue;
+use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+use opentelemetry_sdk::trace::TracerProvider;
+use tiny_agent_graph::engine::{run_flow, RunStatus};
+use tiny_agent_graph::flow::{build_step_graph, Flow};
+use tiny_agent_graph::telemetry;
+use tracing_subscriber::layer::SubscriberExt;
+
+#[tokio::test]
+async fn test_run_and_step_spans_are_exported() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = TracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry().with(telemetry::layer(&provider));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let flow: Flow = serde_yaml::from_str("id: traced\nnodes:\n  - id: a\n    kind: noop\n").unwrap();
+    let graph = build_step_graph(&flow).unwrap();
+    let history = run_flow(&flow, graph).await.unwrap();
+    assert_eq!(history.status, RunStatus::Success);
+
+    for result in provider.force_flush() {
+        result.unwrap();
+    }
+    let spans = exporter.get_finished_spans().unwrap();
+
+    let run = spans.iter().find(|span| span.name == "run").expect("run span exported");
+    assert!(run.attributes.contains(&KeyValue::new("run.id", history.run_id.clone())));
+
+    let step = spans.iter().find(|span| span.name == "step").expect("step span exported");
+    for expected in [
+        KeyValue::new("flow.id", "traced"),
+        KeyValue::new("run.id", history.run_id.clone()),
+        KeyValue::new("step.kind", "noop"),
+        KeyValue::new("step.status", "success"),
+    ] {
+        assert!(step.attributes.contains(&expected), "missing {expected:?} in {:?}", step.attributes);
+    }
+    assert_eq!(step.parent_span_id, run.span_context.span_id());
+}